@@ -0,0 +1,270 @@
+// Read-only scenes that borrow from the source text. `SceneRef::parse` splits the text into `TokenRef`s and
+// `ElementRef`s pointing into it, so reading a file allocates per section instead of per token, for tools that
+// only look at many files. Values are kept as written, `unquote` reads string literals and only allocates when
+// they contain escapes. `SceneRef::to_scene` makes an owned `Scene` to edit.
+
+use std::{borrow::Cow, fmt, mem, ops::Range};
+
+use crate::element::{Element, ElementData, ElementType, Property};
+use crate::scene::Scene;
+use crate::tokenizer::{Lexer, Span, Token, TokenKind, TokenSink, Tokenizer, TokenizerError};
+use crate::variant::unescape_string;
+
+// A `Token` borrowing its text from the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenRef<'a> {
+    BracketLeft,
+    BracketRight,
+    NewLine,
+    Equals,
+    ElementName(&'a str),
+    ElementDataName(&'a str),
+    ElementDataValue(&'a str),
+    PropertyName(&'a str),
+    PropertyValue(&'a str),
+    Whitespace(&'a str),
+    Comment(&'a str),
+}
+
+impl<'a> TokenRef<'a> {
+    pub fn text(&self) -> &'a str {
+        match self {
+            TokenRef::BracketLeft => "[",
+            TokenRef::BracketRight => "]",
+            TokenRef::NewLine => "\n",
+            TokenRef::Equals => "=",
+            TokenRef::ElementName(text) | TokenRef::ElementDataName(text) | TokenRef::ElementDataValue(text) | TokenRef::PropertyName(text) | TokenRef::PropertyValue(text) | TokenRef::Whitespace(text) | TokenRef::Comment(text) => text,
+        }
+    }
+
+    pub fn to_token(&self) -> Token {
+        match *self {
+            TokenRef::BracketLeft => Token::BracketLeft,
+            TokenRef::BracketRight => Token::BracketRight,
+            TokenRef::NewLine => Token::NewLine,
+            TokenRef::Equals => Token::Equals,
+            TokenRef::ElementName(text) => Token::ElementName(Some(String::from(text))),
+            TokenRef::ElementDataName(text) => Token::ElementDataName(Some(String::from(text))),
+            TokenRef::ElementDataValue(text) => Token::ElementDataValue(Some(String::from(text))),
+            TokenRef::PropertyName(text) => Token::PropertyName(Some(String::from(text))),
+            TokenRef::PropertyValue(text) => Token::PropertyValue(Some(String::from(text))),
+            TokenRef::Whitespace(text) => Token::Whitespace(String::from(text)),
+            TokenRef::Comment(text) => Token::Comment(String::from(text)),
+        }
+    }
+}
+
+impl fmt::Display for TokenRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.text())
+    }
+}
+
+// The text of a string, `StringName` (`&"..."`) or `NodePath` (`^"..."`, `NodePath("...")`) literal, borrowed
+// from `value` unless escapes have to be resolved. `None` for other values.
+pub fn unquote(value:&str) -> Option<Cow<'_, str>> {
+    let value = value.trim();
+    let value = value.strip_prefix(['&', '^']).unwrap_or(value);
+    let value = match value.strip_prefix("NodePath(") {
+        Some(arguments) => arguments.strip_suffix(')')?.trim(),
+        None => value,
+    };
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut escaped = false;
+    let mut has_escapes = false;
+    for c in inner.chars() {
+        if escaped {
+            escaped = false;
+        }
+        else if c == '\\' {
+            escaped = true;
+            has_escapes = true;
+        }
+        else if c == '"' {
+            // Two literals, e.g. `"a" + "b"`, aren't one string.
+            return None;
+        }
+    }
+    if escaped {
+        return None;
+    }
+    if has_escapes {
+        return Some(Cow::Owned(unescape_string(inner)));
+    }
+    Some(Cow::Borrowed(inner))
+}
+
+// An `Element` borrowing its names and values from the source.
+#[derive(Debug, Clone)]
+pub struct ElementRef<'a> {
+    pub element_name:&'a str,
+    pub element_type:ElementType,
+    pub element_data:Vec<(&'a str, &'a str)>, // Name and value of each `name=value` pair in the header.
+    pub properties:Vec<(&'a str, &'a str)>,
+    pub tokens:Range<usize>, // Indices into `SceneRef::tokens`.
+}
+
+impl<'a> ElementRef<'a> {
+    fn starting_at(index:usize) -> Self {
+        ElementRef { element_name: "", element_type: ElementType::UNKOWN, element_data: Vec::new(), properties: Vec::new(), tokens: index..index }
+    }
+
+    pub fn get_data_value(&self, data_name:&str) -> Option<&'a str> {
+        self.element_data.iter().find(|(name, _)| *name == data_name).map(|(_, value)| *value)
+    }
+
+    pub fn get_property_value(&self, property_name:&str) -> Option<&'a str> {
+        self.properties.iter().find(|(name, _)| *name == property_name).map(|(_, value)| *value)
+    }
+
+    // Data holding a string, e.g. `name` or `parent` of a node, see `unquote`.
+    pub fn get_data_str(&self, data_name:&str) -> Option<Cow<'a, str>> {
+        unquote(self.get_data_value(data_name)?)
+    }
+
+    pub fn get_property_str(&self, property_name:&str) -> Option<Cow<'a, str>> {
+        unquote(self.get_property_value(property_name)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SceneRef<'a> {
+    source:&'a str,
+    file_name:Option<String>,
+    tokens:Vec<TokenRef<'a>>,
+    elements:Vec<ElementRef<'a>>,
+    lexer:Lexer, // As it was after the last line, for the tokenizer of `to_scene`.
+}
+
+impl<'a> SceneRef<'a> {
+    pub fn parse(source:&'a str) -> Result<Self, TokenizerError> {
+        SceneRef::parse_named(source, None)
+    }
+
+    // Like `parse`, with `file_name` reported in error locations.
+    pub fn parse_named(source:&'a str, file_name:Option<&str>) -> Result<Self, TokenizerError> {
+        let mut lexer = Lexer::new();
+        let mut tokens = TokenRefs { source, tokens: Vec::new(), whitespace_start: 0 };
+        let mut line_offset = 0;
+        for line in source.split_inclusive('\n') {
+            lexer.read_line(&mut tokens, line, line_offset, file_name).map_err(|(error, _)| error)?;
+            line_offset += line.len();
+        }
+        if let Some(error) = lexer.unfinished(file_name) {
+            return Err(error);
+        }
+        let elements = elements_from_tokens(&tokens.tokens);
+        Ok(SceneRef { source, file_name: file_name.map(String::from), tokens: tokens.tokens, elements, lexer })
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    pub fn tokens(&self) -> &[TokenRef<'a>] {
+        &self.tokens
+    }
+
+    pub fn elements(&self) -> &[ElementRef<'a>] {
+        &self.elements
+    }
+
+    pub fn element_tokens(&self, element:&ElementRef<'a>) -> &[TokenRef<'a>] {
+        &self.tokens[element.tokens.clone()]
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &ElementRef<'a>> {
+        self.elements.iter().filter(|element| element.element_type == ElementType::NODE)
+    }
+
+    // The scene `Scene::from_str` reads from the same text. As there, the tokens and spans are kept by the elements
+    // and `tokenizer` has none of its own.
+    pub fn to_scene(&self) -> Scene {
+        let tokens = self.tokens.iter().map(TokenRef::to_token).collect::<Vec<Token>>();
+        let spans = Span::of_tokens(&tokens);
+        let elements = self.elements.iter().map(|element| Element {
+            element_name: String::from(element.element_name),
+            element_type: element.element_type.clone(),
+            element_data: element.element_data.iter().map(|(name, value)| ElementData(String::from(*name), String::from(*value))).collect(),
+            properties: element.properties.iter().map(|(name, value)| Property(String::from(*name), String::from(*value))).collect(),
+            tokens: tokens[element.tokens.clone()].to_vec(),
+            spans: spans[element.tokens.clone()].to_vec(),
+        }).collect();
+        Scene { elements, tokenizer: Tokenizer::after(self.file_name.as_deref(), self.lexer.clone(), self.source.len()) }
+    }
+}
+
+// Collects the ranges of `Lexer` as `TokenRef`s into `source`.
+struct TokenRefs<'a> {
+    source:&'a str,
+    tokens:Vec<TokenRef<'a>>,
+    whitespace_start:usize, // Start of the last token when it is whitespace, which later pieces extend.
+}
+
+impl<'a> TokenSink for TokenRefs<'a> {
+    fn push_token(&mut self, kind:TokenKind, range:Range<usize>) {
+        let text = &self.source[range.clone()];
+        let token = match kind {
+            TokenKind::BracketLeft => TokenRef::BracketLeft,
+            TokenKind::BracketRight => TokenRef::BracketRight,
+            TokenKind::NewLine => TokenRef::NewLine,
+            TokenKind::Equals => TokenRef::Equals,
+            TokenKind::ElementName => TokenRef::ElementName(text),
+            TokenKind::ElementDataName => TokenRef::ElementDataName(text),
+            TokenKind::ElementDataValue => TokenRef::ElementDataValue(text),
+            TokenKind::PropertyName => TokenRef::PropertyName(text),
+            TokenKind::PropertyValue => TokenRef::PropertyValue(text),
+            TokenKind::Whitespace => {
+                if let Some(TokenRef::Whitespace(whitespace)) = self.tokens.last_mut() {
+                    *whitespace = &self.source[self.whitespace_start..range.end];
+                    return;
+                }
+                self.whitespace_start = range.start;
+                TokenRef::Whitespace(text)
+            },
+            TokenKind::Comment => TokenRef::Comment(text),
+        };
+        self.tokens.push(token);
+    }
+}
+
+// Splits tokens into elements like `Tokenizer::elements_from_tokens`: a `[` starts the next element, comments
+// before the first one belong to it and properties there are an element of their own.
+fn elements_from_tokens<'a>(tokens:&[TokenRef<'a>]) -> Vec<ElementRef<'a>> {
+    let mut elements = Vec::new();
+    let mut current = ElementRef::starting_at(0);
+    let mut element_started = false;
+    for (index, token) in tokens.iter().enumerate() {
+        match *token {
+            TokenRef::BracketLeft => {
+                if element_started || !current.properties.is_empty() {
+                    current.tokens.end = index;
+                    elements.push(mem::replace(&mut current, ElementRef::starting_at(index)));
+                }
+                element_started = true;
+            },
+            TokenRef::ElementName(name) => {
+                current.element_type = ElementType::from_element_name(name);
+                current.element_name = name;
+            },
+            TokenRef::ElementDataName(name) => current.element_data.push((name, "")),
+            TokenRef::ElementDataValue(value) => {
+                if let Some(data) = current.element_data.last_mut() {
+                    data.1 = value;
+                }
+            },
+            TokenRef::PropertyName(name) => current.properties.push((name, "")),
+            TokenRef::PropertyValue(value) => {
+                if let Some(property) = current.properties.last_mut() {
+                    property.1 = value;
+                }
+            },
+            _ => {},
+        }
+    }
+    if element_started || !tokens.is_empty() {
+        current.tokens.end = tokens.len();
+        elements.push(current);
+    }
+    elements
+}
@@ -0,0 +1,308 @@
+// The `tscn` command: query and edit scenes from scripts. Every command takes `-` to read stdin, edits of stdin
+// are written to stdout and edits of a file are written back to it.
+// Exit status is 0 on success, 1 when there are findings (problems, differences, conflicts) and 2 on errors.
+
+use std::{fs, io::{Read, Write}, path::Path};
+
+use crate::{convert, project::{Dependency, Document, Project}, resource::Resource, resource_table::{ResourceKind, ResourceTable}, scene::{self, NodePath, Scene}, variant::Variant};
+
+pub const USAGE:&str = "\
+usage: tscn <command> [options] <file>...
+
+commands:
+  tree <file>                             print the node hierarchy
+  get <file> <node> <property>            print a property of the node at a path like `Player/Sprite2D`
+  set <file> <node> <property> <value>    set a property to a value in Godot's text format
+  validate <file>...                      report syntax errors, missing resources and broken connections
+  fmt [--check] <file>...                 rewrite files the way Godot saves them, `--check` only lists them
+  diff [--json] <old> <new>               print what changed between two versions of a scene
+  deps <file | project directory>         list the files a scene or resource uses, or those of a whole project
+  merge <base> <ours> <theirs>            three-way merge into <ours>, usable as a git merge driver
+
+`-` reads from stdin; edits of stdin are written to stdout, edits of a file are saved in place.
+";
+
+const FINDINGS:u8 = 1;
+const FAILURE:u8 = 2;
+
+struct Io<'a> {
+    stdin:&'a mut dyn Read,
+    stdout:&'a mut dyn Write,
+    stderr:&'a mut dyn Write,
+}
+
+impl Io<'_> {
+    fn read(&mut self, path:&str) -> Result<String, String> {
+        if path == "-" {
+            let mut text = String::new();
+            self.stdin.read_to_string(&mut text).map_err(|error| format!("error: failed to read stdin: {}", error))?;
+            Ok(text)
+        }
+        else {
+            fs::read_to_string(path).map_err(|error| format!("error: failed to read {}: {}", path, error))
+        }
+    }
+
+    fn write(&mut self, path:&str, text:&str) -> Result<(), String> {
+        if path == "-" {
+            self.stdout.write_all(text.as_bytes()).map_err(|error| format!("error: failed to write stdout: {}", error))
+        }
+        else {
+            fs::write(path, text).map_err(|error| format!("error: failed to write {}: {}", path, error))
+        }
+    }
+
+    fn print(&mut self, text:&str) -> Result<(), String> {
+        writeln!(self.stdout, "{}", text).map_err(|error| format!("error: failed to write stdout: {}", error))
+    }
+
+    fn report(&mut self, text:&str) {
+        let _ = writeln!(self.stderr, "{}", text);
+    }
+}
+
+fn display_name(path:&str) -> &str {
+    if path == "-" { "<stdin>" } else { path }
+}
+
+fn is_resource(path:&str) -> bool {
+    path.ends_with(".tres")
+}
+
+fn parse_scene(path:&str, text:&str) -> Result<Scene, String> {
+    Scene::from_str_named(text, Some(display_name(path))).map_err(|error| error.render(text))
+}
+
+fn parse_resource(path:&str, text:&str) -> Result<Resource, String> {
+    Resource::from_str_named(text, Some(display_name(path))).map_err(|error| error.render(text))
+}
+
+fn parse_document(path:&str, text:&str) -> Result<Document, String> {
+    if is_resource(path) {
+        Ok(Document::Resource(parse_resource(path, text)?.into()))
+    }
+    else {
+        Ok(Document::Scene(parse_scene(path, text)?.into()))
+    }
+}
+
+// `--flag` options apart from the other arguments, `Err` for flags the command doesn't take.
+fn split_flags<'a>(args:&'a [String], allowed:&[&str]) -> Result<(Vec<&'a str>, Vec<&'a str>), String> {
+    let (flags, positional):(Vec<&str>, Vec<&str>) = args.iter().map(|arg| arg.as_str()).partition(|arg| arg.starts_with("--"));
+    match flags.iter().find(|flag| !allowed.contains(flag)) {
+        Some(flag) => Err(format!("error: unknown option {}\n\n{}", flag, USAGE)),
+        None => Ok((flags, positional)),
+    }
+}
+
+fn tree(io:&mut Io, path:&str) -> Result<u8, String> {
+    let text = io.read(path)?;
+    let scene = parse_scene(path, &text)?;
+    let tree = scene.tree();
+    for node in tree.depth_first() {
+        let kind = match (node.node_type(), node.instance_path(), node.placeholder_path()) {
+            (Some(node_type), _, _) => format!(" ({})", node_type),
+            (None, Some(instance), _) => format!(" (instance of {:?})", instance),
+            (None, None, Some(placeholder)) => format!(" (placeholder for {:?})", placeholder),
+            (None, None, None) => String::new(),
+        };
+        io.print(&format!("{}{}{}", "  ".repeat(node.depth()), node.name(), kind))?;
+    }
+    Ok(0)
+}
+
+fn get(io:&mut Io, path:&str, node:&str, property:&str) -> Result<u8, String> {
+    let text = io.read(path)?;
+    let scene = parse_scene(path, &text)?;
+    match scene.get_node_property(NodePath::from(node), property) {
+        Ok(value) => {
+            io.print(value.trim())?;
+            Ok(0)
+        },
+        Err(error) => {
+            io.report(&format!("{}: {:?} {}: {}", display_name(path), node, property, error));
+            Ok(FINDINGS)
+        },
+    }
+}
+
+fn set(io:&mut Io, path:&str, node:&str, property:&str, value:&str) -> Result<u8, String> {
+    Variant::parse(value).map_err(|error| format!("error: {:?} is not a valid value: {}", value, error))?;
+    let text = io.read(path)?;
+    let mut scene = parse_scene(path, &text)?;
+    scene.set_node_property(NodePath::from(node), property, value).map_err(|error| format!("error: {}: {:?}: {}", display_name(path), node, error))?;
+    io.write(path, &scene.to_tscn())?;
+    Ok(0)
+}
+
+// Problems Godot would report when loading the file.
+fn problems(document:&Document) -> Vec<String> {
+    let elements = document.elements();
+    let mut problems = Vec::new();
+    let resources = ResourceTable::new(elements);
+    for reference in resources.dangling() {
+        let value = match reference.kind {
+            ResourceKind::Ext => Variant::ExtResource(reference.id.clone()),
+            ResourceKind::Sub => Variant::SubResource(reference.id.clone()),
+        };
+        problems.push(format!("{} {} refers to missing {}", convert::describe(&elements[reference.element]), reference.name, value.to_text(scene::file_format(elements))));
+    }
+    if let Document::Scene(scene) = document {
+        let tree = scene.tree();
+        for connection in scene.connections() {
+            for path in [&connection.from, &connection.to] {
+                if tree.get_node(path).is_none() {
+                    problems.push(format!("connection {:?} of {:?} refers to missing node {:?}", connection.signal, connection.from, path));
+                }
+            }
+        }
+    }
+    problems
+}
+
+fn validate(io:&mut Io, paths:&[&str]) -> Result<u8, String> {
+    let mut code = 0;
+    for &path in paths {
+        let text = io.read(path)?;
+        let document = if is_resource(path) {
+            parse_resource(path, &text).map(|resource| Document::Resource(resource.into()))
+        }
+        else {
+            // Every syntax error is reported, the sections that could be read are checked further.
+            let (scene, errors) = Scene::from_str_named_recovering(&text, Some(display_name(path)));
+            for error in errors.iter() {
+                io.report(&error.render(&text));
+                code = FINDINGS;
+            }
+            Ok(Document::Scene(scene.into()))
+        };
+        match document {
+            Ok(document) => {
+                for problem in problems(&document) {
+                    io.report(&format!("{}: {}", display_name(path), problem));
+                    code = FINDINGS;
+                }
+            },
+            Err(error) => {
+                io.report(&error);
+                code = FINDINGS;
+            },
+        }
+    }
+    Ok(code)
+}
+
+fn fmt(io:&mut Io, paths:&[&str], check:bool) -> Result<u8, String> {
+    let mut code = 0;
+    for &path in paths {
+        let text = io.read(path)?;
+        let formatted = match parse_document(path, &text)? {
+            Document::Scene(scene) => scene.to_canonical_tscn(),
+            Document::Resource(resource) => resource.to_canonical_tres(),
+        };
+        if check {
+            if formatted != text {
+                io.print(display_name(path))?;
+                code = FINDINGS;
+            }
+        }
+        else if path == "-" || formatted != text {
+            io.write(path, &formatted)?;
+        }
+    }
+    Ok(code)
+}
+
+fn diff(io:&mut Io, old_path:&str, new_path:&str, json:bool) -> Result<u8, String> {
+    let old_text = io.read(old_path)?;
+    let old = parse_scene(old_path, &old_text)?;
+    let new_text = io.read(new_path)?;
+    let new = parse_scene(new_path, &new_text)?;
+    let diff = old.diff(&new);
+    if json {
+        io.write("-", &diff.to_json())?;
+    }
+    else {
+        io.write("-", &diff.to_string())?;
+    }
+    Ok(if diff.is_empty() { 0 } else { FINDINGS })
+}
+
+fn deps(io:&mut Io, path:&str) -> Result<u8, String> {
+    let describe = |dependency:&Dependency| match &dependency.resource_type {
+        Some(resource_type) => format!("{} ({})", dependency.path, resource_type),
+        None => dependency.path.clone(),
+    };
+    if path == "-" || !Path::new(path).is_dir() {
+        let text = io.read(path)?;
+        for dependency in parse_document(path, &text)?.dependencies() {
+            io.print(&describe(&dependency))?;
+        }
+        return Ok(0);
+    }
+    let mut project = Project::open(path).map_err(|error| format!("error: {}", error))?;
+    let graph = project.dependency_graph().map_err(|error| format!("error: {}", error))?;
+    for file in graph.files() {
+        io.print(file)?;
+        for dependency in graph.dependencies(file) {
+            io.print(&format!("  {}", describe(dependency)))?;
+        }
+    }
+    let mut code = 0;
+    for missing in graph.missing() {
+        io.report(&format!("missing: {}", missing));
+        code = FINDINGS;
+    }
+    for (file, error) in graph.failed() {
+        io.report(&format!("{}: {}", file, error));
+        code = FINDINGS;
+    }
+    Ok(code)
+}
+
+// As a git merge driver: `git config merge.tscn.driver "tscn merge %O %A %B"` with `*.tscn merge=tscn` in
+// `.gitattributes`. Conflicts are listed on stderr and keep ours, git then marks the file as conflicted.
+fn merge(io:&mut Io, base_path:&str, ours_path:&str, theirs_path:&str) -> Result<u8, String> {
+    let base_text = io.read(base_path)?;
+    let base = parse_scene(base_path, &base_text)?;
+    let ours_text = io.read(ours_path)?;
+    let ours = parse_scene(ours_path, &ours_text)?;
+    let theirs_text = io.read(theirs_path)?;
+    let theirs = parse_scene(theirs_path, &theirs_text)?;
+    let result = Scene::merge3(&base, &ours, &theirs);
+    for conflict in result.conflicts.iter() {
+        io.report(&format!("{}: conflict: {}", display_name(ours_path), conflict));
+    }
+    io.write(ours_path, &result.scene.to_tscn())?;
+    Ok(if result.is_clean() { 0 } else { FINDINGS })
+}
+
+// Runs the command in `args` (without the program name), returning the exit status.
+pub fn run(args:&[String], stdin:&mut dyn Read, stdout:&mut dyn Write, stderr:&mut dyn Write) -> u8 {
+    let mut io = Io { stdin, stdout, stderr };
+    let result = match args.split_first() {
+        Some((command, rest)) => match split_flags(rest, &["--check", "--json"]) {
+            Ok((flags, positional)) => match (command.as_str(), &positional[..]) {
+                ("tree", [path]) => tree(&mut io, path),
+                ("get", [path, node, property]) => get(&mut io, path, node, property),
+                ("set", [path, node, property, value]) => set(&mut io, path, node, property, value),
+                ("validate", paths) if !paths.is_empty() => validate(&mut io, paths),
+                ("fmt", paths) if !paths.is_empty() => fmt(&mut io, paths, flags.contains(&"--check")),
+                ("diff", [old, new]) => diff(&mut io, old, new, flags.contains(&"--json")),
+                ("deps", [path]) => deps(&mut io, path),
+                ("merge", [base, ours, theirs]) => merge(&mut io, base, ours, theirs),
+                ("help" | "--help", _) => io.print(USAGE.trim_end()).map(|_| 0),
+                _ => Err(String::from(USAGE.trim_end())),
+            },
+            Err(error) => Err(error),
+        },
+        None => Err(String::from(USAGE.trim_end())),
+    };
+    match result {
+        Ok(code) => code,
+        Err(error) => {
+            io.report(&error);
+            FAILURE
+        },
+    }
+}
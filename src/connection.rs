@@ -0,0 +1,73 @@
+use crate::{element::{Element, ElementData, ElementType}, tree::data_string, variant::{Format, Variant}};
+
+// A `[connection]` section: `signal` of the node at `from` calls `method` on the node at `to`.
+// Node paths are relative to the scene root, in the form used by `parent=`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Connection {
+    pub signal:String,
+    pub from:String,
+    pub to:String,
+    pub method:String,
+    pub flags:Option<i64>, // `CONNECT_*` flags, e.g. 3 for deferred and persistent.
+    pub unbinds:Option<i64>, // Godot 4 only, trailing signal arguments dropped before calling `method`.
+    pub binds:Vec<Variant>, // Extra arguments passed after the signal's own.
+}
+
+impl Connection {
+    pub fn new(signal:&str, from:&str, to:&str, method:&str) -> Self {
+        Connection { signal: String::from(signal), from: String::from(from), to: String::from(to), method: String::from(method), flags: None, unbinds: None, binds: Vec::new() }
+    }
+
+    pub fn from_element(element:&Element) -> Option<Self> {
+        if element.element_name != "connection" {
+            return None;
+        }
+        let int = |name:&str| match element.get_data_variant(name) {
+            Ok(Variant::Int(int)) => Some(int),
+            _ => None,
+        };
+        let binds = match element.get_data_variant("binds") {
+            Ok(Variant::Array(binds)) => binds,
+            _ => Vec::new(),
+        };
+        Some(Connection {
+            signal: data_string(element, "signal")?,
+            from: data_string(element, "from")?,
+            to: data_string(element, "to")?,
+            method: data_string(element, "method")?,
+            flags: int("flags"),
+            unbinds: int("unbinds"),
+            binds,
+        })
+    }
+
+    pub fn to_element(&self, format:Format) -> Element {
+        let mut element = Element::empty();
+        element.element_name = String::from("connection");
+        element.element_type = ElementType::CONNECTION;
+        let quoted = |string:&String| Variant::String(string.clone()).to_text(format);
+        let mut data = vec![
+            ElementData(String::from("signal"), quoted(&self.signal)),
+            ElementData(String::from("from"), quoted(&self.from)),
+            ElementData(String::from("to"), quoted(&self.to)),
+            ElementData(String::from("method"), quoted(&self.method)),
+        ];
+        let flags = self.flags.map(|flags| ElementData(String::from("flags"), flags.to_string()));
+        let unbinds = self.unbinds.filter(|_| format == Format::Godot4).map(|unbinds| ElementData(String::from("unbinds"), unbinds.to_string()));
+        let binds = Some(&self.binds).filter(|binds| !binds.is_empty()).map(|binds| ElementData(String::from("binds"), Variant::Array(binds.clone()).to_text(format)));
+        // Same order as Godot's saver.
+        match format {
+            Format::Godot3 => data.extend([binds, flags].into_iter().flatten()),
+            Format::Godot4 => data.extend([flags, unbinds, binds].into_iter().flatten()),
+        }
+        element.element_data = data;
+        element.force_update_tokens();
+        element
+    }
+
+    // Godot allows one connection per signal, source, target and method.
+    pub fn is_same(&self, other:&Connection) -> bool {
+        self.signal == other.signal && self.from == other.from && self.to == other.to && self.method == other.method
+    }
+}
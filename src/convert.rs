@@ -0,0 +1,421 @@
+use std::{collections::{HashMap, HashSet}, f64::consts::PI, fmt};
+
+use crate::{element::Element, resource_table::ResourceKind, scene, tree::{self, data_string}, variant::{Format, ResourceId, Variant, VariantError}};
+
+// A Godot 3 property that Godot 4 calls something else.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyRename {
+    pub class:Option<String>, // Godot 3 class the rename applies to, `None` for every class.
+    pub from:String,
+    pub to:String,
+    pub prefix:bool, // Renames every property starting with `from`, e.g. `custom_colors/` to `theme_override_colors/`.
+    pub scale:Option<f64>, // Multiplies numbers and vectors, e.g. `extents` became `size`, twice as large.
+}
+
+// Class and property names that changed between Godot 3 and Godot 4. Entries added later take precedence.
+#[derive(Debug, Clone, Default)]
+pub struct RenameTable {
+    classes:HashMap<String, String>,
+    properties:Vec<PropertyRename>,
+    removed:HashSet<String>, // Classes without a Godot 4 counterpart.
+    review:Vec<(Option<String>, String)>, // Properties that still load but whose values mean something else.
+}
+
+impl RenameTable {
+    // An empty table, only values and resource ids are converted.
+    pub fn new() -> Self {
+        RenameTable::default()
+    }
+
+    // The common renames between Godot 3 and Godot 4: 3D nodes gaining a `3D` suffix, `rect_` and `margin_` properties of controls, and so on.
+    pub fn godot3_to_godot4() -> Self {
+        let mut table = RenameTable::new();
+        let classes = [
+            ("ARVRAnchor", "XRAnchor3D"), ("ARVRCamera", "XRCamera3D"), ("ARVRController", "XRController3D"), ("ARVROrigin", "XROrigin3D"),
+            ("AnimatedSprite", "AnimatedSprite2D"), ("Area", "Area3D"), ("AudioStreamOGGVorbis", "AudioStreamOggVorbis"), ("AudioStreamSample", "AudioStreamWAV"),
+            ("BakedLightmap", "LightmapGI"), ("BitmapFont", "FontFile"), ("BoneAttachment", "BoneAttachment3D"), ("BoxShape", "BoxShape3D"),
+            ("CPUParticles", "CPUParticles3D"), ("CSGBox", "CSGBox3D"), ("CSGCombiner", "CSGCombiner3D"), ("CSGCylinder", "CSGCylinder3D"),
+            ("CSGMesh", "CSGMesh3D"), ("CSGPolygon", "CSGPolygon3D"), ("CSGSphere", "CSGSphere3D"), ("CSGTorus", "CSGTorus3D"),
+            ("Camera", "Camera3D"), ("CapsuleShape", "CapsuleShape3D"), ("CollisionPolygon", "CollisionPolygon3D"), ("CollisionShape", "CollisionShape3D"),
+            ("ConcavePolygonShape", "ConcavePolygonShape3D"), ("ConeTwistJoint", "ConeTwistJoint3D"), ("ConvexPolygonShape", "ConvexPolygonShape3D"), ("CubeMesh", "BoxMesh"),
+            ("CylinderShape", "CylinderShape3D"), ("DirectionalLight", "DirectionalLight3D"), ("DynamicFont", "FontFile"), ("DynamicFontData", "FontFile"),
+            ("GIProbe", "VoxelGI"), ("Generic6DOFJoint", "Generic6DOFJoint3D"), ("GradientTexture", "GradientTexture1D"), ("HeightMapShape", "HeightMapShape3D"),
+            ("HingeJoint", "HingeJoint3D"), ("Joint", "Joint3D"), ("KinematicBody", "CharacterBody3D"), ("KinematicBody2D", "CharacterBody2D"),
+            ("Light2D", "PointLight2D"), ("LineShape2D", "WorldBoundaryShape2D"), ("Listener", "AudioListener3D"), ("Listener2D", "AudioListener2D"),
+            ("MeshInstance", "MeshInstance3D"), ("MultiMeshInstance", "MultiMeshInstance3D"), ("NavigationAgent", "NavigationAgent3D"), ("NavigationMeshInstance", "NavigationRegion3D"),
+            ("NavigationObstacle", "NavigationObstacle3D"), ("NavigationPolygonInstance", "NavigationRegion2D"), ("NoiseTexture", "NoiseTexture2D"), ("OmniLight", "OmniLight3D"),
+            ("OpenSimplexNoise", "FastNoiseLite"), ("Particles", "GPUParticles3D"), ("Particles2D", "GPUParticles2D"), ("ParticlesMaterial", "ParticleProcessMaterial"),
+            ("Path", "Path3D"), ("PathFollow", "PathFollow3D"), ("PhysicalBone", "PhysicalBone3D"), ("PinJoint", "PinJoint3D"),
+            ("PlaneShape", "WorldBoundaryShape3D"), ("PopupDialog", "Popup"), ("Position2D", "Marker2D"), ("Position3D", "Marker3D"),
+            ("RayCast", "RayCast3D"), ("RayShape", "SeparationRayShape3D"), ("RayShape2D", "SeparationRayShape2D"), ("RemoteTransform", "RemoteTransform3D"),
+            ("RigidBody", "RigidBody3D"), ("Skeleton", "Skeleton3D"), ("SliderJoint", "SliderJoint3D"), ("SoftBody", "SoftBody3D"),
+            ("Spatial", "Node3D"), ("SpatialMaterial", "StandardMaterial3D"), ("SphereShape", "SphereShape3D"), ("SpotLight", "SpotLight3D"),
+            ("SpringArm", "SpringArm3D"), ("Sprite", "Sprite2D"), ("StaticBody", "StaticBody3D"), ("StreamTexture", "CompressedTexture2D"),
+            ("Texture", "Texture2D"), ("TextureArray", "Texture2DArray"), ("ToolButton", "Button"), ("VehicleBody", "VehicleBody3D"),
+            ("VehicleWheel", "VehicleWheel3D"), ("ViewportContainer", "SubViewportContainer"), ("VisibilityEnabler", "VisibleOnScreenEnabler3D"), ("VisibilityEnabler2D", "VisibleOnScreenEnabler2D"),
+            ("VisibilityNotifier", "VisibleOnScreenNotifier3D"), ("VisibilityNotifier2D", "VisibleOnScreenNotifier2D"), ("WindowDialog", "Window"), ("World", "World3D"),
+        ];
+        for (from, to) in classes {
+            table.rename_class(from, to);
+        }
+        for name in ["AnimationTreePlayer", "ClippedCamera", "ImmediateGeometry", "InterpolatedCamera", "LargeTexture", "Navigation", "Navigation2D", "PanoramaSky", "ProceduralSky", "ProxyTexture", "Tween", "YSort"] {
+            table.remove_class(name);
+        }
+        let properties = [
+            ("rect_position", "position"), ("rect_size", "size"), ("rect_min_size", "custom_minimum_size"), ("rect_scale", "scale"),
+            ("rect_pivot_offset", "pivot_offset"), ("rect_clip_content", "clip_contents"), ("margin_left", "offset_left"), ("margin_top", "offset_top"),
+            ("margin_right", "offset_right"), ("margin_bottom", "offset_bottom"), ("focus_neighbour_left", "focus_neighbor_left"), ("focus_neighbour_top", "focus_neighbor_top"),
+            ("focus_neighbour_right", "focus_neighbor_right"), ("focus_neighbour_bottom", "focus_neighbor_bottom"), ("translation", "position"),
+        ];
+        for (from, to) in properties {
+            table.rename_property(None, from, to);
+        }
+        for kind in ["colors", "constants", "fonts", "icons", "styles"] {
+            table.rename_property_prefix(&format!("custom_{}/", kind), &format!("theme_override_{}/", kind));
+        }
+        table.rename_scaled_property(None, "rect_rotation", "rotation", PI / 180.0);
+        table.rename_scaled_property(Some("RectangleShape2D"), "extents", "size", 2.0);
+        table.rename_scaled_property(Some("BoxShape"), "extents", "size", 2.0);
+        table.rename_property(Some("Label"), "align", "horizontal_alignment");
+        table.rename_property(Some("Label"), "valign", "vertical_alignment");
+        table.rename_property(Some("Label"), "percent_visible", "visible_ratio");
+        table.rename_property(Some("Button"), "align", "alignment");
+        table.rename_property(Some("LineEdit"), "align", "alignment");
+        table.review_property(None, "pause_mode");
+        table.review_property(Some("Camera2D"), "current");
+        table.review_property(Some("TextureRect"), "expand");
+        table.review_property(Some("TextureRect"), "stretch_mode");
+        table
+    }
+
+    pub fn rename_class(&mut self, from:&str, to:&str) {
+        self.classes.insert(String::from(from), String::from(to));
+    }
+
+    // Reports nodes and resources of this class instead of converting them.
+    pub fn remove_class(&mut self, name:&str) {
+        self.removed.insert(String::from(name));
+    }
+
+    pub fn rename_property(&mut self, class:Option<&str>, from:&str, to:&str) {
+        self.properties.push(PropertyRename { class: class.map(String::from), from: String::from(from), to: String::from(to), prefix: false, scale: None });
+    }
+
+    pub fn rename_property_prefix(&mut self, from:&str, to:&str) {
+        self.properties.push(PropertyRename { class: None, from: String::from(from), to: String::from(to), prefix: true, scale: None });
+    }
+
+    pub fn rename_scaled_property(&mut self, class:Option<&str>, from:&str, to:&str, scale:f64) {
+        self.properties.push(PropertyRename { class: class.map(String::from), from: String::from(from), to: String::from(to), prefix: false, scale: Some(scale) });
+    }
+
+    // Reports the property wherever it's set, as its values need converting by hand.
+    pub fn review_property(&mut self, class:Option<&str>, name:&str) {
+        self.review.push((class.map(String::from), String::from(name)));
+    }
+
+    // The Godot 4 name of a Godot 3 class, `None` if it kept its name.
+    pub fn class(&self, name:&str) -> Option<&str> {
+        self.classes.get(name).map(|name| name.as_str())
+    }
+
+    pub fn is_removed(&self, class:&str) -> bool {
+        !self.classes.contains_key(class) && self.removed.contains(class)
+    }
+
+    // The rename that applies to `name` on an object of the Godot 3 class `class`.
+    pub fn property(&self, class:Option<&str>, name:&str) -> Option<&PropertyRename> {
+        self.properties.iter().rev().find(|rename| {
+            let class_matches = rename.class.is_none() || rename.class.as_deref() == class;
+            let name_matches = if rename.prefix { name.starts_with(&rename.from) } else { name == rename.from };
+            class_matches && name_matches
+        })
+    }
+
+    pub fn needs_review(&self, class:Option<&str>, name:&str) -> bool {
+        self.review.iter().any(|(review_class, review_name)| review_name == name && (review_class.is_none() || review_class.as_deref() == class))
+    }
+}
+
+// Something the conversion left as it was. `section` describes where, e.g. `node "Player/Sprite"`, with ids as the Godot 3 file wrote them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Untranslated {
+    Value { section:String, name:String, error:VariantError }, // A value that couldn't be read, kept as written.
+    RemovedClass { section:String, class:String },
+    Property { section:String, name:String }, // Kept or renamed, but needs checking by hand.
+}
+
+impl fmt::Display for Untranslated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Untranslated::Value { section, name, error } => write!(f, "{}: couldn't convert {}: {}", section, name, error),
+            Untranslated::RemovedClass { section, class } => write!(f, "{}: {} has no Godot 4 counterpart", section, class),
+            Untranslated::Property { section, name } => write!(f, "{}: {} needs converting by hand", section, name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConversionReport {
+    pub ids:Vec<(ResourceKind, ResourceId, ResourceId)>, // Old and new id of every ext and sub resource.
+    pub untranslated:Vec<Untranslated>,
+}
+
+impl ConversionReport {
+    pub fn is_complete(&self) -> bool {
+        self.untranslated.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConversionError {
+    NotGodot3(Format), // 0: Format the file is already in
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::NotGodot3(format) => write!(f, "not a Godot 3 file, the file is in the {:?} format", format),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+const ID_CHARS:&[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+// Five characters like the random part of Godot 4 ids, derived from `seed` so conversions are repeatable.
+pub(crate) fn unique_id(seed:&str) -> String {
+    // FNV-1a
+    let mut hash:u64 = 0xcbf29ce484222325;
+    for byte in seed.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (0..5).map(|_| {
+        let c = ID_CHARS[(hash % ID_CHARS.len() as u64) as usize] as char;
+        hash /= ID_CHARS.len() as u64;
+        c
+    }).collect::<String>()
+}
+
+fn resource_kind(element:&Element) -> Option<ResourceKind> {
+    match &element.element_name[..] {
+        "ext_resource" => Some(ResourceKind::Ext),
+        "sub_resource" => Some(ResourceKind::Sub),
+        _ => None,
+    }
+}
+
+// Godot 4 style ids: `1_abcde` for ext resources and `Type_abcde` for sub resources.
+fn new_ids(elements:&[Element], table:&RenameTable) -> HashMap<(ResourceKind, ResourceId), ResourceId> {
+    let mut ids = HashMap::new();
+    let mut taken = HashSet::new();
+    for element in elements.iter() {
+        let (Some(kind), Ok(id)) = (resource_kind(element), element.get_data_value("id")) else {
+            continue;
+        };
+        let old_id = ResourceId::from_data_value(&id);
+        let prefix = match (kind, &old_id) {
+            (ResourceKind::Ext, ResourceId::Int(int)) => int.to_string(),
+            (ResourceKind::Ext, ResourceId::String(string)) => string.clone(),
+            (ResourceKind::Sub, _) => {
+                let class = data_string(element, "type").unwrap_or_else(|| String::from("Resource"));
+                table.class(&class).map(String::from).unwrap_or(class)
+            },
+        };
+        let seed = format!("{}:{}", data_string(element, "path").unwrap_or_default(), id);
+        let mut attempt = 0;
+        let new_id = loop {
+            let candidate = format!("{}_{}", prefix, unique_id(&format!("{}#{}", seed, attempt)));
+            if taken.insert(candidate.clone()) {
+                break candidate;
+            }
+            attempt += 1;
+        };
+        ids.insert((kind, old_id), ResourceId::String(new_id));
+    }
+    ids
+}
+
+pub(crate) fn describe(element:&Element) -> String {
+    match &element.element_name[..] {
+        "node" => {
+            let name = data_string(element, "name").unwrap_or_default();
+            match data_string(element, "parent") {
+                Some(parent) => format!("node \"{}\"", tree::join_path(&parent, &name)),
+                None => format!("node \"{}\"", name),
+            }
+        },
+        "ext_resource" | "sub_resource" => format!("{} {}", element.element_name, element.get_data_value("id").unwrap_or_default()),
+        name => String::from(name),
+    }
+}
+
+// Points resource references at their new ids and renames classes in `Object(..)` values.
+fn upgrade_value(value:&mut Variant, ids:&HashMap<(ResourceKind, ResourceId), ResourceId>, table:&RenameTable) {
+    match value {
+        Variant::ExtResource(id) => {
+            if let Some(new_id) = ids.get(&(ResourceKind::Ext, id.clone())) {
+                *id = new_id.clone();
+            }
+        },
+        Variant::SubResource(id) => {
+            if let Some(new_id) = ids.get(&(ResourceKind::Sub, id.clone())) {
+                *id = new_id.clone();
+            }
+        },
+        Variant::Array(values) | Variant::TypedArray(_, values) => {
+            for value in values.iter_mut() {
+                upgrade_value(value, ids, table);
+            }
+        },
+        Variant::Dictionary(pairs) | Variant::TypedDictionary(_, pairs) => {
+            for (key, value) in pairs.iter_mut() {
+                upgrade_value(key, ids, table);
+                upgrade_value(value, ids, table);
+            }
+        },
+        Variant::Object(class, properties) => {
+            if let Some(new_class) = table.class(class) {
+                *class = String::from(new_class);
+            }
+            for (_, value) in properties.iter_mut() {
+                upgrade_value(value, ids, table);
+            }
+        },
+        _ => {}
+    }
+}
+
+// Multiplies numbers and float vectors, `false` for other values.
+fn scale_value(value:&mut Variant, scale:f64) -> bool {
+    match value {
+        Variant::Int(int) => {
+            *value = Variant::Float(*int as f64 * scale);
+        },
+        Variant::Float(float) => *float *= scale,
+        Variant::Vector2(x, y) => {
+            *x *= scale;
+            *y *= scale;
+        },
+        Variant::Vector3(x, y, z) => {
+            *x *= scale;
+            *y *= scale;
+            *z *= scale;
+        },
+        _ => return false,
+    }
+    true
+}
+
+// Converts Godot 3 (`format=2`) sections to Godot 4 (`format=3`) in place, keeping their layout.
+// `Err` if the file isn't in the Godot 3 format.
+pub(crate) fn upgrade_elements(elements:&mut [Element], table:&RenameTable) -> Result<ConversionReport, ConversionError> {
+    let format = scene::file_format(elements);
+    if format != Format::Godot3 {
+        return Err(ConversionError::NotGodot3(format));
+    }
+    let ids = new_ids(elements, table);
+    let mut report = ConversionReport::default();
+    for element in elements.iter() {
+        if let (Some(kind), Ok(id)) = (resource_kind(element), element.get_data_value("id")) {
+            let old_id = ResourceId::from_data_value(&id);
+            report.ids.push((kind, old_id.clone(), ids[&(kind, old_id)].clone()));
+        }
+    }
+    // `[resource]` has no `type=`, its class is the one in the file header.
+    let main_class = elements.iter().find(|element| element.element_name == "gd_resource").and_then(|header| data_string(header, "type"));
+    let convert = |text:&str| -> Result<Variant, VariantError> {
+        let mut value = Variant::parse(text)?;
+        upgrade_value(&mut value, &ids, table);
+        Ok(value)
+    };
+    for element in elements.iter_mut() {
+        let section = describe(element);
+        let class = if element.element_name == "resource" { main_class.clone() } else { data_string(element, "type") };
+
+        for index in 0..element.element_data.len() {
+            let (name, value) = (element.element_data[index].0.clone(), element.element_data[index].1.clone());
+            let new_value = match (&element.element_name[..], &name[..], resource_kind(element)) {
+                ("gd_scene" | "gd_resource", "format", _) => String::from("3"),
+                (_, "id", Some(kind)) => match ids.get(&(kind, ResourceId::from_data_value(&value))) {
+                    Some(id) => id.to_string(),
+                    None => continue,
+                },
+                (_, "type", _) => {
+                    let Some(class) = class.as_deref() else {
+                        continue;
+                    };
+                    if table.is_removed(class) {
+                        report.untranslated.push(Untranslated::RemovedClass { section: section.clone(), class: String::from(class) });
+                    }
+                    match table.class(class) {
+                        Some(new_class) => Variant::String(String::from(new_class)).to_text(Format::Godot4),
+                        None => continue,
+                    }
+                },
+                _ => match convert(&value) {
+                    Ok(new_value) => new_value.to_text(Format::Godot4),
+                    Err(error) => {
+                        report.untranslated.push(Untranslated::Value { section: section.clone(), name, error });
+                        continue;
+                    }
+                },
+            };
+            if new_value != value {
+                let _ = element.update_data_by_index(index, &new_value);
+            }
+        }
+
+        let names = element.properties.iter().map(|property| property.0.clone()).collect::<Vec<String>>();
+        for name in names {
+            let value = match element.get_property_value(&name).map(|text| convert(&text)) {
+                Ok(Ok(value)) => value,
+                Ok(Err(error)) => {
+                    report.untranslated.push(Untranslated::Value { section: section.clone(), name, error });
+                    continue;
+                },
+                Err(_) => continue,
+            };
+            // Godot 4 stores each metadata entry as its own `metadata/name` property.
+            if name == "__meta__" {
+                if let Variant::Dictionary(entries) = &value {
+                    if entries.iter().all(|(key, _)| matches!(key, Variant::String(..))) {
+                        let index = element.properties.iter().position(|property| property.0 == name).unwrap_or_default();
+                        let _ = element.remove_property(&name);
+                        for (offset, (key, entry)) in entries.iter().enumerate() {
+                            let key = key.as_str().unwrap_or_default();
+                            let _ = element.insert_property_at(index + offset, &format!("metadata/{}", key), &entry.to_text(Format::Godot4));
+                        }
+                        continue;
+                    }
+                }
+                report.untranslated.push(Untranslated::Property { section: section.clone(), name });
+                continue;
+            }
+            let mut value = value;
+            let rename = table.property(class.as_deref(), &name);
+            if let Some(scale) = rename.and_then(|rename| rename.scale) {
+                if !scale_value(&mut value, scale) {
+                    report.untranslated.push(Untranslated::Property { section: section.clone(), name });
+                    continue;
+                }
+            }
+            let new_value = value.to_text(Format::Godot4);
+            if element.get_property_value(&name).is_ok_and(|old_value| old_value != new_value) {
+                let _ = element.update_property(&name, &new_value);
+            }
+            if let Some(rename) = rename {
+                let new_name = if rename.prefix { name.replacen(&rename.from, &rename.to, 1) } else { rename.to.clone() };
+                if element.rename_property(&name, &new_name).is_err() {
+                    report.untranslated.push(Untranslated::Property { section: section.clone(), name: name.clone() });
+                }
+            }
+            if table.needs_review(class.as_deref(), &name) {
+                report.untranslated.push(Untranslated::Property { section: section.clone(), name });
+            }
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,287 @@
+// Reads Rust values out of scenes with serde: the properties of a node fill the fields of a struct, by name.
+// Values are read as their `Variant`: vectors, colors, transforms and packed arrays as sequences, dictionaries
+// and objects as maps, `ExtResource(..)` and `SubResource(..)` as their text. Enums are read from the name of a
+// variant or, like Godot's enum properties, from its index. Properties whose value can't be parsed are only an
+// error if the struct asks for them.
+
+use std::{fmt, slice};
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+
+use crate::{element::Property, scene::{Scene, SceneError}, variant::{Format, Variant, VariantError}};
+
+#[derive(Debug)]
+pub enum Error {
+    Scene(SceneError),
+    NodeNotFound(String),
+    Value(String, VariantError), // 0: Property name
+    Message(String),
+}
+
+impl Error {
+    fn in_property(self, name:&str) -> Self {
+        match self {
+            Error::Message(message) => Error::Message(format!("{}: {}", name, message)),
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Scene(error) => write!(f, "{}", error),
+            Error::NodeNotFound(path) => write!(f, "node {:?} not found", path),
+            Error::Value(name, error) => write!(f, "{}: {}", name, error),
+            Error::Message(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Scene(error) => Some(error),
+            Error::Value(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T:fmt::Display>(message:T) -> Self {
+        Error::Message(message.to_string())
+    }
+}
+
+// Reads the properties of the root node of the scene in `text`.
+pub fn from_str<T:DeserializeOwned>(text:&str) -> Result<T, Error> {
+    let scene = text.parse::<Scene>().map_err(Error::Scene)?;
+    from_node(&scene, ".")
+}
+
+// Reads the properties of the node at `path`, in the form used by `parent=`. Only those set in this scene are
+// seen, not the ones the node gets from an instanced scene.
+pub fn from_node<T:DeserializeOwned>(scene:&Scene, path:&str) -> Result<T, Error> {
+    let tree = scene.tree();
+    let element = tree.get_node(path).and_then(|node| node.element()).ok_or_else(|| Error::NodeNotFound(String::from(path)))?;
+    from_properties(&element.properties)
+}
+
+// Reads the properties of any section, such as a sub resource or the `[resource]` of a `.tres` file.
+pub fn from_properties<T:DeserializeOwned>(properties:&[Property]) -> Result<T, Error> {
+    T::deserialize(PropertiesDeserializer(properties))
+}
+
+pub fn from_variant<T:DeserializeOwned>(value:&Variant) -> Result<T, Error> {
+    T::deserialize(VariantDeserializer(value))
+}
+
+// The numbers of math types and the items of packed arrays, read as a sequence.
+fn components(value:&Variant) -> Option<Vec<Variant>> {
+    let floats = |values:&[f64]| values.iter().map(|value| Variant::Float(*value)).collect::<Vec<Variant>>();
+    let ints = |values:&[i64]| values.iter().map(|value| Variant::Int(*value)).collect::<Vec<Variant>>();
+    Some(match value {
+        Variant::Vector2(x, y) => floats(&[*x, *y]),
+        Variant::Vector2i(x, y) => ints(&[*x, *y]),
+        Variant::Vector3(x, y, z) => floats(&[*x, *y, *z]),
+        Variant::Vector3i(x, y, z) => ints(&[*x, *y, *z]),
+        Variant::Vector4(x, y, z, w) | Variant::Quaternion(x, y, z, w) | Variant::Plane(x, y, z, w) => floats(&[*x, *y, *z, *w]),
+        Variant::Vector4i(x, y, z, w) => ints(&[*x, *y, *z, *w]),
+        Variant::Rect2(x, y, width, height) => floats(&[*x, *y, *width, *height]),
+        Variant::Rect2i(x, y, width, height) => ints(&[*x, *y, *width, *height]),
+        Variant::Color(r, g, b, a) => floats(&[*r, *g, *b, *a]),
+        Variant::Transform2D(values) | Variant::Aabb(values) => floats(values),
+        Variant::Transform3D(values) => floats(values),
+        Variant::Basis(values) => floats(values),
+        Variant::Projection(values) => floats(values),
+        Variant::PackedByteArray(values) => values.iter().map(|value| Variant::Int(*value as i64)).collect(),
+        Variant::PackedInt32Array(values) => values.iter().map(|value| Variant::Int(*value as i64)).collect(),
+        Variant::PackedInt64Array(values) => ints(values),
+        Variant::PackedFloat32Array(values) => values.iter().map(|value| Variant::Float(*value as f64)).collect(),
+        Variant::PackedFloat64Array(values) => floats(values),
+        Variant::PackedStringArray(values) => values.iter().map(|value| Variant::String(value.clone())).collect(),
+        Variant::PackedVector2Array(values) => values.iter().map(|(x, y)| Variant::Vector2(*x, *y)).collect(),
+        Variant::PackedVector3Array(values) => values.iter().map(|(x, y, z)| Variant::Vector3(*x, *y, *z)).collect(),
+        Variant::PackedVector4Array(values) => values.iter().map(|(x, y, z, w)| Variant::Vector4(*x, *y, *z, *w)).collect(),
+        Variant::PackedColorArray(values) => values.iter().map(|(r, g, b, a)| Variant::Color(*r, *g, *b, *a)).collect(),
+        _ => return None,
+    })
+}
+
+struct VariantDeserializer<'a>(&'a Variant);
+
+impl<'de> Deserializer<'de> for VariantDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V:Visitor<'de>>(self, visitor:V) -> Result<V::Value, Error> {
+        if let Some(items) = components(self.0) {
+            return visitor.visit_seq(Items(items.iter()));
+        }
+        match self.0 {
+            Variant::Nil => visitor.visit_unit(),
+            Variant::Bool(value) => visitor.visit_bool(*value),
+            Variant::Int(value) => visitor.visit_i64(*value),
+            Variant::Float(value) => visitor.visit_f64(*value),
+            Variant::String(string) | Variant::StringName(string) | Variant::NodePath(string) | Variant::Resource(string) => visitor.visit_str(string),
+            Variant::Array(values) | Variant::TypedArray(_, values) => visitor.visit_seq(Items(values.iter())),
+            Variant::Dictionary(pairs) | Variant::TypedDictionary(_, pairs) => visitor.visit_map(Pairs { pairs: pairs.iter(), value: None }),
+            Variant::Object(_, properties) => {
+                let pairs = properties.iter().map(|(name, value)| (Variant::String(name.clone()), value.clone())).collect::<Vec<(Variant, Variant)>>();
+                visitor.visit_map(Pairs { pairs: pairs.iter(), value: None })
+            },
+            value => visitor.visit_string(value.to_text(Format::Godot4)),
+        }
+    }
+
+    fn deserialize_option<V:Visitor<'de>>(self, visitor:V) -> Result<V::Value, Error> {
+        match self.0 {
+            Variant::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_enum<V:Visitor<'de>>(self, _name:&'static str, _variants:&'static [&'static str], visitor:V) -> Result<V::Value, Error> {
+        match self.0 {
+            Variant::String(name) | Variant::StringName(name) => visitor.visit_enum(name.as_str().into_deserializer()),
+            Variant::Int(index) => {
+                let index = u32::try_from(*index).map_err(|_| Error::Message(format!("{} is not a valid enum index", index)))?;
+                visitor.visit_enum(index.into_deserializer())
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V:Visitor<'de>>(self, _name:&'static str, visitor:V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct Items<'a>(slice::Iter<'a, Variant>);
+
+impl<'de> SeqAccess<'de> for Items<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T:DeserializeSeed<'de>>(&mut self, seed:T) -> Result<Option<T::Value>, Error> {
+        self.0.next().map(|value| seed.deserialize(VariantDeserializer(value))).transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct Pairs<'a> {
+    pairs:slice::Iter<'a, (Variant, Variant)>,
+    value:Option<&'a Variant>,
+}
+
+impl<'de> MapAccess<'de> for Pairs<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K:DeserializeSeed<'de>>(&mut self, seed:K) -> Result<Option<K::Value>, Error> {
+        match self.pairs.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(VariantDeserializer(key)).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V:DeserializeSeed<'de>>(&mut self, seed:V) -> Result<V::Value, Error> {
+        let value = self.value.take().ok_or_else(|| Error::Message(String::from("value asked for before its key")))?;
+        seed.deserialize(VariantDeserializer(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.pairs.len())
+    }
+}
+
+// A section's properties as a map from name to value.
+struct PropertiesDeserializer<'a>(&'a [Property]);
+
+impl<'de> Deserializer<'de> for PropertiesDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V:Visitor<'de>>(self, visitor:V) -> Result<V::Value, Error> {
+        visitor.visit_map(Properties { properties: self.0.iter(), value: None })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit unit_struct
+        newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct Properties<'a> {
+    properties:slice::Iter<'a, Property>,
+    value:Option<&'a Property>,
+}
+
+impl<'de> MapAccess<'de> for Properties<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K:DeserializeSeed<'de>>(&mut self, seed:K) -> Result<Option<K::Value>, Error> {
+        match self.properties.next() {
+            Some(property) => {
+                self.value = Some(property);
+                seed.deserialize(property.0.as_str().into_deserializer()).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V:DeserializeSeed<'de>>(&mut self, seed:V) -> Result<V::Value, Error> {
+        let property = self.value.take().ok_or_else(|| Error::Message(String::from("value asked for before its key")))?;
+        seed.deserialize(PropertyDeserializer(property)).map_err(|error| error.in_property(&property.0))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.properties.len())
+    }
+}
+
+// Parses the value when it is read, so unused properties with values that can't be parsed are skipped.
+struct PropertyDeserializer<'a>(&'a Property);
+
+impl PropertyDeserializer<'_> {
+    fn value(&self) -> Result<Variant, Error> {
+        self.0.value().map_err(|error| Error::Value(self.0.0.clone(), error))
+    }
+}
+
+impl<'de> Deserializer<'de> for PropertyDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V:Visitor<'de>>(self, visitor:V) -> Result<V::Value, Error> {
+        VariantDeserializer(&self.value()?).deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V:Visitor<'de>>(self, visitor:V) -> Result<V::Value, Error> {
+        VariantDeserializer(&self.value()?).deserialize_option(visitor)
+    }
+
+    fn deserialize_enum<V:Visitor<'de>>(self, name:&'static str, variants:&'static [&'static str], visitor:V) -> Result<V::Value, Error> {
+        VariantDeserializer(&self.value()?).deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_newtype_struct<V:Visitor<'de>>(self, name:&'static str, visitor:V) -> Result<V::Value, Error> {
+        VariantDeserializer(&self.value()?).deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_ignored_any<V:Visitor<'de>>(self, visitor:V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier
+    }
+}
@@ -0,0 +1,453 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{connection::Connection, element::Element, interchange, json::{self, Value}, resource_table::{ResourceEntry, ResourceKind, ResourceTable}, scene::Scene, tree::{self, data_string}, variant::{Format, ResourceId, Variant}};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeChange {
+    Added { path:String, node_type:Option<String>, instance:Option<String> }, // `instance` is the `res://` path of an instanced scene.
+    Removed { path:String, node_type:Option<String>, instance:Option<String> },
+    Moved { from:String, to:String }, // New parent, and possibly a new name when the node has a `unique_id`.
+    Renamed { from:String, to:String },
+}
+
+// Where a changed property lives, by path or id in the new scene.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyOwner {
+    Node(String),
+    SubResource(ResourceId),
+}
+
+// A property set, changed or unset. Values that can't be parsed are compared as text and given as `Variant::String`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyChange {
+    pub owner:PropertyOwner,
+    pub name:String,
+    pub header:bool, // Header data such as `type` or `groups` rather than a property.
+    pub old:Option<Variant>,
+    pub new:Option<Variant>,
+}
+
+// Ext resources are matched by UID, or by path when either side has none. Sub resources by id and type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceChange {
+    Added(ResourceEntry), // Element index into the new scene.
+    Removed(ResourceEntry), // Element index into the old scene.
+    Moved { old:ResourceEntry, new:ResourceEntry }, // Same UID, different path.
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionChange {
+    Added(Connection),
+    Removed(Connection),
+    Changed { old:Connection, new:Connection }, // Different flags or binds.
+}
+
+// What changed between two versions of a scene, in the terms of the scene rather than its text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneDiff {
+    pub nodes:Vec<NodeChange>,
+    pub properties:Vec<PropertyChange>,
+    pub resources:Vec<ResourceChange>,
+    pub connections:Vec<ConnectionChange>,
+    pub old_format:Format, // Values of each side are written the way its file writes them.
+    pub new_format:Format,
+}
+
+// Node header data that says where the node is rather than what it is.
+const PLACEMENT_DATA:[&str; 5] = ["name", "parent", "owner", "index", "unique_id"];
+
+// How deep sub resources holding sub resources are compared before assuming they are the same.
+pub(crate) const MAX_RESOURCE_DEPTH:usize = 16;
+
+pub(crate) struct Side<'a> {
+    pub(crate) resources:ResourceTable<'a>,
+    pub(crate) nodes:Vec<(String, &'a Element)>, // Depth first.
+}
+
+impl<'a> Side<'a> {
+    pub(crate) fn new(scene:&'a Scene) -> Self {
+        let tree = scene.tree();
+        let nodes = tree.depth_first().filter_map(|node| Some((node.path(), node.element_index()?))).collect::<Vec<(String, usize)>>();
+        Side { resources: scene.resources(), nodes: nodes.into_iter().map(|(path, index)| (path, &scene.elements[index])).collect() }
+    }
+
+    fn instance(&self, element:&Element) -> Option<String> {
+        self.resources.resolve(&element.get_data_variant("instance").ok()?)?.path.clone()
+    }
+}
+
+pub(crate) fn parse(text:&str) -> Variant {
+    Variant::parse(text).unwrap_or_else(|_| Variant::String(String::from(text.trim())))
+}
+
+pub(crate) fn parent_path(path:&str) -> &str {
+    match path.rsplit_once('/') {
+        Some((parent, _)) => parent,
+        None if path == "." => "",
+        None => ".",
+    }
+}
+
+pub(crate) fn name_of(path:&str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// Whether two values mean the same, following resource references into each scene: ids are renumbered freely.
+pub(crate) fn equivalent(a:&Variant, a_side:&Side, b:&Variant, b_side:&Side, depth:usize) -> bool {
+    match (a, b) {
+        (Variant::ExtResource(_), Variant::ExtResource(_)) => match (a_side.resources.resolve(a), b_side.resources.resolve(b)) {
+            (Some(a_entry), Some(b_entry)) => same_file(a_entry, b_entry) && a_entry.resource_type == b_entry.resource_type,
+            _ => a == b,
+        },
+        (Variant::SubResource(_), Variant::SubResource(_)) => match (a_side.resources.resolve(a), b_side.resources.resolve(b)) {
+            // The same sub resource on both sides, changes to it are listed for the sub resource itself.
+            (Some(a_entry), Some(b_entry)) if a_entry.id == b_entry.id && a_entry.resource_type == b_entry.resource_type => true,
+            (Some(a_entry), Some(b_entry)) => depth >= MAX_RESOURCE_DEPTH || same_sub_resource(a_entry, a_side, b_entry, b_side, depth + 1),
+            _ => a == b,
+        },
+        (Variant::Array(a_values), Variant::Array(b_values)) => all_equivalent(a_values, a_side, b_values, b_side, depth),
+        (Variant::TypedArray(a_type, a_values), Variant::TypedArray(b_type, b_values)) => a_type == b_type && all_equivalent(a_values, a_side, b_values, b_side, depth),
+        (Variant::Dictionary(a_pairs), Variant::Dictionary(b_pairs)) | (Variant::TypedDictionary(_, a_pairs), Variant::TypedDictionary(_, b_pairs)) => {
+            a_pairs.len() == b_pairs.len() && a_pairs.iter().zip(b_pairs.iter()).all(|((a_key, a_value), (b_key, b_value))| {
+                equivalent(a_key, a_side, b_key, b_side, depth) && equivalent(a_value, a_side, b_value, b_side, depth)
+            })
+        },
+        _ => a == b,
+    }
+}
+
+pub(crate) fn all_equivalent(a:&[Variant], a_side:&Side, b:&[Variant], b_side:&Side, depth:usize) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| equivalent(a, a_side, b, b_side, depth))
+}
+
+pub(crate) fn same_file(a:&ResourceEntry, b:&ResourceEntry) -> bool {
+    match (&a.uid, &b.uid) {
+        (Some(a_uid), Some(b_uid)) => a_uid == b_uid,
+        _ => a.path == b.path,
+    }
+}
+
+pub(crate) fn same_sub_resource(a:&ResourceEntry, a_side:&Side, b:&ResourceEntry, b_side:&Side, depth:usize) -> bool {
+    let a_properties = &a_side.resources.element(a).properties;
+    let b_properties = &b_side.resources.element(b).properties;
+    a.resource_type == b.resource_type && a_properties.len() == b_properties.len() && a_properties.iter().all(|a_property| {
+        b_properties.iter().find(|b_property| b_property.0 == a_property.0).is_some_and(|b_property| equivalent(&parse(&a_property.1), a_side, &parse(&b_property.1), b_side, depth))
+    })
+}
+
+// Property and header data changes between two versions of the same node or sub resource.
+pub(crate) fn compare_elements(owner:PropertyOwner, old:&Element, old_side:&Side, new:&Element, new_side:&Side, changes:&mut Vec<PropertyChange>) {
+    let mut compare = |name:&str, header:bool, old_text:Option<&String>, new_text:Option<&String>| {
+        let old_value = old_text.map(|text| parse(text));
+        let new_value = new_text.map(|text| parse(text));
+        let same = match (&old_value, &new_value) {
+            (Some(old_value), Some(new_value)) => equivalent(old_value, old_side, new_value, new_side, 0),
+            (None, None) => true,
+            _ => false,
+        };
+        if !same {
+            changes.push(PropertyChange { owner: owner.clone(), name: String::from(name), header, old: old_value, new: new_value });
+        }
+    };
+    for header in [true, false] {
+        let old_values = named_values(old, header);
+        let new_values = named_values(new, header);
+        for (name, old_text) in old_values.iter() {
+            compare(name, header, Some(old_text), new_values.iter().find(|(new_name, _)| new_name == name).map(|(_, text)| text));
+        }
+        for (name, new_text) in new_values.iter().filter(|(name, _)| !old_values.iter().any(|(old_name, _)| old_name == name)) {
+            compare(name, header, None, Some(new_text));
+        }
+    }
+}
+
+// Properties, or header data other than the element's name, place and id.
+fn named_values(element:&Element, header:bool) -> Vec<(String, String)> {
+    if header {
+        element.element_data.iter().filter(|data| !PLACEMENT_DATA.contains(&&data.0[..]) && data.0 != "id").map(|data| (data.0.clone(), data.1.clone())).collect::<Vec<(String, String)>>()
+    }
+    else {
+        element.properties.iter().map(|property| (property.0.clone(), property.1.clone())).collect::<Vec<(String, String)>>()
+    }
+}
+
+impl SceneDiff {
+    pub fn new(old:&Scene, new:&Scene) -> Self {
+        let old_side = Side::new(old);
+        let new_side = Side::new(new);
+        let mut diff = SceneDiff { old_format: old.format(), new_format: new.format(), ..SceneDiff::default() };
+
+        // Nodes: first by path, then nodes that moved or were renamed.
+        let new_paths = new_side.nodes.iter().enumerate().map(|(index, (path, _))| (path.as_str(), index)).collect::<HashMap<&str, usize>>();
+        let mut matched:Vec<Option<usize>> = old_side.nodes.iter().map(|(path, _)| new_paths.get(path.as_str()).copied()).collect();
+        let mut new_matched = vec![false; new_side.nodes.len()];
+        for &index in matched.iter().flatten() {
+            new_matched[index] = true;
+        }
+        let mut moves:HashMap<String, String> = HashMap::new(); // Old path to new path of every matched node whose path changed.
+        for (old_index, (old_path, old_element)) in old_side.nodes.iter().enumerate() {
+            if matched[old_index].is_some() {
+                continue;
+            }
+            let old_parent = parent_path(old_path);
+            // Under a node that moved, children follow their parent.
+            let new_parent = moves.get(old_parent).map(|path| path.as_str()).unwrap_or(old_parent);
+            if new_parent != old_parent {
+                let expected = tree::join_path(new_parent, name_of(old_path));
+                if let Some(&new_index) = new_paths.get(expected.as_str()).filter(|&&new_index| !new_matched[new_index]) {
+                    matched[old_index] = Some(new_index);
+                    new_matched[new_index] = true;
+                    moves.insert(old_path.clone(), expected);
+                    continue;
+                }
+            }
+            let same_kind = |element:&Element| data_string(element, "type") == data_string(old_element, "type") && new_side.instance(element) == old_side.instance(old_element);
+            let unique_id = old_element.get_data_value("unique_id").ok();
+            let candidates = new_side.nodes.iter().enumerate().filter(|(new_index, (new_path, new_element))| {
+                if new_matched[*new_index] || new_path == "." {
+                    return false;
+                }
+                if unique_id.is_some() && new_element.get_data_value("unique_id").ok().is_some() {
+                    return new_element.get_data_value("unique_id").ok() == unique_id;
+                }
+                let moved = name_of(new_path) == name_of(old_path) && parent_path(new_path) != new_parent;
+                let renamed = parent_path(new_path) == new_parent && new_element.properties.len() == old_element.properties.len() && new_element.properties.iter().all(|property| {
+                    old_element.get_property_value(&property.0).is_ok_and(|old_text| equivalent(&parse(&old_text), &old_side, &parse(&property.1), &new_side, 0))
+                });
+                same_kind(new_element) && (moved || renamed)
+            }).map(|(new_index, _)| new_index).collect::<Vec<usize>>();
+            if let [new_index] = candidates[..] {
+                let new_path = new_side.nodes[new_index].0.clone();
+                matched[old_index] = Some(new_index);
+                new_matched[new_index] = true;
+                if parent_path(&new_path) == new_parent {
+                    diff.nodes.push(NodeChange::Renamed { from: old_path.clone(), to: new_path.clone() });
+                }
+                else {
+                    diff.nodes.push(NodeChange::Moved { from: old_path.clone(), to: new_path.clone() });
+                }
+                moves.insert(old_path.clone(), new_path);
+            }
+        }
+        for (old_index, (old_path, old_element)) in old_side.nodes.iter().enumerate() {
+            match matched[old_index] {
+                Some(new_index) => {
+                    let (new_path, new_element) = &new_side.nodes[new_index];
+                    // The root's name isn't part of any path.
+                    if old_path == "." && data_string(old_element, "name") != data_string(new_element, "name") {
+                        let name = |element:&Element| data_string(element, "name").map(Variant::String);
+                        diff.properties.push(PropertyChange { owner: PropertyOwner::Node(String::from(".")), name: String::from("name"), header: true, old: name(old_element), new: name(new_element) });
+                    }
+                    compare_elements(PropertyOwner::Node(new_path.clone()), old_element, &old_side, new_element, &new_side, &mut diff.properties);
+                },
+                None => {
+                    diff.nodes.push(NodeChange::Removed { path: old_path.clone(), node_type: data_string(old_element, "type"), instance: old_side.instance(old_element) });
+                }
+            }
+        }
+        for (new_index, (new_path, new_element)) in new_side.nodes.iter().enumerate() {
+            if !new_matched[new_index] {
+                diff.nodes.push(NodeChange::Added { path: new_path.clone(), node_type: data_string(new_element, "type"), instance: new_side.instance(new_element) });
+            }
+        }
+
+        // Resources.
+        let old_entries = old_side.resources.entries();
+        let new_entries = new_side.resources.entries();
+        let counterpart = |entry:&ResourceEntry, others:&'_ [ResourceEntry]| -> Option<usize> {
+            others.iter().position(|other| other.kind == entry.kind && match entry.kind {
+                ResourceKind::Ext => same_file(entry, other),
+                ResourceKind::Sub => other.id == entry.id && other.resource_type == entry.resource_type,
+            })
+        };
+        for old_entry in old_entries.iter() {
+            match counterpart(old_entry, new_entries) {
+                Some(new_index) => {
+                    let new_entry = &new_entries[new_index];
+                    if old_entry.kind == ResourceKind::Ext && old_entry.path != new_entry.path {
+                        diff.resources.push(ResourceChange::Moved { old: old_entry.clone(), new: new_entry.clone() });
+                    }
+                    if old_entry.kind == ResourceKind::Sub {
+                        let owner = PropertyOwner::SubResource(new_entry.id.clone());
+                        compare_elements(owner, old_side.resources.element(old_entry), &old_side, new_side.resources.element(new_entry), &new_side, &mut diff.properties);
+                    }
+                },
+                None => {
+                    diff.resources.push(ResourceChange::Removed(old_entry.clone()));
+                }
+            }
+        }
+        for new_entry in new_entries.iter().filter(|new_entry| counterpart(new_entry, old_entries).is_none()) {
+            diff.resources.push(ResourceChange::Added(new_entry.clone()));
+        }
+
+        // Connections, with old paths updated for the nodes that moved.
+        let follow = |path:&str| moves.iter().filter_map(|(from, to)| tree::moved_path(path, from, to)).max_by_key(|path| path.len()).unwrap_or_else(|| String::from(path));
+        let old_connections = old.connections().into_iter().map(|mut connection| {
+            connection.from = follow(&connection.from);
+            connection.to = follow(&connection.to);
+            connection
+        }).collect::<Vec<Connection>>();
+        let new_connections = new.connections();
+        for old_connection in old_connections.iter() {
+            match new_connections.iter().find(|new_connection| new_connection.is_same(old_connection)) {
+                Some(new_connection) => {
+                    let same_binds = all_equivalent(&old_connection.binds, &old_side, &new_connection.binds, &new_side, 0);
+                    if old_connection.flags != new_connection.flags || old_connection.unbinds != new_connection.unbinds || !same_binds {
+                        diff.connections.push(ConnectionChange::Changed { old: old_connection.clone(), new: new_connection.clone() });
+                    }
+                },
+                None => {
+                    diff.connections.push(ConnectionChange::Removed(old_connection.clone()));
+                }
+            }
+        }
+        for new_connection in new_connections.into_iter().filter(|new_connection| !old_connections.iter().any(|old_connection| old_connection.is_same(new_connection))) {
+            diff.connections.push(ConnectionChange::Added(new_connection));
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.properties.is_empty() && self.resources.is_empty() && self.connections.is_empty()
+    }
+
+    // Values are typed like in the `interchange` schema, `null` when unset.
+    pub fn to_json(&self) -> String {
+        let value = |value:&Option<Variant>| value.as_ref().map_or(Value::Null, interchange::typed).to_compact();
+        let nodes = self.nodes.iter().map(|change| match change {
+            NodeChange::Added { path, node_type, instance } | NodeChange::Removed { path, node_type, instance } => json::object(&[
+                ("change", json::string(if matches!(change, NodeChange::Added { .. }) { "added" } else { "removed" })),
+                ("path", json::string(path)),
+                ("type", json::optional_string(node_type.as_deref())),
+                ("instance", json::optional_string(instance.as_deref())),
+            ]),
+            NodeChange::Moved { from, to } | NodeChange::Renamed { from, to } => json::object(&[
+                ("change", json::string(if matches!(change, NodeChange::Moved { .. }) { "moved" } else { "renamed" })),
+                ("from", json::string(from)),
+                ("to", json::string(to)),
+            ]),
+        }).collect::<Vec<String>>();
+        let properties = self.properties.iter().map(|change| {
+            let owner = match &change.owner {
+                PropertyOwner::Node(path) => ("node", json::string(path)),
+                PropertyOwner::SubResource(id) => ("sub_resource", json::string(&resource_id(id))),
+            };
+            json::object(&[owner, ("name", json::string(&change.name)), ("header", change.header.to_string()), ("old", value(&change.old)), ("new", value(&change.new))])
+        }).collect::<Vec<String>>();
+        let entry = |change:&str, entry:&ResourceEntry| json::object(&[
+            ("change", json::string(change)),
+            ("kind", json::string(resource_kind(entry.kind))),
+            ("id", json::string(&resource_id(&entry.id))),
+            ("type", json::optional_string(entry.resource_type.as_deref())),
+            ("path", json::optional_string(entry.path.as_deref())),
+        ]);
+        let resources = self.resources.iter().map(|change| match change {
+            ResourceChange::Added(added) => entry("added", added),
+            ResourceChange::Removed(removed) => entry("removed", removed),
+            ResourceChange::Moved { old, new } => json::object(&[
+                ("change", json::string("moved")),
+                ("kind", json::string(resource_kind(new.kind))),
+                ("id", json::string(&resource_id(&new.id))),
+                ("from", json::optional_string(old.path.as_deref())),
+                ("to", json::optional_string(new.path.as_deref())),
+            ]),
+        }).collect::<Vec<String>>();
+        let connection = |connection:&Connection| json::object(&[
+            ("signal", json::string(&connection.signal)),
+            ("from", json::string(&connection.from)),
+            ("to", json::string(&connection.to)),
+            ("method", json::string(&connection.method)),
+            ("flags", connection.flags.map_or(String::from("null"), |flags| flags.to_string())),
+            ("binds", Value::Array(connection.binds.iter().map(interchange::typed).collect()).to_compact()),
+        ]);
+        let connections = self.connections.iter().map(|change| match change {
+            ConnectionChange::Added(added) => json::object(&[("change", json::string("added")), ("connection", connection(added))]),
+            ConnectionChange::Removed(removed) => json::object(&[("change", json::string("removed")), ("connection", connection(removed))]),
+            ConnectionChange::Changed { old, new } => json::object(&[("change", json::string("changed")), ("old", connection(old)), ("new", connection(new))]),
+        }).collect::<Vec<String>>();
+        format!(
+            "{{\n  \"nodes\": {},\n  \"properties\": {},\n  \"resources\": {},\n  \"connections\": {}\n}}\n",
+            json::array(&nodes, 2), json::array(&properties, 2), json::array(&resources, 2), json::array(&connections, 2),
+        )
+    }
+}
+
+fn resource_kind(kind:ResourceKind) -> &'static str {
+    match kind {
+        ResourceKind::Ext => "ext",
+        ResourceKind::Sub => "sub",
+    }
+}
+
+// The id without the quotes of its text form.
+pub(crate) fn resource_id(id:&ResourceId) -> String {
+    match id {
+        ResourceId::Int(int) => int.to_string(),
+        ResourceId::String(string) => string.clone(),
+    }
+}
+
+pub(crate) fn describe_connection(connection:&Connection) -> String {
+    format!("connection {:?} from {:?} to {:?} method {:?}", connection.signal, connection.from, connection.to, connection.method)
+}
+
+fn describe_entry(entry:&ResourceEntry) -> String {
+    let name = match entry.kind {
+        ResourceKind::Ext => format!("ext_resource {:?}", entry.path.as_deref().unwrap_or_default()),
+        ResourceKind::Sub => format!("sub_resource {:?}", resource_id(&entry.id)),
+    };
+    match &entry.resource_type {
+        Some(resource_type) => format!("{} ({})", name, resource_type),
+        None => name,
+    }
+}
+
+// One change per line: `+` added, `-` removed, `>` moved or renamed, `~` changed.
+impl fmt::Display for SceneDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.nodes.iter() {
+            match change {
+                NodeChange::Added { path, node_type, instance } | NodeChange::Removed { path, node_type, instance } => {
+                    let sign = if matches!(change, NodeChange::Added { .. }) { '+' } else { '-' };
+                    let kind = match (node_type, instance) {
+                        (Some(node_type), _) => format!(" ({})", node_type),
+                        (None, Some(instance)) => format!(" (instance of {:?})", instance),
+                        (None, None) => String::new(),
+                    };
+                    writeln!(f, "{} node {:?}{}", sign, path, kind)?;
+                },
+                NodeChange::Moved { from, to } => writeln!(f, "> node {:?} moved to {:?}", from, to)?,
+                NodeChange::Renamed { from, to } => writeln!(f, "> node {:?} renamed to {:?}", from, to)?,
+            }
+        }
+        for change in self.properties.iter() {
+            let owner = match &change.owner {
+                PropertyOwner::Node(path) => format!("node {:?}", path),
+                PropertyOwner::SubResource(id) => format!("sub_resource {:?}", resource_id(id)),
+            };
+            let name = if change.header { format!("[{}]", change.name) } else { change.name.clone() };
+            let value = |value:&Option<Variant>, format:Format| value.as_ref().map_or(String::from("(unset)"), |value| value.to_text(format));
+            writeln!(f, "~ {} {}: {} -> {}", owner, name, value(&change.old, self.old_format), value(&change.new, self.new_format))?;
+        }
+        for change in self.resources.iter() {
+            match change {
+                ResourceChange::Added(entry) => writeln!(f, "+ {}", describe_entry(entry))?,
+                ResourceChange::Removed(entry) => writeln!(f, "- {}", describe_entry(entry))?,
+                ResourceChange::Moved { old, new } => writeln!(f, "> {} moved to {:?}", describe_entry(old), new.path.as_deref().unwrap_or_default())?,
+            }
+        }
+        for change in self.connections.iter() {
+            match change {
+                ConnectionChange::Added(connection) => writeln!(f, "+ {}", describe_connection(connection))?,
+                ConnectionChange::Removed(connection) => writeln!(f, "- {}", describe_connection(connection))?,
+                ConnectionChange::Changed { old, new } => {
+                    let flags = |connection:&Connection| connection.flags.map_or(String::from("(unset)"), |flags| flags.to_string());
+                    let binds = |connection:&Connection, format:Format| Variant::Array(connection.binds.clone()).to_text(format);
+                    writeln!(f, "~ {}: flags {} -> {}, binds {} -> {}", describe_connection(new), flags(old), flags(new), binds(old, self.old_format), binds(new, self.new_format))?;
+                },
+            }
+        }
+        Ok(())
+    }
+}
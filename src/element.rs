@@ -1,19 +1,37 @@
-use crate::{tokenizer::Token, scene::{NodePath, NodePathError}};
+use std::{fmt, ops::Range};
 
+use crate::{tokenizer::{Span, Token}, scene::NodePathError, variant::{Variant, VariantError}};
 
-#[derive(Debug, Clone)]
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Property(pub String, pub String);
 impl Property {
-    pub fn to_tokens(&self) -> [Token;2] {
-        [Token::PropertyName(Some(self.0.clone())), Token::PropertyValue(Some(self.1.clone()))]
+    pub fn to_tokens(&self) -> [Token;5] {
+        [
+            Token::PropertyName(Some(self.0.clone())),
+            Token::Whitespace(String::from(' ')),
+            Token::Equals,
+            Token::Whitespace(String::from(' ')),
+            Token::PropertyValue(Some(self.1.clone())),
+        ]
+    }
+
+    pub fn value(&self) -> Result<Variant, VariantError> {
+        Variant::parse(&self.1)
     }
 }
 
-#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ElementData(pub String, pub String); // 0: Name, 1: Value
 impl ElementData {
-    pub fn to_tokens(&self) -> [Token;2] {
-        [Token::ElementDataName(Some(self.0.clone())), Token::ElementDataValue(Some(self.1.clone()))]
+    pub fn to_tokens(&self) -> [Token;3] {
+        [Token::ElementDataName(Some(self.0.clone())), Token::Equals, Token::ElementDataValue(Some(self.1.clone()))]
+    }
+
+    pub fn value(&self) -> Result<Variant, VariantError> {
+        Variant::parse(&self.1)
     }
 }
 
@@ -26,6 +44,49 @@ pub enum ExpectedType {
     PropertyValue,
 }
 
+impl fmt::Display for ExpectedType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ExpectedType::ElementName => "element name",
+            ExpectedType::ElementDataName => "element data name",
+            ExpectedType::ElementDataValue => "element data value",
+            ExpectedType::PropertyName => "property name",
+            ExpectedType::PropertyValue => "property value",
+        };
+        f.write_str(name)
+    }
+}
+
+// Why reading or editing an element's header data or properties failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LookupError {
+    NotFound(String), // 0: Data or property name
+    AlreadyExists(String), // 0: Data or property name
+    OutOfRange(usize), // 0: Index past the last data or property
+    InvalidValue(VariantError),
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupError::NotFound(name) => write!(f, "{} not found", name),
+            LookupError::AlreadyExists(name) => write!(f, "{} already exists", name),
+            LookupError::OutOfRange(index) => write!(f, "index {} is out of range", index),
+            LookupError::InvalidValue(error) => write!(f, "invalid value: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for LookupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LookupError::InvalidValue(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Clone)]
 pub struct Element {
     pub element_name:String,
@@ -33,25 +94,37 @@ pub struct Element {
     pub element_data:Vec<ElementData>,
     pub properties:Vec<Property>,
     pub tokens:Vec<Token>,
+    pub spans:Vec<Span>, // Source position of each token, empty for elements built or rebuilt in code.
 }
 
 impl Element {
     pub fn empty() -> Self {
-        Element { element_name: String::from("Undefined"), element_type:ElementType::UNKOWN, element_data: Vec::new(), properties: Vec::new(), tokens: Vec::new() }
+        Element { element_name: String::new(), element_type:ElementType::UNKOWN, element_data: Vec::new(), properties: Vec::new(), tokens: Vec::new(), spans: Vec::new() }
     }
     
+    // Properties above the first section header, e.g. `config_version=5` in `project.godot`, or a whole file
+    // without headers. They are kept in an element with an empty name, which has no header to write.
+    pub fn is_preamble(&self) -> bool {
+        self.element_name.is_empty()
+    }
+
     pub fn force_update_tokens(&mut self) {
-        let mut tokens:Vec<Token> = vec![Token::BracketLeft, Token::ElementName(Some(self.element_name.clone()))]; // Elements start with [element_name
-        
-        // Append ElementData tokens.
-        tokens.append(
-            &mut self.element_data.iter().flat_map(|element_data| {
-                element_data.to_tokens().to_vec()
-            }).collect()
-        );
-        // Close element
-        tokens.push(Token::BracketRight);
-        tokens.push(Token::NewLine);
+        let mut tokens:Vec<Token> = Vec::new();
+        if !self.is_preamble() {
+            tokens.extend([Token::BracketLeft, Token::ElementName(Some(self.element_name.clone()))]); // Elements start with [element_name
+
+            // Append ElementData tokens.
+            tokens.append(
+                &mut self.element_data.iter().flat_map(|element_data| {
+                    let mut v = vec![Token::Whitespace(String::from(' '))];
+                    v.extend(element_data.to_tokens());
+                    v
+                }).collect()
+            );
+            // Close element
+            tokens.push(Token::BracketRight);
+            tokens.push(Token::NewLine);
+        }
         if !self.properties.is_empty() {
             // Append property tokens
             tokens.append(
@@ -61,14 +134,58 @@ impl Element {
                     v
                 }).collect()
             );
-            println!("{:?}", tokens);
             tokens.push(Token::NewLine);
         }
 
         // Update complete.
         self.tokens = tokens;
+        self.spans.clear();
     }
 
+    // Rewrites only the `index`-th data or property value token so the rest of the element keeps its original formatting.
+    fn update_value_token(&mut self, index:usize, is_data:bool, new_value:&str) {
+        let value_token = self.tokens.iter_mut().filter(|token| {
+            if is_data {
+                matches!(token, Token::ElementDataValue(..))
+            }
+            else {
+                matches!(token, Token::PropertyValue(..))
+            }
+        }).nth(index);
+        match value_token {
+            Some(Token::ElementDataValue(value) | Token::PropertyValue(value)) => {
+                *value = Some(String::from(new_value));
+            },
+            _ => {
+                self.force_update_tokens();
+            }
+        }
+    }
+
+    // Source position of the element header.
+    pub fn span(&self) -> Option<&Span> {
+        let index = self.tokens.iter().position(|token| matches!(token, Token::BracketLeft))?;
+        self.spans.get(index)
+    }
+
+    // Source position of a property's value.
+    pub fn property_span(&self, property_name:&str) -> Option<&Span> {
+        let index = self.properties.iter().position(|prop| prop.0 == property_name)?;
+        let token_index = self.tokens.iter().enumerate().filter(|(_, token)| matches!(token, Token::PropertyValue(..))).nth(index)?.0;
+        self.spans.get(token_index)
+    }
+
+    pub fn get_data_variant(&self, data_name:&str) -> Result<Variant, LookupError> {
+        for data in self.element_data.iter() {
+            if data.0 == data_name {
+                return data.value().map_err(LookupError::InvalidValue);
+            }
+        }
+        Err(LookupError::NotFound(String::from(data_name)))
+    }
+
+    // The lookups below predate `LookupError` and keep reporting a missing name or index as `Err(())`.
+    #[allow(clippy::result_unit_err)]
     pub fn get_data_value(&self, data_name:&str) -> Result<String, ()> {
         for data in self.element_data.iter() {
             if data.0 == data_name {
@@ -78,49 +195,215 @@ impl Element {
         Err(())
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn update_data(&mut self, data_name:&str, new_value:&str) -> Result<(), ()> {
-        let mut found = false;
-        self.element_data = self.element_data.iter().map(|data| { 
-            if data.0 == data_name {
-                found = true;
-                return ElementData(data.0.clone(), String::from(new_value));
+        match self.element_data.iter().position(|data| data.0 == data_name) {
+            Some(index) => {
+                self.update_data_by_index(index, new_value)
+            },
+            None => {
+                Err(())
             }
-            data.clone()
-        }).collect::<Vec<ElementData>>();
-        if found {
-            self.force_update_tokens();
-            return Ok(());
         }
-        Err(())
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn update_data_by_index(&mut self, index:usize, new_value:&str) -> Result<(), ()> {
         if let Some(data) = self.element_data.get_mut(index) {
             data.1 = String::from(new_value);
-            self.force_update_tokens();
+            self.update_value_token(index, true, new_value);
             return Ok(());
         }
         Err(())
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn update_property(&mut self, property_name:&str, new_value:&str) -> Result<(), ()> {
-        let mut found = false;
-        for (index, prop) in self.properties.iter().enumerate() {
-            if prop.0 == property_name {
-                found = true;
-                self.properties[index] = Property(prop.0.clone(), String::from(new_value));
-                break;
-            }
-        }
-        if found {
-            self.force_update_tokens();
+        if let Some(index) = self.properties.iter().position(|prop| prop.0 == property_name) {
+            self.properties[index].1 = String::from(new_value);
+            self.update_value_token(index, false, new_value);
             return Ok(());
         }
         Err(())
     }
 
+    // Replaces `range` of the token stream, keeping `spans` lined up with the tokens that stay.
+    fn splice_tokens(&mut self, range:Range<usize>, tokens:Vec<Token>) {
+        if self.spans.len() == self.tokens.len() {
+            self.spans.splice(range.clone(), tokens.iter().map(|_| Span::default()));
+        }
+        self.tokens.splice(range, tokens);
+    }
+
+    // Whether the element's lines end with `\r\n`, which is tokenized as a `\r` whitespace before the `NewLine`.
+    fn uses_crlf(&self) -> bool {
+        match self.tokens.iter().position(|token| *token == Token::NewLine) {
+            Some(index) => index > 0 && matches!(&self.tokens[index - 1], Token::Whitespace(whitespace) if whitespace.ends_with('\r')),
+            None => false,
+        }
+    }
+
+    // Ends every line with `\r\n`, for elements added to a file that uses them.
+    pub(crate) fn use_crlf(&mut self) {
+        if add_carriage_returns(&mut self.tokens) {
+            self.spans.clear();
+        }
+    }
+
+    fn nth_token(&self, index:usize, is_kind:fn(&Token) -> bool) -> Option<usize> {
+        self.tokens.iter().enumerate().filter(|(_, token)| is_kind(token)).nth(index).map(|(token_index, _)| token_index)
+    }
+
+    // Start of the line holding the token at `index`, including its indentation.
+    fn line_start(&self, index:usize) -> usize {
+        let mut start = index;
+        while start > 0 && matches!(self.tokens[start - 1], Token::Whitespace(..)) {
+            start -= 1;
+        }
+        start
+    }
+
+    // Just past the line break ending the line that holds the token at `index`.
+    fn line_end(&self, index:usize) -> usize {
+        match self.tokens[index..].iter().position(|token| *token == Token::NewLine) {
+            Some(offset) => index + offset + 1,
+            None => self.tokens.len(),
+        }
+    }
+
+    // Updates the property, or adds it after the others if the element doesn't have it yet.
+    pub fn set_property(&mut self, property_name:&str, new_value:&str) {
+        if self.update_property(property_name, new_value).is_err() {
+            let _ = self.insert_property_at(self.properties.len(), property_name, new_value);
+        }
+    }
+
+    // Adds a property on its own line before the `index`-th one, `Err` if it already exists or `index` is past the end.
+    pub fn insert_property_at(&mut self, index:usize, property_name:&str, value:&str) -> Result<(), LookupError> {
+        if self.properties.iter().any(|prop| prop.0 == property_name) {
+            return Err(LookupError::AlreadyExists(String::from(property_name)));
+        }
+        if index > self.properties.len() {
+            return Err(LookupError::OutOfRange(index));
+        }
+        let property = Property(String::from(property_name), String::from(value));
+        let crlf = self.uses_crlf();
+        let mut tokens = property.to_tokens().to_vec();
+        tokens.push(Token::NewLine);
+        let position = if index < self.properties.len() {
+            self.nth_token(index, |token| matches!(token, Token::PropertyName(..))).map(|token_index| self.line_start(token_index))
+        }
+        else {
+            // After the line of the last property, or of the header.
+            let last_line = match self.properties.len() {
+                0 => self.tokens.iter().position(|token| *token == Token::BracketRight),
+                count => self.nth_token(count - 1, |token| matches!(token, Token::PropertyValue(..))),
+            };
+            last_line.map(|token_index| self.line_end(token_index))
+        };
+        self.properties.insert(index, property);
+        match position {
+            Some(position) => {
+                if position == self.tokens.len() && self.tokens.last() != Some(&Token::NewLine) {
+                    // The file ended without a line break, keep it that way.
+                    tokens.pop();
+                    tokens.insert(0, Token::NewLine);
+                }
+                if crlf {
+                    add_carriage_returns(&mut tokens);
+                }
+                self.splice_tokens(position..position, tokens);
+            },
+            None => {
+                self.force_update_tokens();
+            }
+        }
+        Ok(())
+    }
+
+    // Removes the property together with its line.
+    pub fn remove_property(&mut self, property_name:&str) -> Result<Property, LookupError> {
+        let index = self.properties.iter().position(|prop| prop.0 == property_name).ok_or_else(|| LookupError::NotFound(String::from(property_name)))?;
+        if let Some(token_index) = self.nth_token(index, |token| matches!(token, Token::PropertyName(..))) {
+            let mut start = self.line_start(token_index);
+            let end = self.line_end(token_index);
+            if end == self.tokens.len() && self.tokens.last() != Some(&Token::NewLine) && start > 0 && self.tokens[start - 1] == Token::NewLine {
+                start -= 1;
+            }
+            self.splice_tokens(start..end, Vec::new());
+        }
+        Ok(self.properties.remove(index))
+    }
+
+    // Renames the property in place, `Err` if it doesn't exist or `new_name` is already taken.
+    pub fn rename_property(&mut self, property_name:&str, new_name:&str) -> Result<(), LookupError> {
+        let index = self.properties.iter().position(|prop| prop.0 == property_name).ok_or_else(|| LookupError::NotFound(String::from(property_name)))?;
+        if self.properties.iter().any(|prop| prop.0 == new_name) {
+            return Err(LookupError::AlreadyExists(String::from(new_name)));
+        }
+        self.properties[index].0 = String::from(new_name);
+        match self.nth_token(index, |token| matches!(token, Token::PropertyName(..))) {
+            Some(token_index) => {
+                self.tokens[token_index] = Token::PropertyName(Some(String::from(new_name)));
+            },
+            None => {
+                self.force_update_tokens();
+            }
+        }
+        Ok(())
+    }
+
+    // Updates the header data, or adds it before the closing `]` if the element doesn't have it yet.
+    pub fn set_data(&mut self, data_name:&str, new_value:&str) {
+        if self.update_data(data_name, new_value).is_err() {
+            let _ = self.insert_data_at(self.element_data.len(), data_name, new_value);
+        }
+    }
+
+    // Adds header data before the `index`-th item, `Err` if it already exists or `index` is past the end.
+    pub fn insert_data_at(&mut self, index:usize, data_name:&str, value:&str) -> Result<(), LookupError> {
+        if self.element_data.iter().any(|data| data.0 == data_name) {
+            return Err(LookupError::AlreadyExists(String::from(data_name)));
+        }
+        if index > self.element_data.len() {
+            return Err(LookupError::OutOfRange(index));
+        }
+        let data = ElementData(String::from(data_name), String::from(value));
+        let mut tokens = data.to_tokens().to_vec();
+        let position = if index < self.element_data.len() {
+            tokens.push(Token::Whitespace(String::from(' ')));
+            self.nth_token(index, |token| matches!(token, Token::ElementDataName(..)))
+        }
+        else {
+            tokens.insert(0, Token::Whitespace(String::from(' ')));
+            self.tokens.iter().position(|token| *token == Token::BracketRight)
+        };
+        self.element_data.insert(index, data);
+        match position {
+            Some(position) => {
+                self.splice_tokens(position..position, tokens);
+            },
+            None => {
+                self.force_update_tokens();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_data(&mut self, data_name:&str) -> Result<ElementData, LookupError> {
+        let index = self.element_data.iter().position(|data| data.0 == data_name).ok_or_else(|| LookupError::NotFound(String::from(data_name)))?;
+        let name_index = self.nth_token(index, |token| matches!(token, Token::ElementDataName(..)));
+        let value_index = self.nth_token(index, |token| matches!(token, Token::ElementDataValue(..)));
+        if let (Some(mut start), Some(end)) = (name_index, value_index) {
+            if start > 0 && matches!(self.tokens[start - 1], Token::Whitespace(..)) {
+                start -= 1;
+            }
+            self.splice_tokens(start..end + 1, Vec::new());
+        }
+        Ok(self.element_data.remove(index))
+    }
+
     pub fn get_property_value(&self, property_name:&str) -> Result<String, NodePathError> {
-        println!("{:?}", self.properties);
         for prop in self.properties.iter() {
             if prop.0 == property_name {
                 return Ok(prop.1.clone());
@@ -128,12 +411,63 @@ impl Element {
         }
         Err(NodePathError::PropertyNotFound)
     }
+
+    pub fn get_property_variant(&self, property_name:&str) -> Result<Variant, NodePathError> {
+        for prop in self.properties.iter() {
+            if prop.0 == property_name {
+                return prop.value().map_err(NodePathError::InvalidValue);
+            }
+        }
+        Err(NodePathError::PropertyNotFound)
+    }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(PartialEq, Debug, Clone)]
 pub enum ElementType {
     UNKOWN,
     SCENE_DATA,
     RESOURCE,
     NODE,
-}
\ No newline at end of file
+    MAIN_RESOURCE, // The `[resource]` section of a `.tres` file.
+    CONNECTION,
+}
+
+impl ElementType {
+    // The kind of section a `[element_name ...]` header starts.
+    pub fn from_element_name(element_name:&str) -> Self {
+        match element_name {
+            "gd_scene" | "gd_resource" => ElementType::SCENE_DATA,
+            "ext_resource" | "sub_resource" => ElementType::RESOURCE,
+            "node" => ElementType::NODE,
+            "resource" => ElementType::MAIN_RESOURCE,
+            "connection" => ElementType::CONNECTION,
+            _ => ElementType::UNKOWN,
+        }
+    }
+}
+
+// Puts a `\r` before every `NewLine` that doesn't have one, `true` if any was added.
+fn add_carriage_returns(tokens:&mut Vec<Token>) -> bool {
+    let mut added = false;
+    let mut index = 0;
+    while index < tokens.len() {
+        if tokens[index] == Token::NewLine {
+            match index.checked_sub(1).map(|previous| &mut tokens[previous]) {
+                Some(Token::Whitespace(whitespace)) if whitespace.ends_with('\r') => {},
+                Some(Token::Whitespace(whitespace)) => {
+                    whitespace.push('\r');
+                    added = true;
+                },
+                _ => {
+                    tokens.insert(index, Token::Whitespace(String::from('\r')));
+                    index += 1;
+                    added = true;
+                },
+            }
+        }
+        index += 1;
+    }
+    added
+}
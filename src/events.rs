@@ -0,0 +1,238 @@
+// A pull parser: `EventReader` reads a file a line at a time and yields what it finds as `Event`s, dropping the
+// tokens behind them. Memory stays bounded by the longest value instead of growing with the file, which is what
+// huge generated scenes need. `Scene` and `Resource` are built on top of it, keeping the tokens of each section.
+
+use std::{collections::VecDeque, io::BufRead, mem};
+
+use crate::element::{Element, ElementData, ElementType, ExpectedType, Property};
+use crate::tokenizer::{Location, Span, Token, Tokenizer, TokenizerError};
+
+// Properties above the first header, like `config_version=5` in `project.godot`, come before any `SectionStart`
+// and a `SectionEnd` closes them, as a section without a header. So does the end of a file without headers.
+// `Scene` and `Resource` keep them in an element of their own, see `Element::is_preamble`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    SectionStart(String), // 0: Element name, e.g. `node`
+    Data(ElementData), // A `name=value` pair of the section header.
+    Property(Property), // A `name = value` line below the header.
+    SectionEnd,
+}
+
+pub struct EventReader<R:BufRead> {
+    reader:R,
+    tokenizer:Tokenizer,
+    line:String,
+    recovering:bool,
+    errors:VecDeque<TokenizerError>,
+    finished:bool,
+    in_section:bool,
+    preamble:bool, // Tokens were read above the first header.
+    preamble_properties:bool, // Properties were, which makes them a section of their own.
+    name:Option<String>, // Name of the data or property whose value comes next.
+    consumed:usize, // Tokens already turned into events.
+    settled:usize, // Tokens that won't change anymore.
+    // Kept tokens stay in the tokenizer until `take_section` collects them, see `read_elements`.
+    keep_tokens:bool,
+    section_end:usize,
+    cursor:Span,
+}
+
+impl<R:BufRead> EventReader<R> {
+    pub fn new(reader:R) -> Self {
+        EventReader::named(reader, None)
+    }
+
+    // Like `new`, with `file_name` reported in error locations.
+    pub fn named(reader:R, file_name:Option<&str>) -> Self {
+        EventReader {
+            reader,
+            tokenizer: Tokenizer::new(file_name),
+            line: String::new(),
+            recovering: false,
+            errors: VecDeque::new(),
+            finished: false,
+            in_section: false,
+            preamble: false,
+            preamble_properties: false,
+            name: None,
+            consumed: 0,
+            settled: 0,
+            keep_tokens: false,
+            section_end: 0,
+            cursor: Span::start_of_file(),
+        }
+    }
+
+    // Keeps going after an error like `Tokenizer::tokenize_recovering`: the broken section is skipped and the
+    // error is yielded in its place. Other readers end after their first error.
+    pub fn recovering(reader:R, file_name:Option<&str>) -> Self {
+        EventReader { recovering: true, ..EventReader::named(reader, file_name) }
+    }
+
+    // The character the reader stopped at, to point at the section or property an event came from.
+    pub fn location(&self) -> Location {
+        self.tokenizer.location()
+    }
+
+    // Name and value text of the token, moved out unless the tokens are kept.
+    fn text(&mut self, index:usize) -> Option<String> {
+        match &mut self.tokenizer.tokens[index] {
+            Token::ElementName(text) | Token::ElementDataName(text) | Token::ElementDataValue(text) | Token::PropertyName(text) | Token::PropertyValue(text) => {
+                let text = text.as_mut()?;
+                Some(if self.keep_tokens { text.clone() } else { mem::take(text) })
+            },
+            _ => None,
+        }
+    }
+
+    fn missing(&self, expected:ExpectedType) -> TokenizerError {
+        TokenizerError::NotFound(expected, self.tokenizer.location())
+    }
+
+    fn event_at(&mut self, index:usize) -> Result<Option<Event>, TokenizerError> {
+        if !self.in_section && self.tokenizer.tokens[index] != Token::BracketLeft {
+            self.preamble = true;
+        }
+        let event = match &self.tokenizer.tokens[index] {
+            Token::BracketLeft => {
+                // A new header closes the previous section, properties belong to the header above them.
+                self.preamble = false;
+                let closes_preamble = mem::take(&mut self.preamble_properties);
+                if !mem::replace(&mut self.in_section, true) && !closes_preamble {
+                    return Ok(None);
+                }
+                self.section_end = index;
+                Event::SectionEnd
+            },
+            Token::ElementName(..) => Event::SectionStart(self.text(index).ok_or_else(|| self.missing(ExpectedType::ElementName))?),
+            Token::ElementDataName(..) | Token::PropertyName(..) => {
+                let expected = if matches!(self.tokenizer.tokens[index], Token::PropertyName(..)) { ExpectedType::PropertyName } else { ExpectedType::ElementDataName };
+                self.name = Some(self.text(index).ok_or_else(|| self.missing(expected))?);
+                return Ok(None);
+            },
+            Token::ElementDataValue(..) => {
+                let value = self.text(index).ok_or_else(|| self.missing(ExpectedType::ElementDataValue))?;
+                let name = self.name.take().ok_or_else(|| TokenizerError::UnexpectedErr(self.tokenizer.location()))?;
+                Event::Data(ElementData(name, value))
+            },
+            Token::PropertyValue(..) => {
+                let value = self.text(index).ok_or_else(|| self.missing(ExpectedType::PropertyValue))?;
+                let name = self.name.take().ok_or_else(|| TokenizerError::UnexpectedErr(self.tokenizer.location()))?;
+                self.preamble_properties |= !self.in_section;
+                Event::Property(Property(name, value))
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+
+    // Reads lines until there are new tokens to turn into events, or the file ends.
+    fn read_more(&mut self) -> Result<(), TokenizerError> {
+        if !self.keep_tokens {
+            self.tokenizer.tokens.drain(..self.consumed);
+            self.settled -= self.consumed;
+            self.consumed = 0;
+        }
+        let mut errors = Vec::new();
+        while self.settled == self.consumed && !self.finished && errors.is_empty() {
+            let recovered = if self.recovering { Some(&mut errors) } else { None };
+            match self.tokenizer.read_line(&mut self.reader, &mut self.line, recovered) {
+                Ok(true) => {
+                    self.settled = self.tokenizer.settled_len();
+                },
+                Ok(false) => {
+                    self.finished = true;
+                    self.settled = self.tokenizer.tokens.len();
+                },
+                Err(error) => {
+                    self.finished = true;
+                    self.settled = self.consumed;
+                    return Err(error);
+                },
+            }
+        }
+        self.errors.extend(errors);
+        Ok(())
+    }
+
+    // Tokens of the section the last `SectionEnd` closed, with their spans. Comments above the first header come
+    // with the first section, like in `Tokenizer::elements_from_tokens`.
+    fn take_section(&mut self) -> (Vec<Token>, Vec<Span>) {
+        let tokens = self.tokenizer.tokens.drain(..self.section_end).collect::<Vec<Token>>();
+        self.consumed -= self.section_end;
+        self.settled -= self.section_end;
+        self.section_end = 0;
+        let spans = tokens.iter().map(|token| self.cursor.advance(&token.to_string())).collect();
+        (tokens, spans)
+    }
+}
+
+impl<R:BufRead> Iterator for EventReader<R> {
+    type Item = Result<Event, TokenizerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(error) = self.errors.pop_front() {
+                return Some(Err(error));
+            }
+            while self.consumed < self.settled {
+                let index = self.consumed;
+                self.consumed += 1;
+                match self.event_at(index) {
+                    Ok(Some(event)) => return Some(Ok(event)),
+                    Ok(None) => {},
+                    Err(error) if self.recovering => self.errors.push_back(error),
+                    Err(error) => {
+                        self.finished = true;
+                        self.settled = self.consumed;
+                        return Some(Err(error));
+                    },
+                }
+                if !self.errors.is_empty() {
+                    break;
+                }
+            }
+            if !self.errors.is_empty() {
+                continue;
+            }
+            if self.finished {
+                if mem::replace(&mut self.in_section, false) || mem::take(&mut self.preamble) {
+                    self.section_end = self.tokenizer.tokens.len();
+                    return Some(Ok(Event::SectionEnd));
+                }
+                return None;
+            }
+            if let Err(error) = self.read_more() {
+                self.in_section = false;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+// Elements of every section `events` yields, each with the tokens it was read from so it writes back exactly.
+// Strict readers stop at their first error, recovering ones leave out what they skipped. The tokenizer holds
+// the reader's state, its tokens are all in the elements.
+pub(crate) fn read_elements<R:BufRead>(mut events:EventReader<R>) -> (Vec<Element>, Tokenizer, Vec<TokenizerError>) {
+    events.keep_tokens = true;
+    let mut elements = Vec::new();
+    let mut errors = Vec::new();
+    let mut current = Element::empty();
+    while let Some(event) = events.next() {
+        match event {
+            Ok(Event::SectionStart(name)) => {
+                current.element_type = ElementType::from_element_name(&name);
+                current.element_name = name;
+            },
+            Ok(Event::Data(data)) => current.element_data.push(data),
+            Ok(Event::Property(property)) => current.properties.push(property),
+            Ok(Event::SectionEnd) => {
+                (current.tokens, current.spans) = events.take_section();
+                elements.push(mem::replace(&mut current, Element::empty()));
+            },
+            Err(error) => errors.push(error),
+        }
+    }
+    events.tokenizer.tokens.clear();
+    (elements, events.tokenizer, errors)
+}
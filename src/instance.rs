@@ -0,0 +1,59 @@
+use std::{collections::HashMap, io, path::{Path, PathBuf}, rc::Rc};
+
+use crate::scene::{Scene, SceneError};
+
+// Loads the scenes that `instance=` nodes and inherited scenes refer to.
+pub trait SceneSource {
+    fn load_scene(&mut self, res_path:&str) -> Result<Rc<Scene>, SceneError>;
+}
+
+// Scenes already in memory, keyed by `res://` path.
+impl SceneSource for HashMap<String, Rc<Scene>> {
+    fn load_scene(&mut self, res_path:&str) -> Result<Rc<Scene>, SceneError> {
+        match self.get(res_path) {
+            Some(scene) => Ok(scene.clone()),
+            None => Err(SceneError::LoadFailed(String::from(res_path), io::Error::from(io::ErrorKind::NotFound))),
+        }
+    }
+}
+
+pub(crate) fn res_to_file(root:&Path, res_path:&str) -> Option<PathBuf> {
+    let relative = res_path.strip_prefix("res://")?;
+    if relative.split('/').any(|segment| segment == "..") {
+        return None;
+    }
+    Some(root.join(relative))
+}
+
+// Reads scenes from a project directory, the one `res://` stands for. Each file is loaded once.
+#[derive(Debug)]
+pub struct ResDirectory {
+    root:PathBuf,
+    cache:HashMap<String, Rc<Scene>>,
+}
+
+impl ResDirectory {
+    pub fn new(root:impl Into<PathBuf>) -> Self {
+        ResDirectory { root: root.into(), cache: HashMap::new() }
+    }
+
+    // Filesystem path of a `res://` path, `None` for paths outside the project.
+    pub fn resolve(&self, res_path:&str) -> Option<PathBuf> {
+        res_to_file(&self.root, res_path)
+    }
+}
+
+impl SceneSource for ResDirectory {
+    fn load_scene(&mut self, res_path:&str) -> Result<Rc<Scene>, SceneError> {
+        if let Some(scene) = self.cache.get(res_path) {
+            return Ok(scene.clone());
+        }
+        let file_path = match self.resolve(res_path) {
+            Some(file_path) => file_path,
+            None => return Err(SceneError::LoadFailed(String::from(res_path), io::Error::from(io::ErrorKind::InvalidInput))),
+        };
+        let scene = Rc::new(Scene::from_tscn_file(&file_path.to_string_lossy())?);
+        self.cache.insert(String::from(res_path), scene.clone());
+        Ok(scene)
+    }
+}
@@ -0,0 +1,596 @@
+// A JSON form of scenes for tools that don't read `.tscn`. `Scene::to_json` writes it and `Scene::from_json`
+// reads it back, the two round-trip through the `Scene` model. Schema version 1:
+//
+// {
+//   "schema": 1,
+//   "header": {"format": V, "uid": V},
+//   "ext_resources": [{"id": "1_abc", "type": "Texture2D", "uid": "uid://...", "path": "res://icon.svg"}],
+//   "sub_resources": [{"id": "RectangleShape2D_x", "type": "RectangleShape2D", "properties": {"size": V}}],
+//   "nodes": [{"name": "Root", "type": "Node2D", "data": {"groups": V}, "properties": {}, "children": [...]}],
+//   "connections": [{"signal": "pressed", "from": "Button", "to": ".", "method": "_on_pressed", "data": {"flags": V}}],
+//   "editable": [{"path": "Enemy"}],
+//   "other": [{"section": "name", "data": {}, "properties": {}}]
+// }
+//
+// "header" is the data of `[gd_scene]`, null without one. Each section lists its `key=value` pairs in "data"
+// and its `key = value` lines in "properties", both left out when empty. The fields shown as plain strings are
+// only there when the value is a string (`id` is a number for Godot 3 integer ids), otherwise they stay in
+// "data". Nodes nest in "children" and leave out `parent`, nodes whose parent isn't in the scene come after the
+// root in "nodes" and keep it. Sections this schema doesn't know go to "other", properties above the first
+// header are the one with an empty "section" name.
+//
+// V is a value tagged with its Godot type, {"type": "Vector2", "value": [1.0, 2.0]}:
+// - Nil is null, bool, int and float are JSON values. Floats that aren't finite are "inf", "-inf" and "nan".
+// - String, StringName, NodePath and Resource are strings.
+// - Vectors, Rect2, Quaternion, Plane, AABB, Basis, Transform2D, Transform3D, Projection and Color are an array
+//   of their components, in the order Godot writes them.
+// - Array is an array of V, typed arrays add "element_type" with the type as written, e.g. "int".
+// - Dictionary is an array of [key V, value V] pairs, typed dictionaries add "entry_types", e.g. "String, int".
+// - Packed arrays are arrays of numbers or strings, vector and color arrays hold an array per element.
+// - ExtResource and SubResource are the id, as a string or a number.
+// - Object adds "class", its value is an object of V.
+// - Values this crate can't parse are {"type": "raw", "value": "<text as written>"}.
+
+use std::{collections::HashMap, fmt};
+
+use crate::element::{Element, ElementData, Property};
+use crate::json::{self, Value};
+use crate::scene::{self, Scene, SceneError};
+use crate::tree::{data_string, join_path};
+use crate::variant::{Format, ResourceId, Variant};
+use crate::writer;
+
+pub const SCHEMA_VERSION:i64 = 1;
+
+#[derive(Debug)]
+pub enum JsonError {
+    Syntax(String, usize), // 1: Byte offset
+    Schema(String),
+    Scene(SceneError), // The sections don't read back as a scene, e.g. a property name with spaces.
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Syntax(message, offset) => write!(f, "invalid JSON at offset {}: {}", offset, message),
+            JsonError::Schema(message) => write!(f, "{}", message),
+            JsonError::Scene(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+// Section data kept as plain JSON fields instead of typed values.
+const EXT_RESOURCE_FIELDS:&[&str] = &["id", "type", "uid", "path"];
+const SUB_RESOURCE_FIELDS:&[&str] = &["id", "type"];
+const NODE_FIELDS:&[&str] = &["name", "type"];
+const CONNECTION_FIELDS:&[&str] = &["signal", "from", "to", "method"];
+const EDITABLE_FIELDS:&[&str] = &["path"];
+
+fn int(value:i64) -> Value {
+    Value::Number(value.to_string())
+}
+
+fn float(value:f64) -> Value {
+    match value {
+        value if value.is_nan() => Value::String(String::from("nan")),
+        value if value.is_infinite() => Value::String(String::from(if value > 0.0 { "inf" } else { "-inf" })),
+        value => Value::Number(format!("{:?}", value)),
+    }
+}
+
+fn floats(values:&[f64]) -> Value {
+    Value::Array(values.iter().map(|value| float(*value)).collect())
+}
+
+fn resource_id(id:&ResourceId) -> Value {
+    match id {
+        ResourceId::Int(int) => Value::Number(int.to_string()),
+        ResourceId::String(string) => Value::String(string.clone()),
+    }
+}
+
+pub(crate) fn typed(variant:&Variant) -> Value {
+    let mut members = vec![(String::from("type"), Value::String(String::from(variant.type_name())))];
+    let value = match variant {
+        Variant::Nil => Value::Null,
+        Variant::Bool(bool) => Value::Bool(*bool),
+        Variant::Int(value) => int(*value),
+        Variant::Float(value) => float(*value),
+        Variant::String(string) | Variant::StringName(string) | Variant::NodePath(string) | Variant::Resource(string) => {
+            Value::String(string.clone())
+        },
+        Variant::Vector2(x, y) => floats(&[*x, *y]),
+        Variant::Vector2i(x, y) => Value::Array(vec![int(*x), int(*y)]),
+        Variant::Vector3(x, y, z) => floats(&[*x, *y, *z]),
+        Variant::Vector3i(x, y, z) => Value::Array(vec![int(*x), int(*y), int(*z)]),
+        Variant::Vector4(x, y, z, w) | Variant::Rect2(x, y, z, w) | Variant::Quaternion(x, y, z, w) | Variant::Plane(x, y, z, w) | Variant::Color(x, y, z, w) => {
+            floats(&[*x, *y, *z, *w])
+        },
+        Variant::Vector4i(x, y, z, w) | Variant::Rect2i(x, y, z, w) => Value::Array(vec![int(*x), int(*y), int(*z), int(*w)]),
+        Variant::Transform2D(values) | Variant::Aabb(values) => floats(values),
+        Variant::Transform3D(values) => floats(values),
+        Variant::Basis(values) => floats(values),
+        Variant::Projection(values) => floats(values),
+        Variant::Array(items) => Value::Array(items.iter().map(typed).collect()),
+        Variant::TypedArray(element_type, items) => {
+            members.push((String::from("element_type"), Value::String(element_type.clone())));
+            Value::Array(items.iter().map(typed).collect())
+        },
+        Variant::Dictionary(entries) => Value::Array(entries.iter().map(|(key, value)| Value::Array(vec![typed(key), typed(value)])).collect()),
+        Variant::TypedDictionary(entry_types, entries) => {
+            members.push((String::from("entry_types"), Value::String(entry_types.clone())));
+            Value::Array(entries.iter().map(|(key, value)| Value::Array(vec![typed(key), typed(value)])).collect())
+        },
+        Variant::PackedByteArray(bytes) => Value::Array(bytes.iter().map(|byte| int(*byte as i64)).collect()),
+        Variant::PackedInt32Array(ints) => Value::Array(ints.iter().map(|value| int(*value as i64)).collect()),
+        Variant::PackedInt64Array(ints) => Value::Array(ints.iter().map(|value| int(*value)).collect()),
+        Variant::PackedFloat32Array(values) => Value::Array(values.iter().map(|value| {
+            if value.is_finite() { Value::Number(format!("{:?}", value)) } else { float(*value as f64) }
+        }).collect()),
+        Variant::PackedFloat64Array(values) => floats(values),
+        Variant::PackedStringArray(strings) => Value::Array(strings.iter().map(|string| Value::String(string.clone())).collect()),
+        Variant::PackedVector2Array(vectors) => Value::Array(vectors.iter().map(|(x, y)| floats(&[*x, *y])).collect()),
+        Variant::PackedVector3Array(vectors) => Value::Array(vectors.iter().map(|(x, y, z)| floats(&[*x, *y, *z])).collect()),
+        Variant::PackedVector4Array(vectors) | Variant::PackedColorArray(vectors) => {
+            Value::Array(vectors.iter().map(|(x, y, z, w)| floats(&[*x, *y, *z, *w])).collect())
+        },
+        Variant::ExtResource(id) | Variant::SubResource(id) => resource_id(id),
+        Variant::Object(class, properties) => {
+            members.push((String::from("class"), Value::String(class.clone())));
+            Value::Object(properties.iter().map(|(name, value)| (name.clone(), typed(value))).collect())
+        },
+    };
+    members.push((String::from("value"), value));
+    Value::Object(members)
+}
+
+fn typed_text(text:&str) -> Value {
+    match Variant::parse(text) {
+        Ok(variant) => typed(&variant),
+        Err(_) => Value::Object(vec![(String::from("type"), Value::String(String::from("raw"))), (String::from("value"), Value::String(String::from(text.trim())))]),
+    }
+}
+
+// The value of a data field that can be written as a plain JSON field.
+fn field(data:&ElementData) -> Option<Value> {
+    match (&data.0[..], data.value()) {
+        (_, Ok(Variant::String(string))) => Some(Value::String(string)),
+        ("id", Ok(Variant::Int(id))) => Some(int(id)),
+        _ => None,
+    }
+}
+
+fn section(element:&Element, fields:&[&str], skip:&[&str]) -> Vec<(String, Value)> {
+    let mut members = Vec::new();
+    for name in fields.iter() {
+        if let Some(value) = element.element_data.iter().find(|data| data.0 == *name).and_then(field) {
+            members.push((String::from(*name), value));
+        }
+    }
+    let data = element.element_data.iter()
+        .filter(|data| !skip.contains(&&data.0[..]) && !members.iter().any(|(name, _)| *name == data.0))
+        .map(|data| (data.0.clone(), typed_text(&data.1)))
+        .collect::<Vec<(String, Value)>>();
+    if !data.is_empty() {
+        members.push((String::from("data"), Value::Object(data)));
+    }
+    if !element.properties.is_empty() {
+        let properties = element.properties.iter().map(|property| (property.0.clone(), typed_text(&property.1))).collect();
+        members.push((String::from("properties"), Value::Object(properties)));
+    }
+    members
+}
+
+// A node while the tree is built, children are indices into the list of all nodes.
+struct TreeNode {
+    members:Vec<(String, Value)>,
+    children:Vec<usize>,
+}
+
+fn nest(nodes:&mut Vec<Option<TreeNode>>, index:usize) -> Value {
+    let Some(mut node) = nodes[index].take() else {
+        return Value::Null;
+    };
+    if !node.children.is_empty() {
+        let children = node.children.iter().map(|child| nest(nodes, *child)).collect();
+        node.members.push((String::from("children"), Value::Array(children)));
+    }
+    Value::Object(node.members)
+}
+
+fn node_tree(elements:&[&Element]) -> Vec<Value> {
+    let mut nodes:Vec<Option<TreeNode>> = Vec::new();
+    let mut paths:HashMap<String, usize> = HashMap::new();
+    let mut top = Vec::new();
+    for element in elements.iter() {
+        let name = data_string(element, "name");
+        let parent = match element.get_data_variant("parent") {
+            Ok(Variant::String(parent)) => Some(parent),
+            _ => None,
+        };
+        let attached_to = parent.as_ref().and_then(|parent| paths.get(parent).copied());
+        let skip:&[&str] = if attached_to.is_some() { &["parent"] } else { &[] };
+        let index = nodes.len();
+        nodes.push(Some(TreeNode { members: section(element, NODE_FIELDS, skip), children: Vec::new() }));
+
+        let path = match (&parent, &name, element.element_data.iter().any(|data| data.0 == "parent")) {
+            (None, _, false) if top.is_empty() => Some(String::from(".")),
+            (Some(parent), Some(name), _) => Some(join_path(parent, name)),
+            _ => None,
+        };
+        if let Some(path) = path {
+            paths.entry(path).or_insert(index);
+        }
+        match attached_to {
+            Some(parent) => nodes[parent].as_mut().expect("parents come before their children").children.push(index),
+            None => top.push(index),
+        }
+    }
+    top.iter().map(|index| nest(&mut nodes, *index)).collect()
+}
+
+pub fn to_json(scene:&Scene) -> String {
+    let mut header = Value::Null;
+    let mut ext_resources = Vec::new();
+    let mut sub_resources = Vec::new();
+    let mut nodes = Vec::new();
+    let mut connections = Vec::new();
+    let mut editable = Vec::new();
+    let mut other = Vec::new();
+    for element in scene.elements.iter() {
+        match &element.element_name[..] {
+            "gd_scene" if header == Value::Null && element.properties.is_empty() => {
+                header = Value::Object(element.element_data.iter().map(|data| (data.0.clone(), typed_text(&data.1))).collect());
+            },
+            "ext_resource" => ext_resources.push(Value::Object(section(element, EXT_RESOURCE_FIELDS, &[]))),
+            "sub_resource" => sub_resources.push(Value::Object(section(element, SUB_RESOURCE_FIELDS, &[]))),
+            "node" => nodes.push(element),
+            "connection" => connections.push(Value::Object(section(element, CONNECTION_FIELDS, &[]))),
+            "editable" => editable.push(Value::Object(section(element, EDITABLE_FIELDS, &[]))),
+            name => {
+                let mut members = vec![(String::from("section"), Value::String(String::from(name)))];
+                members.append(&mut section(element, &[], &[]));
+                other.push(Value::Object(members));
+            },
+        }
+    }
+    let document = Value::Object(vec![
+        (String::from("schema"), int(SCHEMA_VERSION)),
+        (String::from("header"), header),
+        (String::from("ext_resources"), Value::Array(ext_resources)),
+        (String::from("sub_resources"), Value::Array(sub_resources)),
+        (String::from("nodes"), Value::Array(node_tree(&nodes))),
+        (String::from("connections"), Value::Array(connections)),
+        (String::from("editable"), Value::Array(editable)),
+        (String::from("other"), Value::Array(other)),
+    ]);
+    document.to_pretty(0) + "\n"
+}
+
+fn schema_error(at:&str, message:&str) -> JsonError {
+    JsonError::Schema(format!("{}: {}", at, message))
+}
+
+fn to_int(value:&Value) -> Option<i64> {
+    match value {
+        Value::Number(number) => number.parse::<i64>().ok(),
+        _ => None,
+    }
+}
+
+fn to_float(value:&Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.parse::<f64>().ok(),
+        Value::String(string) => match &string[..] {
+            "inf" => Some(f64::INFINITY),
+            "-inf" => Some(f64::NEG_INFINITY),
+            "nan" => Some(f64::NAN),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn to_float32(value:&Value) -> Option<f32> {
+    match value {
+        Value::Number(number) => number.parse::<f32>().ok(),
+        value => to_float(value).map(|value| value as f32),
+    }
+}
+
+fn to_string(value:&Value) -> Option<String> {
+    value.as_str().map(String::from)
+}
+
+fn components<const N:usize>(value:&Value) -> Option<[f64; N]> {
+    value.as_array()?.iter().map(to_float).collect::<Option<Vec<f64>>>()?.try_into().ok()
+}
+
+fn int_components<const N:usize>(value:&Value) -> Option<[i64; N]> {
+    value.as_array()?.iter().map(to_int).collect::<Option<Vec<i64>>>()?.try_into().ok()
+}
+
+fn each<T>(value:&Value, convert:impl Fn(&Value) -> Option<T>) -> Option<Vec<T>> {
+    value.as_array()?.iter().map(convert).collect()
+}
+
+fn to_resource_id(value:&Value) -> Option<ResourceId> {
+    match value {
+        Value::String(string) => Some(ResourceId::String(string.clone())),
+        value => to_int(value).map(ResourceId::Int),
+    }
+}
+
+fn entries(value:&Value) -> Option<Vec<(Variant, Variant)>> {
+    each(value, |entry| match entry.as_array()? {
+        [key, value] => Some((to_variant(key)?, to_variant(value)?)),
+        _ => None,
+    })
+}
+
+fn to_variant(typed:&Value) -> Option<Variant> {
+    let value = typed.get("value").unwrap_or(&Value::Null);
+    let variant = match typed.get("type")?.as_str()? {
+        "Nil" => Variant::Nil,
+        "bool" => match value {
+            Value::Bool(bool) => Variant::Bool(*bool),
+            _ => return None,
+        },
+        "int" => Variant::Int(to_int(value)?),
+        "float" => Variant::Float(to_float(value)?),
+        "String" => Variant::String(to_string(value)?),
+        "StringName" => Variant::StringName(to_string(value)?),
+        "NodePath" => Variant::NodePath(to_string(value)?),
+        "Resource" => Variant::Resource(to_string(value)?),
+        "Vector2" => {
+            let [x, y] = components(value)?;
+            Variant::Vector2(x, y)
+        },
+        "Vector2i" => {
+            let [x, y] = int_components(value)?;
+            Variant::Vector2i(x, y)
+        },
+        "Vector3" => {
+            let [x, y, z] = components(value)?;
+            Variant::Vector3(x, y, z)
+        },
+        "Vector3i" => {
+            let [x, y, z] = int_components(value)?;
+            Variant::Vector3i(x, y, z)
+        },
+        "Vector4" => {
+            let [x, y, z, w] = components(value)?;
+            Variant::Vector4(x, y, z, w)
+        },
+        "Vector4i" => {
+            let [x, y, z, w] = int_components(value)?;
+            Variant::Vector4i(x, y, z, w)
+        },
+        "Rect2" => {
+            let [x, y, width, height] = components(value)?;
+            Variant::Rect2(x, y, width, height)
+        },
+        "Rect2i" => {
+            let [x, y, width, height] = int_components(value)?;
+            Variant::Rect2i(x, y, width, height)
+        },
+        "Quaternion" => {
+            let [x, y, z, w] = components(value)?;
+            Variant::Quaternion(x, y, z, w)
+        },
+        "Plane" => {
+            let [x, y, z, d] = components(value)?;
+            Variant::Plane(x, y, z, d)
+        },
+        "Color" => {
+            let [r, g, b, a] = components(value)?;
+            Variant::Color(r, g, b, a)
+        },
+        "Transform2D" => Variant::Transform2D(components(value)?),
+        "Transform3D" => Variant::Transform3D(components(value)?),
+        "Basis" => Variant::Basis(components(value)?),
+        "AABB" => Variant::Aabb(components(value)?),
+        "Projection" => Variant::Projection(components(value)?),
+        "Array" => {
+            let items = each(value, to_variant)?;
+            match typed.get("element_type") {
+                Some(element_type) => Variant::TypedArray(to_string(element_type)?, items),
+                None => Variant::Array(items),
+            }
+        },
+        "Dictionary" => {
+            let entries = entries(value)?;
+            match typed.get("entry_types") {
+                Some(entry_types) => Variant::TypedDictionary(to_string(entry_types)?, entries),
+                None => Variant::Dictionary(entries),
+            }
+        },
+        "PackedByteArray" => Variant::PackedByteArray(each(value, |byte| u8::try_from(to_int(byte)?).ok())?),
+        "PackedInt32Array" => Variant::PackedInt32Array(each(value, |int| i32::try_from(to_int(int)?).ok())?),
+        "PackedInt64Array" => Variant::PackedInt64Array(each(value, to_int)?),
+        "PackedFloat32Array" => Variant::PackedFloat32Array(each(value, to_float32)?),
+        "PackedFloat64Array" => Variant::PackedFloat64Array(each(value, to_float)?),
+        "PackedStringArray" => Variant::PackedStringArray(each(value, to_string)?),
+        "PackedVector2Array" => Variant::PackedVector2Array(each(value, |vector| {
+            let [x, y] = components(vector)?;
+            Some((x, y))
+        })?),
+        "PackedVector3Array" => Variant::PackedVector3Array(each(value, |vector| {
+            let [x, y, z] = components(vector)?;
+            Some((x, y, z))
+        })?),
+        "PackedVector4Array" => Variant::PackedVector4Array(each(value, |vector| {
+            let [x, y, z, w] = components(vector)?;
+            Some((x, y, z, w))
+        })?),
+        "PackedColorArray" => Variant::PackedColorArray(each(value, |color| {
+            let [r, g, b, a] = components(color)?;
+            Some((r, g, b, a))
+        })?),
+        "ExtResource" => Variant::ExtResource(to_resource_id(value)?),
+        "SubResource" => Variant::SubResource(to_resource_id(value)?),
+        "Object" => {
+            let class = to_string(typed.get("class")?)?;
+            let properties = value.as_object()?.iter().map(|(name, value)| Some((name.clone(), to_variant(value)?))).collect::<Option<Vec<(String, Variant)>>>()?;
+            Variant::Object(class, properties)
+        },
+        _ => return None,
+    };
+    Some(variant)
+}
+
+// Reads the text of a value as it goes into the scene file.
+fn value_text(typed:&Value, format:Format, at:&str) -> Result<String, JsonError> {
+    if typed.get("type").and_then(Value::as_str) == Some("raw") {
+        return typed.get("value").and_then(to_string).ok_or_else(|| schema_error(at, "expected the text of a raw value"));
+    }
+    match to_variant(typed) {
+        Some(variant) => Ok(variant.to_text(format)),
+        None => {
+            let type_name = typed.get("type").and_then(Value::as_str).unwrap_or("typed");
+            Err(schema_error(at, &format!("invalid {} value", type_name)))
+        },
+    }
+}
+
+// Names go into the file unquoted, anything that would end them early can't be written back.
+fn check_name(name:&str, at:&str) -> Result<(), JsonError> {
+    if name.is_empty() || name.contains(|c:char| c.is_whitespace() || matches!(c, '=' | '[' | ']' | '"')) {
+        return Err(schema_error(at, &format!("invalid name {:?}", name)));
+    }
+    Ok(())
+}
+
+fn members<'a>(value:&'a Value, key:&str, at:&str) -> Result<&'a [(String, Value)], JsonError> {
+    match value.get(key) {
+        None => Ok(&[]),
+        Some(value) => value.as_object().ok_or_else(|| schema_error(at, &format!("expected \"{}\" to be an object", key))),
+    }
+}
+
+fn list<'a>(document:&'a Value, key:&str) -> Result<&'a [Value], JsonError> {
+    match document.get(key) {
+        None => Ok(&[]),
+        Some(value) => value.as_array().ok_or_else(|| JsonError::Schema(format!("expected \"{}\" to be an array", key))),
+    }
+}
+
+fn read_section(value:&Value, element_name:&str, fields:&[&str], format:Format, at:&str) -> Result<Element, JsonError> {
+    if value.as_object().is_none() {
+        return Err(schema_error(at, "expected an object"));
+    }
+    let mut element = Element::empty();
+    element.element_name = String::from(element_name);
+    for name in fields.iter() {
+        let text = match value.get(name) {
+            None => continue,
+            Some(Value::String(string)) => Variant::String(string.clone()).to_text(format),
+            Some(Value::Number(number)) if *name == "id" && number.parse::<i64>().is_ok() => number.clone(),
+            Some(_) => return Err(schema_error(at, &format!("expected \"{}\" to be a string", name))),
+        };
+        element.element_data.push(ElementData(String::from(*name), text));
+    }
+    for (name, typed) in members(value, "data", at)?.iter() {
+        check_name(name, at)?;
+        if element.element_data.iter().any(|data| data.0 == *name) {
+            return Err(schema_error(at, &format!("{} is given twice", name)));
+        }
+        element.element_data.push(ElementData(name.clone(), value_text(typed, format, &format!("{} {}", at, name))?));
+    }
+    for (name, typed) in members(value, "properties", at)?.iter() {
+        check_name(name, at)?;
+        element.properties.push(Property(name.clone(), value_text(typed, format, &format!("{} {}", at, name))?));
+    }
+    Ok(element)
+}
+
+// Adds a node and then its children, `parent` is where nesting put it.
+fn read_node(value:&Value, parent:Option<&str>, format:Format, elements:&mut Vec<Element>) -> Result<(), JsonError> {
+    let at = match (value.get("name").and_then(Value::as_str), parent) {
+        (Some(name), Some(parent)) => format!("node {:?}", join_path(parent, name)),
+        (Some(name), None) => format!("node {:?}", name),
+        (None, _) => String::from("node"),
+    };
+    let mut element = read_section(value, "node", NODE_FIELDS, format, &at)?;
+    if let Some(parent) = parent {
+        if element.element_data.iter().any(|data| data.0 == "parent") {
+            return Err(schema_error(&at, "nested nodes can't set their parent"));
+        }
+        element.element_data.push(ElementData(String::from("parent"), Variant::String(String::from(parent)).to_text(format)));
+    }
+    // Only the first node without a parent is the root, others can't have children.
+    let is_root = !elements.iter().any(|element| element.element_name == "node");
+    let path = match (data_string(&element, "name"), data_string(&element, "parent")) {
+        (_, None) if is_root => String::from("."),
+        (_, None) => String::new(),
+        (Some(name), Some(parent)) => join_path(&parent, &name),
+        (None, Some(_)) => String::new(),
+    };
+    elements.push(element);
+
+    let children = list(value, "children").map_err(|_| schema_error(&at, "expected \"children\" to be an array"))?;
+    if !children.is_empty() && path.is_empty() {
+        return Err(schema_error(&at, "nodes with children need a name"));
+    }
+    for child in children.iter() {
+        read_node(child, Some(&path), format, elements)?;
+    }
+    Ok(())
+}
+
+pub fn from_json(json:&str) -> Result<Scene, JsonError> {
+    let document = json::parse(json).map_err(|(message, offset)| JsonError::Syntax(message, offset))?;
+    if document.as_object().is_none() {
+        return Err(JsonError::Schema(String::from("expected an object")));
+    }
+    match document.get("schema").and_then(to_int) {
+        Some(SCHEMA_VERSION) => {},
+        Some(version) => return Err(JsonError::Schema(format!("unsupported schema version {}", version))),
+        None => return Err(JsonError::Schema(String::from("missing schema version"))),
+    }
+
+    let mut elements = Vec::new();
+    match document.get("header") {
+        None | Some(Value::Null) => {},
+        Some(header) => {
+            let mut element = Element::empty();
+            element.element_name = String::from("gd_scene");
+            let data = header.as_object().ok_or_else(|| JsonError::Schema(String::from("expected \"header\" to be an object")))?;
+            for (name, typed) in data.iter() {
+                check_name(name, "header")?;
+                element.element_data.push(ElementData(name.clone(), value_text(typed, Format::Godot4, &format!("header {}", name))?));
+            }
+            elements.push(element);
+        },
+    }
+    let format = scene::file_format(&elements);
+
+    for (index, value) in list(&document, "ext_resources")?.iter().enumerate() {
+        elements.push(read_section(value, "ext_resource", EXT_RESOURCE_FIELDS, format, &format!("ext resource {}", index))?);
+    }
+    for (index, value) in list(&document, "sub_resources")?.iter().enumerate() {
+        elements.push(read_section(value, "sub_resource", SUB_RESOURCE_FIELDS, format, &format!("sub resource {}", index))?);
+    }
+    for value in list(&document, "nodes")?.iter() {
+        read_node(value, None, format, &mut elements)?;
+    }
+    for (index, value) in list(&document, "connections")?.iter().enumerate() {
+        elements.push(read_section(value, "connection", CONNECTION_FIELDS, format, &format!("connection {}", index))?);
+    }
+    for (index, value) in list(&document, "editable")?.iter().enumerate() {
+        elements.push(read_section(value, "editable", EDITABLE_FIELDS, format, &format!("editable {}", index))?);
+    }
+    for (index, value) in list(&document, "other")?.iter().enumerate() {
+        let at = format!("other section {}", index);
+        let name = value.get("section").and_then(Value::as_str).ok_or_else(|| schema_error(&at, "expected a \"section\" name"))?;
+        if !name.is_empty() {
+            check_name(name, &at)?;
+        }
+        elements.push(read_section(value, name, &[], format, &at)?);
+    }
+
+    let text = writer::write_elements(&elements, format);
+    text.parse::<Scene>().map_err(JsonError::Scene)
+}
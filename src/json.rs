@@ -0,0 +1,330 @@
+// Helpers for reading and writing JSON without a serialization library.
+
+pub(crate) fn string(value:&str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub(crate) fn optional_string(value:Option<&str>) -> String {
+    match value {
+        Some(value) => string(value),
+        None => String::from("null"),
+    }
+}
+
+// `{"key": value, ...}` from already encoded values.
+pub(crate) fn object(fields:&[(&str, String)]) -> String {
+    let fields = fields.iter().map(|(key, value)| format!("{}: {}", string(key), value)).collect::<Vec<String>>();
+    format!("{{{}}}", fields.join(", "))
+}
+
+// An array of already encoded values, one per line.
+pub(crate) fn array(values:&[String], indent:usize) -> String {
+    if values.is_empty() {
+        return String::from("[]");
+    }
+    let padding = " ".repeat(indent + 2);
+    let values = values.iter().map(|value| format!("{}{}", padding, value)).collect::<Vec<String>>();
+    format!("[\n{}\n{}]", values.join(",\n"), " ".repeat(indent))
+}
+
+// A parsed JSON document. Numbers keep their text so integers and floats convert without loss, members of an
+// object keep their order.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub(crate) fn get(&self, key:&str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_compact(&self) -> String {
+        match self {
+            Value::Null => String::from("null"),
+            Value::Bool(bool) => bool.to_string(),
+            Value::Number(number) => number.clone(),
+            Value::String(value) => string(value),
+            Value::Array(values) => {
+                format!("[{}]", values.iter().map(|value| value.to_compact()).collect::<Vec<String>>().join(", "))
+            },
+            Value::Object(members) => {
+                let members = members.iter().map(|(key, value)| format!("{}: {}", string(key), value.to_compact())).collect::<Vec<String>>();
+                format!("{{{}}}", members.join(", "))
+            },
+        }
+    }
+
+    // Arrays and objects that fit on a line are written on one, others get a line per member.
+    pub(crate) fn to_pretty(&self, indent:usize) -> String {
+        let compact = self.to_compact();
+        if indent + compact.len() <= PRETTY_WIDTH {
+            return compact;
+        }
+        let padding = " ".repeat(indent + 2);
+        match self {
+            Value::Array(values) if !values.is_empty() => {
+                let values = values.iter().map(|value| format!("{}{}", padding, value.to_pretty(indent + 2))).collect::<Vec<String>>();
+                format!("[\n{}\n{}]", values.join(",\n"), " ".repeat(indent))
+            },
+            Value::Object(members) if !members.is_empty() => {
+                let members = members.iter().map(|(key, value)| format!("{}{}: {}", padding, string(key), value.to_pretty(indent + 2))).collect::<Vec<String>>();
+                format!("{{\n{}\n{}}}", members.join(",\n"), " ".repeat(indent))
+            },
+            _ => compact,
+        }
+    }
+}
+
+const PRETTY_WIDTH:usize = 100;
+
+// Nesting deeper than this is rejected instead of risking the stack.
+const MAX_DEPTH:usize = 256;
+
+// Parses a JSON document, `Err` holds the message and the byte offset it applies to.
+pub(crate) fn parse(text:&str) -> Result<Value, (String, usize)> {
+    let mut parser = Parser { src: text, pos: 0 };
+    let value = parser.parse_value(0)?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(parser.error("unexpected data after the document"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    src:&'a str,
+    pos:usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message:&str) -> (String, usize) {
+        (String::from(message), self.pos)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte:u8) -> Result<(), (String, usize)> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn parse_value(&mut self, depth:usize) -> Result<Value, (String, usize)> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.parse_object(depth),
+            Some(b'[') => self.parse_array(depth),
+            Some(b'"') => Ok(Value::String(self.parse_string()?)),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => {
+                for (word, value) in [("null", Value::Null), ("true", Value::Bool(true)), ("false", Value::Bool(false))] {
+                    if self.src[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("expected a value"))
+            },
+            None => Err(self.error("unexpected end of document")),
+        }
+    }
+
+    fn parse_object(&mut self, depth:usize) -> Result<Value, (String, usize)> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a member name"));
+            }
+            let key = self.parse_string()?;
+            self.expect(b':')?;
+            members.push((key, self.parse_value(depth + 1)?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                },
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self, depth:usize) -> Result<Value, (String, usize)> {
+        self.pos += 1;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(values));
+                },
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value, (String, usize)> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let digits = |parser:&mut Self| {
+            let start = parser.pos;
+            while matches!(parser.peek(), Some(b'0'..=b'9')) {
+                parser.pos += 1;
+            }
+            parser.pos > start
+        };
+        if !digits(self) {
+            return Err(self.error("expected a digit"));
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        Ok(Value::Number(String::from(&self.src[start..self.pos])))
+    }
+
+    fn parse_string(&mut self) -> Result<String, (String, usize)> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let rest = &self.src[self.pos..];
+            let Some(c) = rest.chars().next() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => out.push(self.parse_unicode_escape()?),
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                },
+                c if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                c => out.push(c),
+            }
+        }
+    }
+
+    // `\uXXXX` after the `\u`, including surrogate pairs written as two escapes.
+    fn parse_unicode_escape(&mut self) -> Result<char, (String, usize)> {
+        let high = self.parse_hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"));
+        }
+        if !self.src[self.pos..].starts_with("\\u") {
+            return Err(self.error("unpaired surrogate"));
+        }
+        self.pos += 2;
+        let low = self.parse_hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(self.error("unpaired surrogate"));
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, (String, usize)> {
+        let hex = self.src.get(self.pos..self.pos + 4).filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        let hex = hex.ok_or_else(|| self.error("invalid unicode escape"))?;
+        let code = u32::from_str_radix(hex, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
@@ -1,13 +1,38 @@
-mod loader;
-mod tokenizer;
-mod scene;
-mod element;
+pub mod loader;
+pub mod tokenizer;
+pub mod events;
+pub mod borrowed;
+pub mod scene;
+pub mod resource;
+pub mod resource_table;
+pub mod element;
+pub mod variant;
+pub mod tree;
+pub mod connection;
+pub mod convert;
+pub mod diff;
+pub mod merge;
+pub mod json;
+pub mod interchange;
+pub mod instance;
+pub mod project;
+pub mod uid;
+pub mod writer;
+pub mod cli;
+#[cfg(feature = "serde")]
+pub mod de;
+
+#[cfg(feature = "serde")]
+pub use de::{from_node, from_properties, from_str, from_variant};
 
 #[cfg(test)]
 mod tests {
-    use crate::{scene::{NodePath, Scene}};
+    use std::{borrow::Cow, collections::HashMap, path::Path, rc::Rc, str::FromStr};
+
+    use crate::{borrowed::{unquote, SceneRef, TokenRef}, cli, connection::Connection, events::{Event, EventReader}, convert::{ConversionReport, RenameTable, Untranslated}, diff::{NodeChange, PropertyOwner, SceneDiff}, merge::{MergeConflict, MergeSide}, interchange::JsonError, instance::{ResDirectory, SceneSource}, project::{Document, Project}, uid::{Uid, UidIndex}, element::{ElementData, ElementType, LookupError, Property}, resource::Resource, resource_table::ResourceKind, scene::{NodePath, NodePathError, Scene, SceneError}, tokenizer::{Tokenizer, TokenizerError}, variant::{Format, ResourceId, Variant}};
 
     #[test]
+    #[allow(unused_must_use)] // Doesn't check whether the update succeeded.
     fn tokenize() {
         let scene = Scene::from_tscn_file(r"./src/test.tscn");
         if let Ok(mut sc) = scene {
@@ -17,4 +42,1046 @@ mod tests {
             println!("{:#?}", sc.get_node_property(NodePath::from("Tree/StaticBody2D/CollisionShape2D"), "test"));
         }
     }
+
+    #[test]
+    fn variants() {
+        assert_eq!(Variant::parse("Vector2(0, 32.1053)"), Ok(Variant::Vector2(0.0, 32.1053)));
+        assert_eq!(Variant::parse("ExtResource( 1 )"), Ok(Variant::ExtResource(ResourceId::Int(1))));
+        assert_eq!(Variant::parse(r#"SubResource("RectangleShape2D_jkvx4")"#), Ok(Variant::SubResource(ResourceId::String("RectangleShape2D_jkvx4".into()))));
+        assert_eq!(Variant::parse("PoolStringArray( \"a\", \"b\" )"), Ok(Variant::PackedStringArray(vec!["a".into(), "b".into()])));
+        assert_eq!(Variant::parse("inf_neg"), Ok(Variant::Float(f64::NEG_INFINITY)));
+        assert_eq!(Variant::parse("&\"idle\""), Ok(Variant::StringName("idle".into())));
+        assert_eq!(Variant::parse("-9223372036854775808"), Ok(Variant::Int(i64::MIN)));
+        assert_eq!(Variant::parse("0xFFFFFFFFFFFFFFFF"), Ok(Variant::Int(-1)));
+        assert_eq!(Variant::parse("-0b1010"), Ok(Variant::Int(-10)));
+
+        for text in [
+            "null", "true", "-12", "1.5", "inf", "\"say \\\"hi\\\"\"", "NodePath(\"Tree/Area2D:position:x\")",
+            "Vector2i(-3, 4)", "Vector3(1, 2.5, -3)", "Rect2(0, 130, 48, 64)", "Color(1, 0, 0.0509804, 0.188235)",
+            "Transform3D(1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0)", "AABB(0, 0, 0, 1, 1, 1)", "Quaternion(0, 0, 0, 1)",
+            "[1, \"two\", 3.0]", "{\n\"a\": 1,\n\"b\": [2]\n}", "Array[int]([1, 2])", "PackedInt32Array(-1245199, 196608, 0)",
+            "PackedVector2Array(-9.5, 6.5, 9.5, 6.5)", "Vector2(1.342e-06, 15.3507)",
+            "PackedFloat32Array(0.1, 2.5, 1e-07, 3.4028235e+38)",
+        ] {
+            assert_eq!(Variant::parse(text).map(|v| v.to_string()), Ok(text.to_string()));
+        }
+
+        let scene = Scene::from_tscn_file(r"./src/test.tscn").unwrap();
+        assert_eq!(scene.get_node_property_variant(NodePath::from("Camera2D"), "zoom").unwrap(), Variant::Vector2(2.0, 2.0));
+    }
+
+    #[test]
+    fn multiline_values() {
+        let scene = Scene::from_tscn_file(r"./src/test_multiline.tscn").unwrap();
+        assert_eq!(scene.elements.len(), 5);
+
+        let animation = &scene.elements[2];
+        assert_eq!(animation.properties.len(), 9);
+        let keys = animation.get_property_variant("tracks/0/keys").unwrap();
+        assert_eq!(keys.as_dictionary().unwrap()[2], (Variant::String("update".into()), Variant::Int(0)));
+
+        assert_eq!(scene.elements[3].get_data_variant("groups"), Ok(Variant::Array(vec!["Interactable".into(), "Sign [wood]".into()])));
+        assert_eq!(scene.elements[3].get_property_variant("lines").unwrap(), Variant::PackedStringArray(vec!["Welcome to Myti.".into(), "Mind the trees.".into()]));
+        assert_eq!(scene.elements[3].get_property_variant("text").unwrap(), Variant::String("Line one\nLine \"two\" ]\nLine three".into()));
+        assert_eq!(scene.get_node_property(NodePath::from("Sprite2D"), "texture").unwrap(), "ExtResource(\"1_roxug\")");
+    }
+
+    #[test]
+    fn parse_without_files() {
+        let source = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node2D\"]\n\n[node name=\"Child\" type=\"Node2D\" parent=\".\"]\nvisible = false";
+        let from_str = source.parse::<Scene>().unwrap();
+        let from_reader = Scene::from_reader(std::io::Cursor::new(source.as_bytes())).unwrap();
+        for scene in [from_str, from_reader] {
+            assert_eq!(scene.elements.len(), 3);
+            assert_eq!(scene.get_node_property_variant(NodePath::from("Child"), "visible").unwrap(), Variant::Bool(false));
+        }
+        assert!(matches!("[node name=\"Root\"".parse::<Scene>(), Err(SceneError::TokenizerError(..))));
+    }
+
+    #[test]
+    fn lossless_round_trip() {
+        for path in ["./src/test.tscn", "./src/test_multiline.tscn"] {
+            let source = std::fs::read_to_string(path).unwrap();
+            let mut scene = source.parse::<Scene>().unwrap();
+            assert_eq!(scene.to_tscn(), source);
+
+            let node = scene.elements.iter().position(|element| matches!(element.get_data_value("name").as_deref(), Ok("\"Camera2D\"" | "\"Sprite2D\""))).unwrap();
+            let property = scene.elements[node].properties[0].0.clone();
+            scene.elements[node].update_property(&property, "Vector2(4, 4)").unwrap();
+            let output = scene.to_tscn();
+            let changed = source.lines().zip(output.lines()).filter(|(before, after)| before != after).collect::<Vec<_>>();
+            assert_eq!(source.lines().count(), output.lines().count());
+            assert_eq!(changed.len(), 1);
+            assert!(changed[0].1.ends_with("= Vector2(4, 4)"));
+        }
+
+        let source = "; Hand-written\r\n[gd_scene format=2]\r\n\r\n[node name=\"Root\" type=\"Spatial\" ]\r\nvisible=false ; hidden\r\ntransform = Transform( 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0 )\r\n\r\n\r\n[node name=\"Child\" parent=\".\" instance=ExtResource( 1 )]";
+        let mut scene = source.parse::<Scene>().unwrap();
+        assert_eq!(scene.to_tscn(), source);
+        scene.elements[1].update_property("visible", "true").unwrap();
+        assert_eq!(scene.to_tscn(), source.replace("visible=false", "visible=true"));
+        assert_eq!(scene.elements[2].get_data_variant("instance"), Ok(Variant::ExtResource(ResourceId::Int(1))));
+
+        // Properties and comments without a header above them are kept in an element of their own.
+        for source in ["x = 1\n", "; notes\n\n", "a = 1\nb = \"two\" ; note\n\n[section]\nc = 3\n"] {
+            let elements = Tokenizer::tokenize_str(source).unwrap().elements;
+            assert_eq!(elements.iter().flat_map(|element| element.tokens.iter()).map(|token| token.to_string()).collect::<String>(), source);
+            assert!(elements[0].is_preamble());
+        }
+        let elements = Tokenizer::tokenize_str("a = 1\n\n[section]\nc = 3\n").unwrap().elements;
+        assert_eq!((elements[0].properties.len(), elements[1].element_name.as_str(), elements[1].properties.len()), (1, "section", 1));
+    }
+
+    #[test]
+    fn canonical_output() {
+        let source = std::fs::read_to_string("./src/test.tscn").unwrap();
+        assert_eq!(source.parse::<Scene>().unwrap().to_canonical_tscn(), source);
+
+        let messy = "[gd_scene format=3 load_steps=9]\n[node name=\"Root\" type=\"Node2D\" ]\nscale=Vector2( 2,2 )\nspeed = 3\n\n\n; note\n[connection signal=\"pressed\" from=\".\" to=\".\" method=\"_on_pressed\" binds=[1]]\n[sub_resource id=\"A_1\" type=\"Gradient\"]\ncolors = PackedColorArray(0,0,0,1,1,1,1,1)\n[ext_resource type=\"Script\" path=\"res://a.gd\" id=\"1_a\"]\n[node name=\"Child\" type=\"Node2D\" parent=\".\" groups=[\"a\",\"b\"]]";
+        let expected = "[gd_scene load_steps=3 format=3]\n\n[ext_resource type=\"Script\" path=\"res://a.gd\" id=\"1_a\"]\n\n[sub_resource type=\"Gradient\" id=\"A_1\"]\ncolors = PackedColorArray(0, 0, 0, 1, 1, 1, 1, 1)\n\n[node name=\"Root\" type=\"Node2D\"]\nscale = Vector2(2, 2)\nspeed = 3\n\n[node name=\"Child\" type=\"Node2D\" parent=\".\" groups=[\"a\", \"b\"]]\n\n[connection signal=\"pressed\" from=\".\" to=\".\" method=\"_on_pressed\" binds= [1]]\n";
+        assert_eq!(messy.parse::<Scene>().unwrap().to_canonical_tscn(), expected);
+
+        let godot3 = "[gd_scene load_steps=2 format=2]\n\n[ext_resource path=\"res://a.gd\" type=\"Script\" id=1]\n\n[node name=\"Root\" type=\"Spatial\" groups=[\n\"enemies\",\n]]\ntransform = Transform( 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 1e+06, 0 )\nscript = ExtResource( 1 )\nitems = [  ]\nnames = PoolStringArray( \"a\", \"b\" )\n";
+        assert_eq!(godot3.parse::<Scene>().unwrap().to_canonical_tscn(), godot3);
+
+        let instanced = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node\"]\n\n[node name=\"E\" parent=\".\" instance=ExtResource(\"1_a\") groups=[\"enemies\"] node_paths=PackedStringArray(\"target\")]\n";
+        let expected = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node\"]\n\n[node name=\"E\" parent=\".\" node_paths=PackedStringArray(\"target\") groups=[\"enemies\"] instance=ExtResource(\"1_a\")]\n";
+        assert_eq!(instanced.parse::<Scene>().unwrap().to_canonical_tscn(), expected);
+    }
+
+    #[test]
+    fn scene_tree() {
+        let scene = Scene::from_tscn_file(r"./src/test.tscn").unwrap();
+        let tree = scene.tree();
+        let root = tree.root().unwrap();
+        assert_eq!(root.name(), "Room");
+        assert_eq!(root.path(), ".");
+        assert!(root.children().any(|child| child.name() == "Camera2D"));
+
+        let shape = tree.get_node("Tree/StaticBody2D/CollisionShape2D").unwrap();
+        assert_eq!(shape.node_type().as_deref(), Some("CollisionPolygon2D"));
+        assert_eq!(shape.parent().unwrap().parent().unwrap().name(), "Tree");
+        assert_eq!(shape.depth(), 3);
+        assert_eq!(root.find_child("Leaves", false), None);
+        assert_eq!(root.find_child("Leaves", true).unwrap().path(), "Tree/Leaves");
+        assert_eq!(root.find_child("StaticBody2D", true).unwrap().path(), "Tree/StaticBody2D");
+        assert_eq!(scene.get_node_property(NodePath::from("Tree/StaticBody2D/CollisionShape2D"), "test").unwrap(), "\"TOOP\"");
+
+        let tree_names = tree.get_node("Tree").unwrap().depth_first().map(|node| node.path()).collect::<Vec<String>>();
+        assert_eq!(tree_names, ["Tree", "Tree/Leaves", "Tree/Area2D", "Tree/Area2D/CollisionShape2D", "Tree/StaticBody2D", "Tree/StaticBody2D/CollisionShape2D"]);
+        let levels = tree.breadth_first().map(|node| node.depth()).collect::<Vec<usize>>();
+        assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(tree.depth_first().count(), tree.len());
+
+        // Nodes inside instanced scenes only show up as parents.
+        let inherited = "[gd_scene format=3]\n\n[node name=\"Level\" instance=ExtResource(\"1\")]\n\n[node name=\"Extra\" type=\"Node\" parent=\"Enemies/Boss\"]\n".parse::<Scene>().unwrap();
+        let tree = inherited.tree();
+        let boss = tree.get_node("Enemies/Boss").unwrap();
+        assert!(boss.element().is_none());
+        assert_eq!(boss.find_child("Extra", false).unwrap().path(), "Enemies/Boss/Extra");
+    }
+
+    #[test]
+    fn node_paths() {
+        let path = NodePath::from("../Sibling/Sprite2D:position:x");
+        assert_eq!(path.names(), ["..", "Sibling", "Sprite2D"]);
+        assert_eq!(path.subnames(), ["position", "x"]);
+        assert_eq!(path.to_string(), "../Sibling/Sprite2D:position:x");
+        assert_eq!(NodePath::from("/root/Room").to_string(), "/root/Room");
+        assert_eq!(NodePath::from(":modulate").concatenated_subnames(), "modulate");
+        assert_eq!(NodePath::from("./A/../B/./C").simplified().to_string(), "B/C");
+        assert!(!NodePath::from("A:").is_valid());
+
+        let source = "[gd_scene format=3]\n\n[node name=\"Main\" type=\"Node\"]\n\n[node name=\"UI\" type=\"Control\" parent=\".\"]\n\n[node name=\"Health\" type=\"Label\" parent=\"UI\"]\nunique_name_in_owner = true\n\n[node name=\"Player\" type=\"Node2D\" parent=\".\"]\n\n[node name=\"Sprite2D\" type=\"Sprite2D\" parent=\"Player\"]\n";
+        let scene = source.parse::<Scene>().unwrap();
+        let tree = scene.tree();
+        let sprite = tree.get_node("Player/Sprite2D").unwrap();
+        let health = tree.get_node("UI/Health").unwrap();
+        assert_eq!(sprite.get_node(&NodePath::from("../../UI/Health:text")), Some(health));
+        assert_eq!(sprite.get_node(&NodePath::from("%Health")), Some(health));
+        assert_eq!(sprite.get_node(&NodePath::from("/root/Main/UI")), health.parent());
+        assert_eq!(sprite.get_node(&NodePath::from("/root/Other")), None);
+        assert_eq!(sprite.get_node(&NodePath::from(".")), Some(sprite));
+        assert_eq!(sprite.path_to(&health).to_string(), "../../UI/Health");
+        assert_eq!(health.path_to(&tree.root().unwrap()).to_string(), "../..");
+        assert_eq!(sprite.path_to(&sprite).to_string(), ".");
+        assert_eq!(sprite.absolute_path().to_string(), "/root/Main/Player/Sprite2D");
+        assert_eq!(scene.get_node_property(NodePath::from("%Health"), "unique_name_in_owner").unwrap(), "true");
+    }
+
+    #[test]
+    fn error_locations() {
+        let source = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node2D\"]\nposition = Vector2(1, 2)\nscale Vector2(1, 1)\n";
+        let error = source.parse::<Scene>().unwrap_err();
+        let location = error.location().unwrap();
+        assert_eq!((location.line, location.column), (5, 7));
+        assert_eq!(&source[location.span.clone()], "V");
+        assert_eq!(error.to_string(), "unexpected character 'V' at <string>:5:7");
+        assert_eq!(error.render(source), "error: unexpected character 'V' at <string>:5:7\n --> <string>:5:7\n  |\n5 | scale Vector2(1, 1)\n  |       ^");
+
+        let unclosed = "[node name=\"Root\" type=\"Node2D\"]\ntext = \"never closed\nmore\n";
+        let error = unclosed.parse::<Scene>().unwrap_err();
+        assert_eq!(error.location().map(|location| location.line), Some(2));
+        assert!(matches!(error, SceneError::TokenizerError(TokenizerError::EarlyEOF(..))));
+
+        let missing = Scene::from_tscn_file("./src/missing.tscn").unwrap_err();
+        assert!(missing.to_string().starts_with("failed to load ./src/missing.tscn"));
+        assert!(std::error::Error::source(&missing).is_some());
+
+        let scene = Scene::from_tscn_file("./src/test.tscn").unwrap();
+        let tree = scene.tree();
+        let camera = tree.get_node("Camera2D").unwrap().element().unwrap();
+        assert_eq!(camera.span().map(|span| span.line), Some(95));
+        assert_eq!(camera.property_span("zoom").map(|span| (span.line, span.column)), Some((98, 8)));
+    }
+
+    #[test]
+    fn error_recovery() {
+        let source = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node2D\"]\nposition = Vector2(1, 2)\nscale Vector2(1, 1)\nrotation = 1.5\n\n[node name=\"Broken\" parent=]\nvisible = false\n\n[node name=\"Child\" type=\"Sprite2D\" parent=\".\"]\n<<<<<<< HEAD\nz_index = 1\n=======\nz_index = 2\n>>>>>>> branch\n\n[node name=\"Last\" type=\"Node\" parent=\".\"]\ntext = \"never closed\n";
+        let (scene, errors) = Scene::from_str_recovering(source);
+        assert_eq!(errors.iter().map(|error| error.location().line).collect::<Vec<usize>>(), vec![5, 8, 12, 19]);
+        assert!(matches!(errors[3], TokenizerError::EarlyEOF(..)));
+        // Everything before an error is kept, the rest of its section is skipped.
+        assert_eq!(scene.elements.len(), 4);
+        let tree = scene.tree();
+        assert_eq!(tree.depth_first().map(|node| node.name().to_string()).collect::<Vec<String>>(), vec!["Root", "Child", "Last"]);
+        let root = tree.root().unwrap().element().unwrap();
+        assert_eq!(root.properties.len(), 1);
+        assert!(root.get_property_value("rotation").is_err());
+        assert!(tree.get_node("Child").unwrap().element().unwrap().properties.is_empty());
+        // Skipped text is still written back, so output and error positions match the source.
+        assert_eq!(scene.to_tscn(), source);
+        assert_eq!(&source[errors[2].location().span.clone()], "H");
+        let unterminated = source.trim_end();
+        let (scene, errors) = Scene::from_str_recovering(unterminated);
+        assert!(matches!(errors[3], TokenizerError::EarlyEOF(..)));
+        assert_eq!(scene.to_tscn(), unterminated);
+
+        let (scene, errors) = Scene::from_str_recovering(&std::fs::read_to_string("./src/test.tscn").unwrap());
+        assert!(errors.is_empty());
+        assert_eq!(scene.elements.len(), Scene::from_tscn_file("./src/test.tscn").unwrap().elements.len());
+        assert!("[node name=\"A\"]\n=======\n".parse::<Scene>().is_err());
+    }
+
+    #[test]
+    fn text_resources() {
+        let source = std::fs::read_to_string("./src/test.tres").unwrap();
+        let resource = Resource::from_tres_file("./src/test.tres").unwrap();
+        assert_eq!(resource.resource_type().as_deref(), Some("StandardMaterial3D"));
+        assert_eq!(resource.uid().as_deref(), Some("uid://c8f0xq2m3k7bd"));
+        assert_eq!(resource.script_class(), None);
+        assert_eq!(resource.main_resource().map(|element| element.element_type.clone()), Some(ElementType::MAIN_RESOURCE));
+        assert_eq!(resource.properties().len(), 6);
+        assert_eq!(resource.get_property_variant("roughness").unwrap(), Variant::Float(0.85));
+        assert_eq!(resource.get_property_variant("uv1_scale").unwrap(), Variant::Vector3(2.0, 2.0, 2.0));
+        assert!(resource.get_property("metallic").is_err());
+
+        assert_eq!(resource.ext_resources().len(), 1);
+        assert_eq!(resource.sub_resources().len(), 2);
+        let texture = resource.get_referenced(&resource.get_property_variant("albedo_texture").unwrap()).unwrap();
+        assert_eq!(texture.get_data_variant("path").unwrap(), Variant::String(String::from("res://textures/bricks_albedo.png")));
+        let detail = resource.get_referenced(&resource.get_property_variant("detail_albedo").unwrap()).unwrap();
+        let gradient = resource.get_referenced(&detail.get_property_variant("gradient").unwrap()).unwrap();
+        assert_eq!(gradient.get_data_variant("type").unwrap(), Variant::String(String::from("Gradient")));
+        assert!(resource.get_sub_resource(&ResourceId::Int(1)).is_none());
+
+        assert_eq!(resource.to_tres(), source);
+        assert_eq!(resource.to_canonical_tres(), source);
+    }
+
+    #[test]
+    fn resource_table() {
+        let scene = Scene::from_tscn_file("./src/test.tscn").unwrap();
+        let resources = scene.resources();
+        assert_eq!(resources.len(), 26);
+        let material = resources.resolve(&Variant::SubResource(ResourceId::String(String::from("ShaderMaterial_cspw1")))).unwrap();
+        assert_eq!(material.resource_type.as_deref(), Some("ShaderMaterial"));
+        assert_eq!(scene.nodes_referencing(material), vec!["Tree/Leaves"]);
+        let shader = resources.resolve(&resources.element(material).get_property_variant("shader").unwrap()).unwrap();
+        assert_eq!(shader.path.as_deref(), Some("res://core/shaders/tree.gdshader"));
+        assert_eq!(resources.referencing_elements(shader), vec![material.element]);
+        assert!(scene.nodes_referencing(shader).is_empty());
+        let tree_scene = resources.get(ResourceKind::Ext, &ResourceId::String(String::from("8_ofe6u"))).unwrap();
+        assert_eq!(tree_scene.uid.as_deref(), Some("uid://cegcxjghbap83"));
+        assert!(resources.references_to(tree_scene).iter().all(|reference| reference.name == "instance"));
+        assert!(scene.nodes_referencing(tree_scene).contains(&String::from("Tree12")));
+        assert!(resources.dangling().is_empty());
+        assert!(resources.unused().is_empty());
+
+        let godot3 = "[gd_scene load_steps=4 format=2]\n\n[ext_resource path=\"res://icon.png\" type=\"Texture\" id=1]\n\n[sub_resource type=\"RectangleShape2D\" id=1]\n\n[sub_resource type=\"CircleShape2D\" id=3]\n\n[node name=\"Root\" type=\"Node2D\"]\nframes = [ ExtResource( 1 ), SubResource( 2 ) ]\nshape = SubResource( 1 )\n".parse::<Scene>().unwrap();
+        let resources = godot3.resources();
+        assert_eq!(resources.resolve(&Variant::ExtResource(ResourceId::Int(1))).map(|entry| entry.kind), Some(ResourceKind::Ext));
+        assert_eq!(resources.resolve(&Variant::SubResource(ResourceId::Int(1))).map(|entry| entry.kind), Some(ResourceKind::Sub));
+        assert_eq!(resources.dangling().iter().map(|reference| (reference.name.as_str(), reference.id.clone())).collect::<Vec<(&str, ResourceId)>>(), vec![("frames", ResourceId::Int(2))]);
+        assert_eq!(resources.unused().iter().map(|entry| (entry.kind, entry.id.clone())).collect::<Vec<(ResourceKind, ResourceId)>>(), vec![(ResourceKind::Sub, ResourceId::Int(3))]);
+
+        let resource = Resource::from_tres_file("./src/test.tres").unwrap();
+        assert!(resource.resources().unused().is_empty());
+    }
+
+    #[test]
+    fn connections() {
+        let source = std::fs::read_to_string("./src/test.tscn").unwrap();
+        let mut scene = source.parse::<Scene>().unwrap();
+        assert_eq!(scene.connections().len(), 3);
+        assert_eq!(scene.connections_from("TileMap/Stairs").iter().map(|connection| connection.signal.as_str()).collect::<Vec<&str>>(), vec!["body_entered", "body_exited"]);
+        assert_eq!(scene.connections_to("To To Town Path 2")[0].method, "_on_Area2D_body_entered");
+        assert!(scene.connections_from(".").is_empty());
+
+        let mut connection = Connection::new("timeout", "Camera2D", ".", "_on_timeout");
+        connection.flags = Some(3);
+        connection.binds = vec![Variant::Int(1), Variant::String(String::from("a"))];
+        scene.connect(connection.clone()).unwrap();
+        assert!(scene.connect(Connection::new("timeout", "Camera2D", ".", "_on_timeout")).is_err());
+        let added = "[connection signal=\"timeout\" from=\"Camera2D\" to=\".\" method=\"_on_timeout\" flags=3 binds=[1, \"a\"]]\n";
+        assert_eq!(scene.to_tscn(), source.clone() + added);
+        assert_eq!(scene.to_tscn().parse::<Scene>().unwrap().connections()[3], connection);
+
+        scene.retarget_connections("TileMap", "Map");
+        assert_eq!(scene.connections_from("Map/Stairs").len(), 2);
+        assert_eq!(scene.to_tscn().matches("\"Map/Stairs\"").count(), 4);
+        scene.retarget_connections("Map/Stairs", "Stairs");
+        scene.retarget_connections("Stairs", "Map/Stairs");
+        scene.retarget_connections("Map", "TileMap");
+        scene.disconnect(&connection).unwrap();
+        assert!(scene.disconnect(&connection).is_err());
+        assert_eq!(scene.to_tscn(), source);
+
+        // Connections stay on consecutive lines when one in the middle is removed.
+        let middle = scene.connections()[1].clone();
+        scene.disconnect(&middle).unwrap();
+        let line = source.lines().filter(|line| line.starts_with("[connection")).nth(1).unwrap();
+        assert_eq!(scene.to_tscn(), source.replace(&format!("{}\n", line), ""));
+
+        // The first connection of a scene gets a blank line before it, removing it takes the blank line away.
+        let source = "[gd_scene format=2]\n\n[node name=\"Root\" type=\"Node2D\"]\n\n[node name=\"Timer\" type=\"Timer\" parent=\".\"]\nautostart = true\n";
+        let mut scene = source.parse::<Scene>().unwrap();
+        let mut connection = Connection::new("timeout", "Timer", ".", "_on_timeout");
+        connection.binds = vec![Variant::Int(2)];
+        connection.flags = Some(0);
+        scene.connect(connection.clone()).unwrap();
+        assert_eq!(scene.to_tscn(), source.to_string() + "\n[connection signal=\"timeout\" from=\"Timer\" to=\".\" method=\"_on_timeout\" binds=[ 2 ] flags=0]\n");
+        scene.disconnect(&connection).unwrap();
+        assert_eq!(scene.to_tscn(), source);
+    }
+
+    #[test]
+    fn node_editing() {
+        let source = "[gd_scene format=3]\n\n[node name=\"Main\" type=\"Node2D\"]\n\n[node name=\"Player\" type=\"CharacterBody2D\" parent=\".\"]\ncamera = NodePath(\"Sprite2D\")\ntarget = NodePath(\"../UI/Health:text\")\n\n[node name=\"Sprite2D\" type=\"Sprite2D\" parent=\"Player\"]\n\n[node name=\"UI\" type=\"CanvasLayer\" parent=\".\"]\n\n[node name=\"Health\" type=\"Label\" parent=\"UI\"]\nplayer = NodePath(\"../../Player\")\nui = NodePath(\"/root/Main/UI\")\n\n[connection signal=\"ready\" from=\"Player/Sprite2D\" to=\"UI/Health\" method=\"_on_ready\"]\n[connection signal=\"hit\" from=\"Player\" to=\".\" method=\"_on_hit\"]\n";
+        let mut scene = source.parse::<Scene>().unwrap();
+        assert_eq!(scene.add_child("UI", "Score", "Label").unwrap(), "UI/Score");
+        assert!(scene.to_tscn().contains("ui = NodePath(\"/root/Main/UI\")\n\n[node name=\"Score\" type=\"Label\" parent=\"UI\"]\n\n[connection"));
+        assert!(matches!(scene.add_child("UI", "Score", "Label"), Err(NodePathError::NameTaken(..))));
+        assert!(matches!(scene.add_child("Missing", "Score", "Label"), Err(NodePathError::NodeNotFound)));
+        assert!(matches!(scene.add_child(".", "A/B", "Node"), Err(NodePathError::InvalidEdit(..))));
+
+        assert_eq!(scene.rename_node("Player", "Hero").unwrap(), "Hero");
+        let tree = scene.tree();
+        assert_eq!(tree.get_node("Hero/Sprite2D").and_then(|node| node.parent()).map(|node| node.name()), Some("Hero"));
+        assert_eq!(scene.get_node_property(NodePath::from("UI/Health"), "player").unwrap(), "NodePath(\"../../Hero\")");
+        assert_eq!(scene.connections_from("Hero/Sprite2D").len(), 1);
+        assert_eq!(scene.connections_from("Hero")[0].signal, "hit");
+
+        assert_eq!(scene.reparent("UI/Health", "Hero").unwrap(), "Hero/Health");
+        assert_eq!(scene.get_node_property(NodePath::from("Hero"), "target").unwrap(), "NodePath(\"Health:text\")");
+        assert_eq!(scene.connections_to("Hero/Health").len(), 1);
+        assert!(matches!(scene.reparent("Hero", "Hero/Sprite2D"), Err(NodePathError::InvalidEdit(..))));
+        assert!(matches!(scene.reparent(".", "UI"), Err(NodePathError::InvalidEdit(..))));
+        let tree = scene.tree();
+        assert_eq!(tree.get_node("Hero").unwrap().children().map(|node| node.name()).collect::<Vec<&str>>(), vec!["Sprite2D", "Health"]);
+
+        scene.move_child("Hero/Health", 0).unwrap();
+        let tree = scene.tree();
+        assert_eq!(tree.depth_first().map(|node| node.name()).collect::<Vec<&str>>(), vec!["Main", "Hero", "Health", "Sprite2D", "UI", "Score"]);
+        assert!(scene.move_child("Hero/Health", 2).is_err());
+        // Every edit still reads back as the same scene.
+        assert_eq!(scene.to_tscn().parse::<Scene>().unwrap().tree().depth_first().map(|node| node.path()).collect::<Vec<String>>(), vec![".", "Hero", "Hero/Health", "Hero/Sprite2D", "UI", "UI/Score"]);
+
+        scene.remove_node("Hero").unwrap();
+        assert!(scene.connections().is_empty());
+        assert_eq!(scene.to_tscn(), "[gd_scene format=3]\n\n[node name=\"Main\" type=\"Node2D\"]\n\n[node name=\"UI\" type=\"CanvasLayer\" parent=\".\"]\n\n[node name=\"Score\" type=\"Label\" parent=\"UI\"]\n");
+        assert!(matches!(scene.remove_node("."), Err(NodePathError::InvalidEdit(..))));
+        assert!(matches!(scene.remove_node("Hero"), Err(NodePathError::NodeNotFound)));
+
+        // Edits to a file with `\r\n` line breaks use them too.
+        let source = "[gd_scene format=3]\r\n\r\n[node name=\"A\" type=\"Node\"]\r\nx = 1\r\n";
+        let mut scene = source.parse::<Scene>().unwrap();
+        scene.add_child(".", "B", "Node").unwrap();
+        scene.set_node_property(NodePath::from("B"), "y", "2").unwrap();
+        scene.connect(Connection::new("ready", "B", ".", "_on_ready")).unwrap();
+        let expected = source.to_string() + "\r\n[node name=\"B\" type=\"Node\" parent=\".\"]\r\ny = 2\r\n\r\n[connection signal=\"ready\" from=\"B\" to=\".\" method=\"_on_ready\"]\r\n";
+        assert_eq!(scene.to_tscn(), expected);
+    }
+
+    #[test]
+    fn property_editing() {
+        let source = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node2D\"]\nposition = Vector2(1, 2) ; spawn\n  scale = Vector2(2, 2)\n\n[node name=\"Child\" type=\"Sprite2D\" parent=\".\"]\n\n[node name=\"Last\" type=\"Node\" parent=\".\"]";
+        let mut scene = source.parse::<Scene>().unwrap();
+        scene.set_node_property(NodePath::from("Child"), "visible", "false").unwrap();
+        scene.set_node_property(NodePath::from("."), "position", "Vector2(3, 4)").unwrap();
+        assert!(scene.set_node_property(NodePath::from("Missing"), "visible", "false").is_err());
+        let root = &mut scene.elements[1];
+        root.insert_property_at(1, "rotation", "0.5").unwrap();
+        assert_eq!(root.insert_property_at(0, "position", "Vector2(0, 0)"), Err(LookupError::AlreadyExists(String::from("position"))));
+        assert_eq!(root.insert_property_at(4, "skew", "0.1"), Err(LookupError::OutOfRange(4)));
+        assert_eq!(scene.remove_node_property(NodePath::from("."), "scale").unwrap().1, "Vector2(2, 2)");
+        assert!(matches!(scene.remove_node_property(NodePath::from("."), "scale"), Err(NodePathError::PropertyNotFound)));
+        scene.elements[3].set_property("text", "\"hi\"");
+
+        let child = &mut scene.elements[2];
+        child.set_data("groups", "[\"enemies\"]");
+        child.insert_data_at(1, "index", "\"0\"").unwrap();
+        assert!(child.insert_data_at(0, "name", "\"Other\"").is_err());
+        assert_eq!(child.remove_data("type").unwrap().1, "\"Sprite2D\"");
+        assert!(child.remove_data("type").is_err());
+        assert_eq!(child.element_data.iter().map(|data| data.0.as_str()).collect::<Vec<&str>>(), vec!["name", "index", "parent", "groups"]);
+
+        let expected = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node2D\"]\nposition = Vector2(3, 4) ; spawn\nrotation = 0.5\n\n[node name=\"Child\" index=\"0\" parent=\".\" groups=[\"enemies\"]]\nvisible = false\n\n[node name=\"Last\" type=\"Node\" parent=\".\"]\ntext = \"hi\"";
+        assert_eq!(scene.to_tscn(), expected);
+        assert_eq!(scene.elements[1].properties.iter().map(|property| property.0.as_str()).collect::<Vec<&str>>(), vec!["position", "rotation"]);
+        // Spans of untouched tokens still point into the original source.
+        assert_eq!(scene.elements[1].property_span("position").map(|span| span.line), Some(4));
+        let reparsed = expected.parse::<Scene>().unwrap();
+        assert_eq!(reparsed.get_node_property(NodePath::from("Last"), "text").unwrap(), "\"hi\"");
+        assert_eq!(reparsed.to_tscn(), expected);
+    }
+
+    #[test]
+    fn instanced_scenes() {
+        let enemy = "[gd_scene load_steps=2 format=3]\n\n[ext_resource type=\"Texture2D\" path=\"res://enemy.png\" id=\"1_tex\"]\n\n[node name=\"Enemy\" type=\"CharacterBody2D\"]\nspeed = 100\nhealth = 3\n\n[node name=\"Sprite2D\" type=\"Sprite2D\" parent=\".\"]\ntexture = ExtResource(\"1_tex\")\nmodulate = Color(1, 0, 0, 1)\n";
+        let boss = "[gd_scene load_steps=2 format=3]\n\n[ext_resource type=\"PackedScene\" path=\"res://enemy.tscn\" id=\"1_enemy\"]\n\n[node name=\"Boss\" instance=ExtResource(\"1_enemy\")]\nhealth = 30\n\n[node name=\"Sprite2D\" parent=\".\" index=\"0\"]\nscale = Vector2(2, 2)\n";
+        let looping = "[gd_scene load_steps=2 format=3]\n\n[ext_resource type=\"PackedScene\" path=\"res://loop.tscn\" id=\"1_loop\"]\n\n[node name=\"Loop\" instance=ExtResource(\"1_loop\")]\n";
+        let level = "[gd_scene load_steps=4 format=3]\n\n[ext_resource type=\"PackedScene\" path=\"res://boss.tscn\" id=\"1_boss\"]\n[ext_resource type=\"PackedScene\" path=\"res://missing.tscn\" id=\"2_missing\"]\n[ext_resource type=\"PackedScene\" path=\"res://loop.tscn\" id=\"3_loop\"]\n\n[node name=\"Level\" type=\"Node2D\"]\n\n[node name=\"Boss\" parent=\".\" instance=ExtResource(\"1_boss\")]\nspeed = 50\n\n[node name=\"Sprite2D\" parent=\"Boss\"]\nmodulate = Color(0, 0, 1, 1)\n\n[node name=\"Label\" type=\"Label\" parent=\"Boss\"]\n\n[node name=\"Later\" parent=\".\" instance_placeholder=\"res://later.tscn\"]\n\n[node name=\"Ghost\" parent=\".\" instance=ExtResource(\"2_missing\")]\n\n[node name=\"Loop\" parent=\".\" instance=ExtResource(\"3_loop\")]\n";
+        let mut scenes:HashMap<String, Rc<Scene>> = HashMap::new();
+        scenes.insert(String::from("res://enemy.tscn"), Rc::new(enemy.parse::<Scene>().unwrap()));
+        scenes.insert(String::from("res://boss.tscn"), Rc::new(boss.parse::<Scene>().unwrap()));
+        scenes.insert(String::from("res://loop.tscn"), Rc::new(looping.parse::<Scene>().unwrap()));
+        let scene = level.parse::<Scene>().unwrap();
+
+        let tree = scene.tree();
+        let boss_node = tree.get_node("Boss").unwrap();
+        assert!(boss_node.is_instance() && !tree.root().unwrap().is_instance());
+        assert_eq!(boss_node.instance_path().as_deref(), Some("res://boss.tscn"));
+        assert_eq!(tree.get_node("Boss/Sprite2D").unwrap().instance_owner(), Some(boss_node));
+        assert_eq!(tree.get_node("Boss/Label").unwrap().instance_owner(), None);
+        assert!(tree.get_node("Later").unwrap().is_placeholder());
+        assert_eq!(tree.get_node("Later").unwrap().placeholder_path().as_deref(), Some("res://later.tscn"));
+        let boss_scene = scenes.load_scene("res://boss.tscn").unwrap();
+        let boss_tree = boss_scene.tree();
+        assert!(boss_tree.root().unwrap().is_instance());
+        assert_eq!(boss_tree.get_node("Sprite2D").unwrap().instance_index(), Some(0));
+
+        // Level overrides Boss, which inherits from Enemy.
+        let properties = scene.effective_properties(NodePath::from("Boss"), &mut scenes).unwrap();
+        assert_eq!(properties.iter().map(|property| (property.0.as_str(), property.1.as_str())).collect::<Vec<(&str, &str)>>(), vec![("speed", "50"), ("health", "30")]);
+        assert!(scene.get_node_property(NodePath::from("Boss"), "health").is_err());
+        assert_eq!(scene.get_effective_property(NodePath::from("Boss/Sprite2D"), "texture", &mut scenes).unwrap(), "ExtResource(\"1_tex\")");
+        assert_eq!(scene.get_effective_property_variant(NodePath::from("Boss/Sprite2D"), "modulate", &mut scenes).unwrap(), Variant::Color(0.0, 0.0, 1.0, 1.0));
+        assert_eq!(scene.get_effective_property_variant(NodePath::from("Boss/Sprite2D"), "scale", &mut scenes).unwrap(), Variant::Vector2(2.0, 2.0));
+        assert!(matches!(scene.get_effective_property(NodePath::from("Boss/Label"), "text", &mut scenes), Err(NodePathError::PropertyNotFound)));
+        assert!(matches!(scene.effective_properties(NodePath::from("Ghost"), &mut scenes), Err(NodePathError::InstanceUnavailable(_, Some(..)))));
+        assert!(matches!(scene.effective_properties(NodePath::from("Loop"), &mut scenes), Err(NodePathError::InstanceUnavailable(_, None))));
+
+        let mut directory = ResDirectory::new("./src");
+        assert_eq!(directory.resolve("res://rooms/Tree.tscn"), Some(Path::new("./src").join("rooms/Tree.tscn")));
+        assert_eq!(directory.resolve("user://save.tscn"), None);
+        let room = directory.load_scene("res://test.tscn").unwrap();
+        assert!(Rc::ptr_eq(&room, &directory.load_scene("res://test.tscn").unwrap()));
+        assert!(room.effective_properties(NodePath::from("Tree12"), &mut directory).unwrap_err().to_string().starts_with("instanced scene res://rooms/reusable/Tree.tscn is unavailable: failed to load"));
+    }
+
+    #[test]
+    fn projects() {
+        let mut project = Project::open("./src/test_project").unwrap();
+        assert_eq!(project.main_scene().as_deref(), Some("res://main.tscn"));
+        assert_eq!(project.autoloads(), vec![(String::from("Globals"), String::from("res://globals.gd"))]);
+        assert_eq!(project.setting("application", "config/name"), Some(Variant::String(String::from("Test Project"))));
+        assert_eq!(project.setting("", "config_version"), Some(Variant::Int(5)));
+        assert_eq!(project.setting("application", "config_version"), None);
+        assert_eq!(project.resolve("res://enemies/enemy.tscn"), Some(Path::new("./src/test_project").join("enemies/enemy.tscn")));
+        assert_eq!(project.res_path(&Path::new("./src/test_project").join("materials/bricks.tres")).as_deref(), Some("res://materials/bricks.tres"));
+        assert!(Project::open("./src").is_err());
+
+        let files = project.files().unwrap();
+        assert!(files.contains(&String::from("res://icon.svg")) && !files.iter().any(|file| file.ends_with(".import") || file.ends_with("project.godot")));
+        assert!(matches!(project.load("res://materials/bricks.tres").unwrap(), Document::Resource(..)));
+        let main = project.load_scene("res://main.tscn").unwrap();
+        assert!(Rc::ptr_eq(&main, &project.load_scene("res://main.tscn").unwrap()));
+        assert_eq!(main.get_effective_property(NodePath::from("Enemy"), "material", &mut project).unwrap(), "ExtResource(\"1_m0a7s\")");
+
+        let graph = project.dependency_graph().unwrap();
+        assert_eq!(graph.instances("res://main.tscn"), vec!["res://enemies/enemy.tscn"]);
+        assert_eq!(graph.instanced_by("res://enemies/enemy.tscn"), vec!["res://main.tscn"]);
+        assert_eq!(graph.shared(), vec![("res://materials/bricks.tres", vec!["res://enemies/enemy.tscn", "res://main.tscn"])]);
+        assert_eq!(graph.cycles(), vec![vec![String::from("res://loop_a.tscn"), String::from("res://loop_b.tscn")]]);
+        assert_eq!(graph.rebuild_set("res://materials/bricks.gdshader").into_iter().collect::<Vec<String>>(), vec!["res://enemies/enemy.tscn", "res://main.tscn", "res://materials/bricks.gdshader", "res://materials/bricks.tres"]);
+        assert_eq!(graph.missing().count(), 0);
+        assert!(graph.failed().is_empty());
+        assert_eq!(project.orphans().unwrap(), vec!["res://loop_a.tscn", "res://loop_b.tscn", "res://materials/unused.tres"]);
+    }
+
+    #[test]
+    fn uids() {
+        let uid = Uid::from_text("uid://cc1e4j7kxw2ys").unwrap();
+        assert_eq!(uid.to_string(), "uid://cc1e4j7kxw2ys");
+        assert_eq!("uid://ba".parse::<Uid>(), Ok(Uid::from_id(34)));
+        assert_eq!(Uid::from_id(33).to_string(), "uid://8");
+        assert_eq!(Uid::from_id(0).to_string(), "uid://a");
+        assert_eq!(Uid::from_text("uid://<invalid>"), None);
+        assert_eq!(Uid::from_text("uid://"), None);
+        assert_eq!(Uid::from_text("res://icon.svg"), None);
+
+        let mut project = Project::open("./src/test_project").unwrap();
+        let index = project.uid_index().unwrap();
+        let path_of = |text:&str| index.path(&Uid::from_text(text).unwrap()).map(String::from);
+        assert_eq!(index.len(), 6);
+        assert_eq!(path_of("uid://cc1e4j7kxw2ys").as_deref(), Some("res://icon.svg"));
+        assert_eq!(path_of("uid://b1xk3n5w8gq2p").as_deref(), Some("res://globals.gd"));
+        assert_eq!(path_of("uid://dq6ie6gldxvbs").as_deref(), Some("res://enemies/enemy.tscn"));
+        assert_eq!(index.uid("res://materials/bricks.gdshader"), Uid::from_text("uid://cu0lqbv1d8rnm"));
+        assert!(index.duplicates().is_empty());
+
+        // Paths follow the UID, other ext resources and the rest of the file are untouched.
+        let source = std::fs::read_to_string("./src/test_project/main.tscn").unwrap();
+        let mut scene = source.parse::<Scene>().unwrap();
+        let mut moved = index.clone();
+        moved.insert(Uid::from_text("uid://dq6ie6gldxvbs").unwrap(), "res://enemies/enemy.tscn");
+        assert!(scene.repair_paths(&moved).is_empty());
+        let mut moved = UidIndex::new();
+        moved.insert(Uid::from_text("uid://dq6ie6gldxvbs").unwrap(), "res://actors/enemy.tscn");
+        let repairs = scene.repair_paths(&moved);
+        assert_eq!(repairs.len(), 1);
+        assert_eq!((repairs[0].id.clone(), &repairs[0].old_path[..], &repairs[0].new_path[..]), (ResourceId::String(String::from("1_e3k1p")), "res://enemies/enemy.tscn", "res://actors/enemy.tscn"));
+        assert_eq!(scene.to_tscn(), source.replace("res://enemies/enemy.tscn", "res://actors/enemy.tscn"));
+        moved.insert(Uid::from_text("uid://dq6ie6gldxvbs").unwrap(), "res://enemy_copy.tscn");
+        assert_eq!(moved.duplicates().len(), 1);
+        assert!(scene.repair_paths(&moved).is_empty());
+
+        // Move files around in a copy of the project, then repair it.
+        fn copy_dir(from:&Path, to:&Path) {
+            std::fs::create_dir_all(to).unwrap();
+            for entry in std::fs::read_dir(from).unwrap() {
+                let entry = entry.unwrap();
+                if entry.path().is_dir() {
+                    copy_dir(&entry.path(), &to.join(entry.file_name()));
+                }
+                else {
+                    std::fs::copy(entry.path(), to.join(entry.file_name())).unwrap();
+                }
+            }
+        }
+        let root = std::env::temp_dir().join(format!("tscn_uids_{}", std::process::id()));
+        copy_dir(Path::new("./src/test_project"), &root);
+        std::fs::create_dir_all(root.join("actors")).unwrap();
+        std::fs::rename(root.join("enemies/enemy.tscn"), root.join("actors/enemy.tscn")).unwrap();
+        std::fs::rename(root.join("materials/bricks.gdshader"), root.join("bricks.gdshader")).unwrap();
+        std::fs::rename(root.join("materials/bricks.gdshader.uid"), root.join("bricks.gdshader.uid")).unwrap();
+        let mut project = Project::open(&root).unwrap();
+        assert_eq!(project.dependency_graph().unwrap().missing().count(), 2);
+        let repaired = project.repair_paths().unwrap();
+        assert_eq!(repaired.iter().map(|(path, repairs)| (&path[..], repairs.len())).collect::<Vec<(&str, usize)>>(), vec![("res://main.tscn", 1), ("res://materials/bricks.tres", 1)]);
+        assert!(std::fs::read_to_string(root.join("main.tscn")).unwrap().contains("path=\"res://actors/enemy.tscn\""));
+        assert_eq!(project.dependency_graph().unwrap().missing().count(), 0);
+        assert_eq!(Project::open(&root).unwrap().dependency_graph().unwrap().missing().count(), 0);
+        assert!(project.repair_paths().unwrap().is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn godot3_conversion() {
+        let table = RenameTable::godot3_to_godot4();
+        let mut scene = Scene::from_tscn_file("./src/test_godot3.tscn").unwrap();
+        let report = scene.upgrade_to_godot4(&table).unwrap();
+        assert_eq!(scene.format(), Format::Godot4);
+        let ext_id = |old:i64| report.ids.iter().find(|(kind, id, _)| *kind == ResourceKind::Ext && *id == ResourceId::Int(old)).map(|(_, _, new_id)| new_id.clone()).unwrap();
+        assert!(matches!(ext_id(2), ResourceId::String(ref id) if id.starts_with("2_") && id.len() == 7));
+        assert!(report.ids.iter().any(|(kind, _, new_id)| *kind == ResourceKind::Sub && matches!(new_id, ResourceId::String(id) if id.starts_with("StandardMaterial3D_"))));
+        let resources = scene.resources();
+        assert!(resources.dangling().is_empty() && resources.unused().is_empty());
+        assert_eq!(resources.get(ResourceKind::Ext, &ext_id(2)).unwrap().resource_type.as_deref(), Some("Texture2D"));
+
+        let tree = scene.tree();
+        let node_type = |path:&str| tree.root().unwrap().get_node(&NodePath::from(path)).unwrap().node_type();
+        assert_eq!((node_type("."), node_type("Sprite"), node_type("Model"), node_type("Model/Mesh")), (Some(String::from("CharacterBody2D")), Some(String::from("Sprite2D")), Some(String::from("Node3D")), Some(String::from("MeshInstance3D"))));
+        assert_eq!(scene.get_node_property(NodePath::from("Sprite"), "texture").unwrap(), format!("ExtResource({})", ext_id(2)));
+        assert_eq!(scene.get_node_property(NodePath::from("Model"), "position").unwrap(), "Vector3(1, 2, 3)");
+        assert_eq!(scene.get_node_property_variant(NodePath::from("."), "metadata/_edit_group_").unwrap(), Variant::Bool(true));
+        assert_eq!(scene.get_node_property(NodePath::from("Label"), "offset_right").unwrap(), "40.0");
+        assert_eq!(scene.get_node_property_variant(NodePath::from("Label"), "rotation").unwrap(), Variant::Float(std::f64::consts::FRAC_PI_2));
+        assert_eq!(scene.get_node_property(NodePath::from("Label"), "theme_override_colors/font_color").unwrap(), "Color(1, 1, 1, 1)");
+        assert_eq!(scene.get_node_property(NodePath::from("Label"), "horizontal_alignment").unwrap(), "1");
+        assert_eq!(scene.get_node_property(NodePath::from("Stats"), "values").unwrap(), "PackedInt32Array(1, 2, 3)");
+        let text = scene.to_tscn();
+        assert!(text.starts_with("[gd_scene load_steps=5 format=3]\n"));
+        assert!(text.contains("size = Vector2(32, 16)") && text.contains("groups=[\"persist\"]]") && text.contains("binds=[1]]"));
+        assert!(text.contains("broken = Vector2( 1 )") && !text.contains("__meta__"));
+        assert!(scene.to_canonical_tscn().contains(&format!("[ext_resource type=\"Texture2D\" path=\"res://icon.png\" id={}]", ext_id(2))));
+
+        // Anything not converted is reported, with sections named as in the Godot 3 file.
+        assert_eq!(report.untranslated.iter().map(|untranslated| untranslated.to_string()).collect::<Vec<String>>(), vec![
+            "node \"Label\": pause_mode needs converting by hand",
+            "node \"Tween\": Tween has no Godot 4 counterpart",
+            "node \"Stats\": couldn't convert broken: invalid arguments for Vector2",
+        ]);
+        assert!(!report.is_complete());
+        assert!(scene.upgrade_to_godot4(&table).is_err());
+
+        // Same ids every time, and the table can be extended.
+        let mut table = RenameTable::godot3_to_godot4();
+        table.rename_class("Tween", "Node");
+        table.rename_property(Some("Node"), "values", "numbers");
+        table.review_property(Some("Sprite"), "region_rect");
+        let mut again = Scene::from_tscn_file("./src/test_godot3.tscn").unwrap();
+        let again_report = again.upgrade_to_godot4(&table).unwrap();
+        assert_eq!(again_report.ids, report.ids);
+        assert_eq!(again.get_node_property(NodePath::from("Stats"), "numbers").unwrap(), "PackedInt32Array(1, 2, 3)");
+        assert_eq!(again.tree().root().unwrap().get_node(&NodePath::from("Tween")).unwrap().node_type().as_deref(), Some("Node"));
+        assert_eq!(again_report.untranslated.len(), 3);
+        assert!(matches!(&again_report.untranslated[0], Untranslated::Property { section, name } if section == "node \"Sprite\"" && name == "region_rect"));
+
+        // An empty table only converts values and ids.
+        let mut plain = Scene::from_tscn_file("./src/test_godot3.tscn").unwrap();
+        let plain_report = plain.upgrade_to_godot4(&RenameTable::new()).unwrap();
+        assert_eq!(plain_report.untranslated.len(), 1);
+        assert_eq!(plain.get_node_property(NodePath::from("Model"), "translation").unwrap(), "Vector3(1, 2, 3)");
+
+        let mut resource = "[gd_resource type=\"SpatialMaterial\" format=2]\n\n[resource]\nalbedo_color = Color( 1, 0, 0, 1 )\n".parse::<Resource>().unwrap();
+        assert_eq!(resource.upgrade_to_godot4(&RenameTable::godot3_to_godot4()), Ok(ConversionReport::default()));
+        assert_eq!(resource.to_tres(), "[gd_resource type=\"StandardMaterial3D\" format=3]\n\n[resource]\nalbedo_color = Color(1, 0, 0, 1)\n");
+    }
+
+    #[test]
+    fn scene_diff() {
+        let old = Scene::from_tscn_file("./src/test_diff/old.tscn").unwrap();
+        let new = Scene::from_tscn_file("./src/test_diff/new.tscn").unwrap();
+        assert!(old.diff(&old).is_empty());
+        let scene = Scene::from_tscn_file("./src/test.tscn").unwrap();
+        assert_eq!(scene.diff(&Scene::from_tscn_file("./src/test.tscn").unwrap()), SceneDiff::default());
+
+        // Renumbered resource ids and nodes following a moved parent aren't changes.
+        let diff = old.diff(&new);
+        assert_eq!(diff.nodes[..2], [
+            NodeChange::Renamed { from: String::from("Sprite"), to: String::from("Graphic") },
+            NodeChange::Moved { from: String::from("Body/Arm"), to: String::from("Arm") },
+        ]);
+        assert_eq!(diff.nodes[3], NodeChange::Added { path: String::from("Gun"), node_type: None, instance: Some(String::from("res://gun.tscn")) });
+        assert_eq!(diff.properties[0].old, Some(Variant::Int(10)));
+        assert_eq!(diff.properties[0].new, Some(Variant::Int(20)));
+        assert_eq!(diff.properties[3].owner, PropertyOwner::SubResource(ResourceId::String(String::from("CircleShape2D_k1x2v"))));
+        assert_eq!(diff.to_string(), "\
+> node \"Sprite\" renamed to \"Graphic\"
+> node \"Body/Arm\" moved to \"Arm\"
+- node \"Old\" (Timer)
++ node \"Gun\" (instance of \"res://gun.tscn\")
+~ node \".\" speed: 10 -> 20
+~ node \".\" hp: (unset) -> 3
+~ node \"Body\" [type]: \"Node2D\" -> \"Node3D\"
+~ sub_resource \"CircleShape2D_k1x2v\" radius: 8.0 -> 12.0
+> ext_resource \"res://icon.svg\" (Texture2D) moved to \"res://art/icon.svg\"
++ ext_resource \"res://gun.tscn\" (PackedScene)
+- connection \"timeout\" from \"Old\" to \".\" method \"_on_timeout\"
+~ connection \"visibility_changed\" from \"Graphic\" to \".\" method \"_on_visibility_changed\": flags (unset) -> 3, binds [] -> []
++ connection \"fired\" from \"Gun\" to \".\" method \"_on_gun_fired\"
+");
+        let json = diff.to_json();
+        assert!(json.starts_with("{\n  \"nodes\": [\n    {\"change\": \"renamed\", \"from\": \"Sprite\", \"to\": \"Graphic\"},\n"));
+        assert!(json.contains("{\"node\": \"Body\", \"name\": \"type\", \"header\": true, \"old\": {\"type\": \"String\", \"value\": \"Node2D\"}, \"new\": {\"type\": \"String\", \"value\": \"Node3D\"}}"));
+        assert!(json.contains("{\"node\": \".\", \"name\": \"hp\", \"header\": false, \"old\": null, \"new\": {\"type\": \"int\", \"value\": 3}}"));
+        assert!(json.contains("{\"change\": \"added\", \"kind\": \"ext\", \"id\": \"3_ddddd\", \"type\": \"PackedScene\", \"path\": \"res://gun.tscn\"}"));
+        assert_eq!(SceneDiff::default().to_json(), "{\n  \"nodes\": [],\n  \"properties\": [],\n  \"resources\": [],\n  \"connections\": []\n}\n");
+
+        // Values of Godot 3 scenes are written the way the file writes them.
+        let godot3 = Scene::from_tscn_file("./src/test_godot3.tscn").unwrap();
+        let mut edited = godot3.clone();
+        edited.set_node_property(NodePath::from("Model"), "translation", "Vector3( 1, 2, 4 )").unwrap();
+        edited.set_node_property(NodePath::from("Sprite"), "texture", "ExtResource( 1 )").unwrap();
+        assert_eq!(godot3.diff(&edited).to_string(), "~ node \"Sprite\" texture: ExtResource( 2 ) -> ExtResource( 1 )\n~ node \"Model\" translation: Vector3( 1, 2, 3 ) -> Vector3( 1, 2, 4 )\n");
+
+        // The other way round.
+        let reverse = new.diff(&old);
+        assert_eq!(reverse.nodes[..2], [
+            NodeChange::Renamed { from: String::from("Graphic"), to: String::from("Sprite") },
+            NodeChange::Moved { from: String::from("Arm"), to: String::from("Body/Arm") },
+        ]);
+        assert_eq!(reverse.properties.len(), diff.properties.len());
+        assert_eq!((reverse.resources.len(), reverse.connections.len()), (2, 3));
+
+        // Renamed root.
+        let mut renamed = Scene::from_tscn_file("./src/test_diff/old.tscn").unwrap();
+        renamed.rename_node(".", "Hero").unwrap();
+        assert_eq!(old.diff(&renamed).to_string(), "~ node \".\" [name]: \"Player\" -> \"Hero\"\n");
+    }
+
+    #[test]
+    fn scene_merge() {
+        let base = Scene::from_tscn_file("./src/test_merge/base.tscn").unwrap();
+        let ours = Scene::from_tscn_file("./src/test_merge/ours.tscn").unwrap();
+        let theirs = Scene::from_tscn_file("./src/test_merge/theirs.tscn").unwrap();
+
+        // Merging with an unchanged side gives the other side.
+        let result = Scene::merge3(&base, &base, &theirs);
+        assert!(result.is_clean() && result.scene.diff(&theirs).is_empty());
+        let result = Scene::merge3(&base, &ours, &base);
+        assert!(result.is_clean() && result.scene.to_tscn() == ours.to_tscn());
+
+        // Only the property both sides set differently conflicts, ours is kept.
+        let result = Scene::merge3(&base, &ours, &theirs);
+        assert_eq!(result.conflicts, vec![MergeConflict::Property {
+            owner: PropertyOwner::Node(String::from("Player")), name: String::from("jump"), header: false,
+            base: Some(Variant::Int(200)), ours: Some(Variant::Int(250)), theirs: Some(Variant::Int(300)), format: Format::Godot4,
+        }]);
+        assert_eq!(result.conflicts[0].to_string(), "node \"Player\" jump: base 200, ours 250, theirs 300");
+        let scene = &result.scene;
+        assert_eq!(scene.get_node_property(NodePath::from("Player"), "speed").unwrap(), "150");
+        assert_eq!(scene.get_node_property(NodePath::from("Player"), "jump").unwrap(), "250");
+        // The same edit on both sides, a move by theirs and a property of a node ours renamed.
+        assert_eq!(scene.get_node_property(NodePath::from("Camera"), "zoom").unwrap(), "Vector2(2, 2)");
+        assert!(scene.tree().get_node("Player/Camera").is_none() && scene.tree().get_node("Enemies/Old").is_none());
+        assert_eq!(scene.get_node_property(NodePath::from("UI/Score"), "text").unwrap(), "\"Score: 0\"");
+
+        // Theirs' resources are copied with the nodes using them, a colliding id is renumbered.
+        let resources = scene.resources();
+        let texture = |path:&str| resources.resolve(&scene.get_node_property_variant(NodePath::from(path), "texture").unwrap()).and_then(|entry| entry.path.clone());
+        assert_eq!(texture("Enemies/Goblin").as_deref(), Some("res://goblin.png"));
+        assert_eq!(texture("Enemies/Bat").as_deref(), Some("res://bat.png"));
+        assert_ne!(scene.get_node_property(NodePath::from("Enemies/Bat"), "texture").unwrap(), "ExtResource(\"2_icon\")");
+        assert_eq!(scene.get_node_property(NodePath::from("Enemies/Bat/Hitbox"), "shape").unwrap(), "SubResource(\"RectangleShape2D_bat\")");
+        let circle = resources.get(ResourceKind::Sub, &ResourceId::String(String::from("CircleShape2D_body"))).unwrap();
+        assert_eq!(resources.element(circle).get_property_value("radius").unwrap(), "12.0");
+        let text = scene.to_tscn();
+        assert!(text.starts_with("[gd_scene load_steps=6 format=3 uid=\"uid://c4mrgbase0k2n\"]\n"));
+        assert!(text.contains("id=\"2_icon\"]\n[ext_resource type=\"Texture2D\" path=\"res://bat.png\""));
+        assert_eq!(scene.connections().iter().map(|connection| (connection.method.as_str(), connection.flags)).collect::<Vec<_>>(), vec![("_on_player_hit", Some(3)), ("_on_ready", None)]);
+        assert!(text.parse::<Scene>().is_ok());
+
+        // Swapping the sides swaps the conflict.
+        let swapped = Scene::merge3(&base, &theirs, &ours);
+        assert!(matches!(&swapped.conflicts[..], [MergeConflict::Property { ours: Some(Variant::Int(300)), theirs: Some(Variant::Int(250)), .. }]));
+        assert!(swapped.scene.diff(scene).nodes.iter().all(|change| matches!(change, NodeChange::Moved { .. })));
+
+        // Removing a node the other side edited keeps ours.
+        let base = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node\"]\n\n[node name=\"A\" type=\"Node\" parent=\".\"]\n".parse::<Scene>().unwrap();
+        let mut edited = base.to_tscn().parse::<Scene>().unwrap();
+        edited.set_node_property(NodePath::from("A"), "x", "1").unwrap();
+        let mut removed = base.to_tscn().parse::<Scene>().unwrap();
+        removed.remove_node("A").unwrap();
+        let result = Scene::merge3(&base, &edited, &removed);
+        assert_eq!(result.conflicts, vec![MergeConflict::Removed { path: String::from("A"), removed_by: MergeSide::Theirs }]);
+        assert!(result.scene.tree().get_node("A").is_some());
+        let result = Scene::merge3(&base, &removed, &edited);
+        assert_eq!(result.conflicts, vec![MergeConflict::Removed { path: String::from("A"), removed_by: MergeSide::Ours }]);
+        assert!(result.scene.tree().get_node("A").is_none());
+
+        // The header gets `load_steps` once theirs adds the first resource.
+        let base = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node2D\"]\n".parse::<Scene>().unwrap();
+        let theirs = "[gd_scene load_steps=2 format=3]\n\n[ext_resource type=\"Script\" path=\"res://root.gd\" id=\"1_aaaaa\"]\n\n[node name=\"Root\" type=\"Node2D\"]\nscript = ExtResource(\"1_aaaaa\")\n".parse::<Scene>().unwrap();
+        let result = Scene::merge3(&base, &base, &theirs);
+        assert!(result.is_clean());
+        assert_eq!(result.scene.to_tscn(), theirs.to_tscn());
+        let result = Scene::merge3(&theirs, &theirs, &base);
+        assert_eq!(result.scene.to_tscn(), base.to_tscn());
+    }
+
+    #[test]
+    fn command_line() {
+        let run = |args:&[&str], stdin:&str| {
+            let args = args.iter().map(|arg| String::from(*arg)).collect::<Vec<String>>();
+            let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+            let code = cli::run(&args, &mut stdin.as_bytes(), &mut stdout, &mut stderr);
+            (code, String::from_utf8(stdout).unwrap(), String::from_utf8(stderr).unwrap())
+        };
+        let source = std::fs::read_to_string("./src/test_merge/base.tscn").unwrap();
+        // Errors name the file the text came from.
+        let error = Scene::from_str_named("[node name=\"A\"\n", Some("level.tscn")).unwrap_err();
+        assert_eq!(error.location().map(|location| location.to_string()), Some(String::from("level.tscn:1:15")));
+        assert!(matches!(Resource::from_str_named("[resource]\nx =\n", Some("a.tres")), Err(SceneError::TokenizerError(TokenizerError::NotFound(..)))));
+        let (code, stdout, _) = run(&["tree", "./src/test_merge/base.tscn"], "");
+        assert_eq!(code, 0);
+        assert_eq!(stdout, "Level (Node2D)\n  Player (CharacterBody2D)\n    Shape (CollisionShape2D)\n    Camera (Camera2D)\n  Enemies (Node2D)\n    Old (Sprite2D)\n  HUD (CanvasLayer)\n    Score (Label)\n");
+        assert_eq!(run(&["get", "-", "Player", "speed"], &source), (0, String::from("100\n"), String::new()));
+        assert_eq!(run(&["get", "-", "Player", "missing"], &source).0, 1);
+
+        // Edits of stdin go to stdout, invalid values are refused.
+        let (code, stdout, _) = run(&["set", "-", "HUD/Score", "text", "\"10\""], &source);
+        assert_eq!(code, 0);
+        assert_eq!(stdout, source.replace("text = \"0\"", "text = \"10\""));
+        let (code, _, stderr) = run(&["set", "-", "Player", "speed", "Vector2(1,"], &source);
+        assert!(code == 2 && stderr.contains("is not a valid value"));
+
+        // Syntax errors with their place, missing resources and connections to missing nodes.
+        assert_eq!(run(&["validate", "./src/test_merge/base.tscn", "./src/test.tres"], ""), (0, String::new(), String::new()));
+        let broken = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node\"]\ntexture = ExtResource(\"1_gone\")\n\n[connection signal=\"ready\" from=\".\" to=\"Nope\" method=\"_on_ready\"]\n\n[editable path=\"Root\" = ]\n";
+        let (code, _, stderr) = run(&["validate", "-"], broken);
+        assert_eq!(code, 1);
+        assert!(stderr.contains("--> <stdin>:8:"), "{}", stderr);
+        assert!(stderr.contains("<stdin>: node \"Root\" texture refers to missing ExtResource(\"1_gone\")"));
+        assert!(stderr.contains("<stdin>: connection \"ready\" of \".\" refers to missing node \"Nope\""));
+
+        let messy = "[gd_scene format=3]\n[node name=\"Root\" type=\"Node2D\" ]\nscale=Vector2( 2,2 )\n";
+        assert_eq!(run(&["fmt", "-"], messy).1, "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node2D\"]\nscale = Vector2(2, 2)\n");
+        assert_eq!(run(&["fmt", "--check", "-"], messy), (1, String::from("<stdin>\n"), String::new()));
+        assert_eq!(run(&["fmt", "--check", "./src/test_merge/base.tscn"], "").0, 0);
+        assert_eq!(run(&["fmt", "--frobnicate", "-"], messy).0, 2);
+
+        let (code, stdout, _) = run(&["diff", "./src/test_diff/old.tscn", "./src/test_diff/new.tscn"], "");
+        assert_eq!((code, stdout), (1, Scene::from_tscn_file("./src/test_diff/old.tscn").unwrap().diff(&Scene::from_tscn_file("./src/test_diff/new.tscn").unwrap()).to_string()));
+        assert!(run(&["diff", "--json", "./src/test_diff/old.tscn", "./src/test_diff/old.tscn"], "").1.contains("\"nodes\": []"));
+
+        assert_eq!(run(&["deps", "./src/test_merge/ours.tscn"], "").1, "res://player.gd (Script)\nres://goblin.png (Texture2D)\n");
+        let (code, stdout, _) = run(&["deps", "./src/test_project"], "");
+        assert_eq!(code, 0);
+        assert!(stdout.contains("res://loop_a.tscn\n  res://loop_b.tscn (PackedScene)\n"));
+
+        // As a merge driver, the result replaces ours.
+        let ours = std::env::temp_dir().join(format!("tscn_merge_{}.tscn", std::process::id()));
+        std::fs::copy("./src/test_merge/ours.tscn", &ours).unwrap();
+        let (code, _, stderr) = run(&["merge", "./src/test_merge/base.tscn", ours.to_str().unwrap(), "./src/test_merge/theirs.tscn"], "");
+        assert_eq!(code, 1);
+        assert!(stderr.ends_with("conflict: node \"Player\" jump: base 200, ours 250, theirs 300\n"));
+        assert!(std::fs::read_to_string(&ours).unwrap().contains("res://bat.png"));
+        std::fs::remove_file(&ours).unwrap();
+        assert_eq!(run(&[], "").0, 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_support() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        enum Kind {
+            Melee,
+            Ranged,
+        }
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Enemy {
+            speed:f32,
+            health:u32,
+            kind:Kind,
+            position:(f64, f64),
+            texture:String,
+            #[serde(rename = "metadata/tags")]
+            tags:Vec<String>,
+            loot:HashMap<String, i64>,
+            #[serde(default)]
+            boss:Option<bool>,
+        }
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Sprite {
+            modulate:[f32; 4],
+        }
+        #[derive(serde::Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Broken {
+            broken:(f64, f64),
+        }
+        let text = "[gd_scene load_steps=2 format=3]\n\n[ext_resource type=\"Texture2D\" path=\"res://enemy.png\" id=\"1_tex\"]\n\n[node name=\"Enemy\" type=\"CharacterBody2D\"]\nspeed = 120.5\nhealth = 3\nkind = 1\nposition = Vector2(4, 8)\ntexture = ExtResource(\"1_tex\")\nmetadata/tags = [\"flying\"]\nloot = {\n\"gold\": 10\n}\n\n[node name=\"Sprite\" type=\"Sprite2D\" parent=\".\"]\nmodulate = Color(1, 0.5, 0, 1)\nbroken = Vector2(1)\n";
+        let enemy = crate::from_str::<Enemy>(text).unwrap();
+        assert_eq!(enemy, Enemy {
+            speed: 120.5, health: 3, kind: Kind::Ranged, position: (4.0, 8.0), texture: String::from("ExtResource(\"1_tex\")"),
+            tags: vec![String::from("flying")], loot: HashMap::from([(String::from("gold"), 10)]), boss: None,
+        });
+        assert_eq!(crate::from_str::<Enemy>(&text.replace("kind = 1", "kind = \"Melee\"")).unwrap().kind, Kind::Melee);
+
+        // Values that don't fit, or can't be parsed, name the property. Unused ones are skipped.
+        let scene = text.parse::<Scene>().unwrap();
+        assert_eq!(crate::from_node::<Sprite>(&scene, "Sprite").unwrap(), Sprite { modulate: [1.0, 0.5, 0.0, 1.0] });
+        assert!(matches!(crate::from_node::<Broken>(&scene, "Sprite"), Err(crate::de::Error::Value(name, _)) if name == "broken"));
+        assert!(matches!(crate::from_node::<Sprite>(&scene, "Missing"), Err(crate::de::Error::NodeNotFound(_))));
+        let error = crate::from_str::<Enemy>(&text.replace("health = 3", "health = -3")).unwrap_err();
+        assert!(error.to_string().starts_with("health: invalid value: integer `-3`"), "{}", error);
+        assert_eq!(crate::from_variant::<Vec<[f64; 2]>>(&Variant::PackedVector2Array(vec![(1.0, 2.0)])).unwrap(), vec![[1.0, 2.0]]);
+        let resource = Resource::from_tres_file("./src/test.tres").unwrap();
+        assert!(crate::from_properties::<HashMap<String, serde_json::Value>>(resource.properties()).is_ok_and(|properties| !properties.is_empty()));
+
+        // The document model and values serialize, and read back to the same scene.
+        assert_eq!(serde_json::to_string(&Variant::Vector2(1.0, 2.0)).unwrap(), "{\"Vector2\":[1.0,2.0]}");
+        assert_eq!(serde_json::to_string(&Variant::ExtResource(ResourceId::String(String::from("1_tex")))).unwrap(), "{\"ExtResource\":\"1_tex\"}");
+        assert_eq!(serde_json::to_string(&Variant::SubResource(ResourceId::Int(2))).unwrap(), "{\"SubResource\":2}");
+        let json = serde_json::to_string(&scene).unwrap();
+        let read = serde_json::from_str::<Scene>(&json).unwrap();
+        assert_eq!(read.to_tscn(), text);
+        assert_eq!(serde_json::to_string(&read).unwrap(), json);
+        let json = serde_json::to_string(&resource).unwrap();
+        assert_eq!(serde_json::from_str::<Resource>(&json).unwrap().to_tres(), resource.to_tres());
+    }
+
+    #[test]
+    fn scene_json() {
+        for file in ["./src/test.tscn", "./src/test_godot3.tscn", "./src/test_multiline.tscn", "./src/test_merge/base.tscn", "./src/test_project/main.tscn"] {
+            let scene = Scene::from_tscn_file(file).unwrap();
+            let read = Scene::from_json(&scene.to_json()).unwrap();
+            assert_eq!(read.to_canonical_tscn(), scene.to_canonical_tscn(), "{}", file);
+            assert_eq!(read.to_json(), scene.to_json());
+        }
+
+        let scene = Scene::from_str("\
+[gd_scene load_steps=2 format=3 uid=\"uid://b1\"]
+
+[ext_resource type=\"PackedScene\" path=\"res://enemy.tscn\" id=\"1_enemy\"]
+
+[node name=\"Root\" type=\"Node2D\"]
+speed = inf
+tags = Array[String]([\"a\", \"b\"])
+weights = PackedFloat32Array(0.1, 2.5)
+input = Object(InputEventKey,\"keycode\":65)
+
+[node name=\"Enemy\" parent=\".\" instance=ExtResource(\"1_enemy\")]
+position = Vector2(1.5, -2)
+
+[node name=\"Lost\" type=\"Node\" parent=\"Missing\"]
+
+[connection signal=\"died\" from=\"Enemy\" to=\".\" method=\"_on_died\" flags=3]
+
+[editable path=\"Enemy\"]
+").unwrap();
+        let json = crate::json::parse(&scene.to_json()).unwrap();
+        let nodes = json.get("nodes").and_then(|nodes| nodes.as_array()).unwrap();
+        assert_eq!(nodes.len(), 2);
+        let enemy = &nodes[0].get("children").and_then(|children| children.as_array()).unwrap()[0];
+        assert_eq!(enemy.get("name").and_then(|name| name.as_str()), Some("Enemy"));
+        assert!(enemy.get("data").unwrap().get("parent").is_none());
+        assert_eq!(crate::json::parse(r#"{"type": "ExtResource", "value": "1_enemy"}"#).ok().as_ref(), enemy.get("data").unwrap().get("instance"));
+        assert_eq!(crate::json::parse(r#"{"type": "Vector2", "value": [1.5, -2.0]}"#).ok().as_ref(), enemy.get("properties").unwrap().get("position"));
+        assert_eq!(nodes[1].get("data").unwrap().get("parent").unwrap().get("value").unwrap().as_str(), Some("Missing"));
+        assert_eq!(json.get("editable").unwrap().as_array().unwrap()[0].get("path").unwrap().as_str(), Some("Enemy"));
+        let root = &nodes[0];
+        assert_eq!(crate::json::parse(r#"{"type": "PackedFloat32Array", "value": [0.1, 2.5]}"#).ok().as_ref(), root.get("properties").unwrap().get("weights"));
+        let read = Scene::from_json(&scene.to_json()).unwrap();
+        assert_eq!(read.to_canonical_tscn(), scene.to_canonical_tscn());
+        assert!(read.to_canonical_tscn().contains("\nweights = PackedFloat32Array(0.1, 2.5)\n"));
+        assert_eq!(read.get_node_property_variant(NodePath::from("Enemy"), "position").ok(), Some(Variant::Vector2(1.5, -2.0)));
+        assert_eq!(read.get_node_property_variant(NodePath::from("."), "speed").ok(), Some(Variant::Float(f64::INFINITY)));
+
+        // JSON written by other tools only needs what it sets.
+        let read = Scene::from_json(r#"{
+            "schema": 1,
+            "header": {"format": {"type": "int", "value": 3}},
+            "nodes": [{"name": "Level", "type": "Node3D", "children": [
+                {"name": "Spawn", "type": "Marker3D", "properties": {"position": {"type": "Vector3", "value": [0, 1, 2]}}}
+            ]}]
+        }"#).unwrap();
+        assert_eq!(read.tree().get_node("Spawn").and_then(|node| node.node_type()), Some(String::from("Marker3D")));
+        assert_eq!(read.to_tscn(), "\
+[gd_scene format=3]
+
+[node name=\"Level\" type=\"Node3D\"]
+
+[node name=\"Spawn\" type=\"Marker3D\" parent=\".\"]
+position = Vector3(0, 1, 2)
+");
+
+        assert!(matches!(Scene::from_json("{\"schema\": 1,"), Err(JsonError::Syntax(_, 13))));
+        assert!(matches!(Scene::from_json("{\"schema\": 2}"), Err(JsonError::Schema(_))));
+        let error = Scene::from_json(r#"{"schema": 1, "nodes": [{"name": "A", "properties": {"p": {"type": "Vector2", "value": [1]}}}]}"#).unwrap_err();
+        assert_eq!(error.to_string(), "node \"A\" p: invalid Vector2 value");
+        let error = Scene::from_json(r#"{"schema": 1, "nodes": [{"name": "A", "properties": {"a b": {"type": "int", "value": 1}}}]}"#).unwrap_err();
+        assert_eq!(error.to_string(), "node \"A\": invalid name \"a b\"");
+    }
+
+    #[test]
+    fn event_reader() {
+        let text = "\
+; Generated
+[gd_scene format=3]
+
+[node name=\"Root\" type=\"Node2D\"]
+points = PackedVector2Array(0, 0,
+  1, 1) ; comment
+visible = false
+";
+        let events = EventReader::new(text.as_bytes()).collect::<Result<Vec<Event>, TokenizerError>>().unwrap();
+        assert_eq!(events, vec![
+            Event::SectionStart(String::from("gd_scene")),
+            Event::Data(ElementData(String::from("format"), String::from("3"))),
+            Event::SectionEnd,
+            Event::SectionStart(String::from("node")),
+            Event::Data(ElementData(String::from("name"), String::from("\"Root\""))),
+            Event::Data(ElementData(String::from("type"), String::from("\"Node2D\""))),
+            Event::Property(Property(String::from("points"), String::from("PackedVector2Array(0, 0,\n  1, 1)"))),
+            Event::Property(Property(String::from("visible"), String::from("false"))),
+            Event::SectionEnd,
+        ]);
+        // Scenes are built from the same events and still write back exactly.
+        let scene = Scene::from_str(text).unwrap();
+        assert_eq!(scene.to_tscn(), text);
+        assert_eq!(scene.elements[1].property_span("visible").map(|span| span.line), Some(7));
+
+        // Strict readers end at the first error, recovering ones skip the broken section and go on.
+        let broken = "[node name=\"A\"]\nx = 1\n[node name=\"B\" \ny = 2\n[node name=\"C\"]\n";
+        let events = EventReader::named(broken.as_bytes(), Some("broken.tscn")).collect::<Vec<Result<Event, TokenizerError>>>();
+        assert!(matches!(events.last(), Some(Err(TokenizerError::InvalidChar('\n', location))) if location.to_string() == "broken.tscn:3:16"));
+        let events = EventReader::recovering(broken.as_bytes(), None).collect::<Vec<Result<Event, TokenizerError>>>();
+        assert_eq!(events.iter().filter(|event| event.is_err()).count(), 1);
+        let sections = events.iter().filter_map(|event| match event {
+            Ok(Event::Data(data)) if data.0 == "name" => Some(data.1.as_str()),
+            _ => None,
+        }).collect::<Vec<&str>>();
+        assert_eq!(sections, ["\"A\"", "\"C\""]);
+
+        // Properties above the first header are a section without a header, not part of the next one.
+        let project = std::fs::read_to_string("./src/test_project/project.godot").unwrap();
+        let events = EventReader::new(project.as_bytes()).take(4).collect::<Result<Vec<Event>, TokenizerError>>().unwrap();
+        assert_eq!(events, vec![
+            Event::Property(Property(String::from("config_version"), String::from("5"))),
+            Event::SectionEnd,
+            Event::SectionStart(String::from("application")),
+            Event::Property(Property(String::from("config/name"), String::from("\"Test Project\""))),
+        ]);
+        let settings = project.parse::<Scene>().unwrap();
+        assert!(settings.elements[0].is_preamble());
+        assert_eq!(settings.elements[0].get_property_value("config_version").unwrap(), "5");
+        assert_eq!(settings.elements[1].element_name, "application");
+        assert!(settings.elements[1].get_property_value("config_version").is_err());
+        assert_eq!(settings.to_tscn(), project);
+        for text in ["x = 1\n", "; notes\n\n", "x = 1"] {
+            let events = EventReader::new(text.as_bytes()).collect::<Result<Vec<Event>, TokenizerError>>().unwrap();
+            assert_eq!(events.last(), Some(&Event::SectionEnd));
+            assert_eq!(Scene::from_str(text).unwrap().to_tscn(), text);
+            let borrowed = SceneRef::parse(text).unwrap();
+            assert_eq!(format!("{:?}", borrowed.to_scene().elements), format!("{:?}", Tokenizer::tokenize_str(text).unwrap().elements));
+        }
+
+        // Events are read a line at a time, without tokenizing the whole file first.
+        let generated = (0..20000).map(|index| format!("[node name=\"N{}\" parent=\".\"]\nposition = Vector2({}, 0)\n\n", index, index)).collect::<String>();
+        let properties = EventReader::new(generated.as_bytes()).filter(|event| matches!(event, Ok(Event::Property(..)))).count();
+        assert_eq!(properties, 20000);
+    }
+
+    #[test]
+    fn borrowed_scene() {
+        // Borrowed scenes read the same elements and tokens as owned ones.
+        let files = ["src/test.tscn", "src/test_godot3.tscn", "src/test_multiline.tscn", "src/test.tres", "src/test_diff/old.tscn", "src/test_merge/theirs.tscn", "src/test_project/enemies/enemy.tscn", "src/test_project/project.godot"];
+        for file in files {
+            let text = std::fs::read_to_string(file).unwrap();
+            let borrowed = SceneRef::parse(&text).unwrap();
+            assert_eq!(borrowed.tokens().iter().map(|token| token.text()).collect::<String>(), text);
+            let owned = Scene::from_str(&text).unwrap();
+            assert_eq!(format!("{:?}", borrowed.to_scene()), format!("{:?}", owned), "{}", file);
+        }
+
+        let text = "[gd_scene format=3]\n\n[node name=\"Root\" type=\"Node2D\"]\ntext = \"say \\\"hi\\\"\" ; greeting\n\n[node name=\"Child\" parent=\".\"]\npath = NodePath(\"../Root\")\nanimation = &\"idle\"";
+        let scene = SceneRef::parse(text).unwrap();
+        assert_eq!(scene.elements().len(), 3);
+        assert_eq!(scene.nodes().map(|node| node.get_data_str("name").unwrap()).collect::<Vec<_>>(), ["Root", "Child"]);
+        let root = &scene.elements()[1];
+        assert_eq!(root.get_property_value("text"), Some("\"say \\\"hi\\\"\""));
+        assert!(matches!(root.get_property_str("text"), Some(Cow::Owned(text)) if text == "say \"hi\""));
+        assert_eq!(scene.element_tokens(root).last(), Some(&TokenRef::NewLine));
+        let child = &scene.elements()[2];
+        assert!(matches!(child.get_data_str("parent"), Some(Cow::Borrowed("."))));
+        assert!(matches!(child.get_property_str("path"), Some(Cow::Borrowed("../Root"))));
+        assert!(matches!(child.get_property_str("animation"), Some(Cow::Borrowed("idle"))));
+        assert_eq!(unquote("\"a\" + \"b\""), None);
+        assert_eq!(scene.to_scene().to_tscn(), text);
+        let crlf = text.replace('\n', "\r\n");
+        let mut scene = SceneRef::parse(&crlf).unwrap().to_scene();
+        scene.add_child(".", "Label", "Label").unwrap();
+        assert!(scene.to_tscn().ends_with("&\"idle\"\r\n\r\n[node name=\"Label\" type=\"Label\" parent=\".\"]\r\n"));
+
+        // Errors point at the same place as the owned tokenizer's.
+        for broken in ["[node name=\"A\"]\nx = 1\n[node name=\"B\" \ny = 2\n", "[node name=\"A\"]\nx = \"open", "x =\n", "[]", "[node name=]\n", "=======\n"] {
+            let borrowed = SceneRef::parse_named(broken, Some("broken.tscn")).unwrap_err();
+            let owned = Tokenizer::tokenize_named(broken.as_bytes(), Some("broken.tscn")).unwrap_err();
+            assert_eq!(format!("{:?}", borrowed), format!("{:?}", owned));
+        }
+    }
 }
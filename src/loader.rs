@@ -1,23 +1,15 @@
-
-use std::{fs::{File}, io::{BufReader, BufRead}};
+use std::{fs::File, io::BufReader};
 
 use crate::scene::{ SceneError };
 
-// Returns Ok((reader, line_count)) or Err(SceneError)
-pub fn load(file_path:&str) -> Result<(BufReader<File>, usize), SceneError> {
-    let f = File::open(file_path);
-    match f {
+// Returns Ok(reader) or Err(SceneError)
+pub fn load(file_path:&str) -> Result<BufReader<File>, SceneError> {
+    match File::open(file_path) {
         Ok(file) => {
-            // Unfortunately we need to open the file twice in order to get the line count without consuming the file ref.
-            let line_count = BufReader::new(
-                    File::open(file_path).expect("An unexpected error occured during file loading.")
-                )
-                .lines()
-                .count();
-            Ok((BufReader::new(file), line_count))
+            Ok(BufReader::new(file))
         },
         Err(file_error) => {
-            Err(SceneError::LoadFailed(file_error))
+            Err(SceneError::LoadFailed(file_path.to_string(), file_error))
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,6 @@
+use std::{env, io, process::ExitCode};
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    ExitCode::from(tscn::cli::run(&args, &mut io::stdin().lock(), &mut io::stdout().lock(), &mut io::stderr().lock()))
+}
@@ -0,0 +1,629 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{connection::Connection, convert, diff::{self, ConnectionChange, NodeChange, PropertyChange, PropertyOwner, ResourceChange, SceneDiff, Side}, element::Element, resource_table::{ResourceEntry, ResourceKind}, scene::Scene, tree, variant::{Format, ResourceId, Variant}, writer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeSide {
+    Ours,
+    Theirs,
+}
+
+// Edits of both sides that can't be combined. Ours is kept in the merged scene, which stays a valid scene either way.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeConflict {
+    // Both sides set the value differently, or added the same node with different values (`base` is `None`).
+    // The owner is given by its path or id in the merged scene.
+    // `format` is the merged scene's, the values are written the way it writes them.
+    Property { owner:PropertyOwner, name:String, header:bool, base:Option<Variant>, ours:Option<Variant>, theirs:Option<Variant>, format:Format },
+    // Both sides moved or renamed the base node at `path`, to different places.
+    Moved { path:String, ours:String, theirs:String },
+    // One side removed the base node at `path` while the other edited it or added a child to it.
+    Removed { path:String, removed_by:MergeSide },
+    // Both sides changed the flags or binds of the same connection differently.
+    Connection { ours:Connection, theirs:Connection, format:Format },
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeConflict::Property { owner, name, header, base, ours, theirs, format } => {
+                let owner = match owner {
+                    PropertyOwner::Node(path) => format!("node {:?}", path),
+                    PropertyOwner::SubResource(id) => format!("sub_resource {:?}", diff::resource_id(id)),
+                };
+                let name = if *header { format!("[{}]", name) } else { name.clone() };
+                let value = |value:&Option<Variant>| value.as_ref().map_or(String::from("(unset)"), |value| value.to_text(*format));
+                let base = if base.is_some() { format!("base {}, ", value(base)) } else { String::from("added by both, ") };
+                write!(f, "{} {}: {}ours {}, theirs {}", owner, name, base, value(ours), value(theirs))
+            },
+            MergeConflict::Moved { path, ours, theirs } => write!(f, "node {:?} moved to {:?} by ours and to {:?} by theirs", path, ours, theirs),
+            MergeConflict::Removed { path, removed_by } => match removed_by {
+                MergeSide::Ours => write!(f, "node {:?} removed by ours and edited by theirs", path),
+                MergeSide::Theirs => write!(f, "node {:?} removed by theirs and edited by ours", path),
+            },
+            MergeConflict::Connection { ours, theirs, format } => {
+                let binds = |connection:&Connection| Variant::Array(connection.binds.clone()).to_text(*format);
+                let flags = |connection:&Connection| connection.flags.map_or(String::from("(unset)"), |flags| flags.to_string());
+                write!(f, "{}: ours flags {} binds {}, theirs flags {} binds {}", diff::describe_connection(ours), flags(ours), binds(ours), flags(theirs), binds(theirs))
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeResult {
+    pub scene:Scene,
+    pub conflicts:Vec<MergeConflict>,
+}
+
+impl MergeResult {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+// Where `path` ends up through the deepest of `moves` (from, to) that contains it.
+fn follow<'m>(path:&str, moves:impl Iterator<Item = (&'m str, &'m str)>) -> String {
+    moves.filter(|(from, _)| tree::is_within(path, from)).max_by_key(|(from, _)| from.len()).and_then(|(from, to)| tree::moved_path(path, from, to)).unwrap_or_else(|| String::from(path))
+}
+
+// The base path of a node at `path` on a side that made `moves`.
+fn to_base(path:&str, moves:&[(String, String)]) -> String {
+    follow(path, moves.iter().map(|(from, to)| (to.as_str(), from.as_str())))
+}
+
+fn moves_of(diff:&SceneDiff) -> Vec<(String, String)> {
+    diff.nodes.iter().filter_map(|change| match change {
+        NodeChange::Moved { from, to } | NodeChange::Renamed { from, to } => Some((from.clone(), to.clone())),
+        _ => None,
+    }).collect::<Vec<(String, String)>>()
+}
+
+// The removed base node `path` is in, if the side removed it.
+fn removed_in<'d>(diff:&'d SceneDiff, path:&str) -> Option<&'d str> {
+    diff.nodes.iter().find_map(|change| match change {
+        NodeChange::Removed { path: removed, .. } if tree::is_within(path, removed) => Some(removed.as_str()),
+        _ => None,
+    })
+}
+
+struct Merge<'a> {
+    scene:Scene,
+    ours:Side<'a>,
+    theirs:Side<'a>,
+    ours_diff:SceneDiff,
+    theirs_diff:SceneDiff,
+    ours_moves:Vec<(String, String)>, // Base path to ours path.
+    theirs_moves:Vec<(String, String)>, // Base path to theirs path.
+    applied:Vec<(String, String)>, // Moves made in the merged scene, in order.
+    added:Vec<(String, String, usize)>, // Theirs and merged path of nodes added from theirs, with the moves applied before.
+    imported:HashMap<(ResourceKind, ResourceId), ResourceId>, // Ids of theirs resources copied into the merged scene.
+    conflicts:Vec<MergeConflict>,
+}
+
+impl<'a> Merge<'a> {
+    fn conflict(&mut self, conflict:MergeConflict) {
+        if !self.conflicts.contains(&conflict) {
+            self.conflicts.push(conflict);
+        }
+    }
+
+    fn exists(&self, path:&str) -> bool {
+        let tree = self.scene.tree();
+        let exists = tree.get_node(path).is_some();
+        exists
+    }
+
+    fn apply_moves(&self, path:&str, start:usize) -> String {
+        let mut path = String::from(path);
+        for (from, to) in self.applied[start..].iter() {
+            if let Some(moved) = tree::moved_path(&path, from, to) {
+                path = moved;
+            }
+        }
+        path
+    }
+
+    // Path in the merged scene of the node at `path` in ours.
+    fn merged_from_ours(&self, path:&str) -> String {
+        self.apply_moves(path, 0)
+    }
+
+    fn merged_from_base(&self, path:&str) -> String {
+        self.merged_from_ours(&follow(path, self.ours_moves.iter().map(|(from, to)| (from.as_str(), to.as_str()))))
+    }
+
+    fn merged_from_theirs(&self, path:&str) -> String {
+        let added = self.added.iter().filter(|(theirs, ..)| tree::is_within(path, theirs)).max_by_key(|(theirs, ..)| theirs.len());
+        match added.and_then(|(theirs, merged, start)| Some((tree::moved_path(path, theirs, merged)?, *start))) {
+            Some((path, start)) => self.apply_moves(&path, start),
+            None => self.merged_from_base(&to_base(path, &self.theirs_moves)),
+        }
+    }
+
+    // Whether ours changed a property of, or added a child under, the base node at `path`.
+    fn edited_by_ours(&self, path:&str) -> bool {
+        let within = |ours_path:&str| tree::is_within(&to_base(ours_path, &self.ours_moves), path);
+        self.ours_diff.properties.iter().any(|change| matches!(&change.owner, PropertyOwner::Node(owner) if within(owner)))
+            || self.ours_diff.nodes.iter().any(|change| matches!(change, NodeChange::Added { path: added, .. } if within(added)))
+    }
+
+    fn same_value(&self, ours:&Option<Variant>, theirs:&Option<Variant>) -> bool {
+        match (ours, theirs) {
+            (Some(ours), Some(theirs)) => diff::equivalent(ours, &self.ours, theirs, &self.theirs, 0),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    // An id for a copy of `entry` that no resource of the merged scene uses.
+    fn free_id(&self, entry:&ResourceEntry) -> ResourceId {
+        let resources = self.scene.resources();
+        let taken = |id:&ResourceId| resources.get(entry.kind, id).is_some() || self.imported.iter().any(|((kind, _), used)| *kind == entry.kind && used == id);
+        if !taken(&entry.id) {
+            return entry.id.clone();
+        }
+        match &entry.id {
+            ResourceId::Int(_) => {
+                let used = resources.entries().iter().filter(|other| other.kind == entry.kind).map(|other| &other.id).chain(self.imported.values());
+                let max = used.filter_map(|id| match id {
+                    ResourceId::Int(int) => Some(*int),
+                    ResourceId::String(_) => None,
+                }).max().unwrap_or(0);
+                ResourceId::Int(max + 1)
+            },
+            ResourceId::String(id) => {
+                // Godot 4 style, `1_abcde` for ext resources and `Type_abcde` for sub resources.
+                let prefix = match entry.kind {
+                    ResourceKind::Ext => (resources.entries().iter().filter(|other| other.kind == ResourceKind::Ext).count() + 1).to_string(),
+                    ResourceKind::Sub => entry.resource_type.clone().unwrap_or_else(|| String::from("Resource")),
+                };
+                let seed = format!("{}:{}", entry.path.as_deref().unwrap_or_default(), id);
+                let mut attempt = 0;
+                loop {
+                    let candidate = ResourceId::String(format!("{}_{}", prefix, convert::unique_id(&format!("{}#{}", seed, attempt))));
+                    if !taken(&candidate) {
+                        break candidate;
+                    }
+                    attempt += 1;
+                }
+            },
+        }
+    }
+
+    // Copies the resource theirs calls `id` into the merged scene, unless it is already there, returning its id there.
+    fn import_resource(&mut self, kind:ResourceKind, id:&ResourceId, depth:usize) -> Option<ResourceId> {
+        if let Some(imported) = self.imported.get(&(kind, id.clone())) {
+            return Some(imported.clone());
+        }
+        let entry = self.theirs.resources.get(kind, id)?.clone();
+        {
+            let resources = self.scene.resources();
+            let existing = match kind {
+                ResourceKind::Ext => resources.entries().iter().find(|other| other.kind == kind && diff::same_file(other, &entry) && other.resource_type == entry.resource_type),
+                // A sub resource from the base is the same one, its changes are merged property by property.
+                // One theirs added is only reused if ours added an identical one under the same id.
+                ResourceKind::Sub => resources.get(kind, id).filter(|other| other.resource_type == entry.resource_type).filter(|other| {
+                    let added = self.theirs_diff.resources.iter().any(|change| matches!(change, ResourceChange::Added(added) if added.kind == kind && added.id == *id));
+                    !added || diff::same_sub_resource(other, &Side::new(&self.scene), &entry, &self.theirs, 0)
+                }),
+            };
+            if let Some(existing) = existing {
+                return Some(existing.id.clone());
+            }
+        }
+        if depth >= diff::MAX_RESOURCE_DEPTH {
+            return None;
+        }
+        let new_id = self.free_id(&entry);
+        self.imported.insert((kind, id.clone()), new_id.clone());
+        let mut element = self.theirs.resources.element(&entry).clone();
+        if new_id != entry.id {
+            let _ = element.update_data("id", &new_id.to_string());
+        }
+        // Sub resources it refers to are copied first, so they come before it in the file.
+        for property in element.properties.clone() {
+            let value = self.import_text(&property.1, depth + 1);
+            if value != property.1 {
+                let _ = element.update_property(&property.0, &value);
+            }
+        }
+        let index = self.scene.section_end(&element.element_name);
+        self.scene.insert_element(index, element);
+        Some(new_id)
+    }
+
+    fn import_value(&mut self, value:&mut Variant, depth:usize) {
+        match value {
+            Variant::ExtResource(id) => {
+                if let Some(new_id) = self.import_resource(ResourceKind::Ext, &id.clone(), depth) {
+                    *id = new_id;
+                }
+            },
+            Variant::SubResource(id) => {
+                if let Some(new_id) = self.import_resource(ResourceKind::Sub, &id.clone(), depth) {
+                    *id = new_id;
+                }
+            },
+            Variant::Array(values) | Variant::TypedArray(_, values) => {
+                for value in values.iter_mut() {
+                    self.import_value(value, depth);
+                }
+            },
+            Variant::Dictionary(pairs) | Variant::TypedDictionary(_, pairs) => {
+                for (key, value) in pairs.iter_mut() {
+                    self.import_value(key, depth);
+                    self.import_value(value, depth);
+                }
+            },
+            Variant::Object(_, pairs) => {
+                for (_, value) in pairs.iter_mut() {
+                    self.import_value(value, depth);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // A value written in theirs, with the resources it refers to brought over. Text is kept as is unless an id changed.
+    fn import_text(&mut self, text:&str, depth:usize) -> String {
+        let Ok(value) = Variant::parse(text) else {
+            return String::from(text);
+        };
+        let mut imported = value.clone();
+        self.import_value(&mut imported, depth);
+        if imported == value {
+            String::from(text)
+        }
+        else {
+            imported.to_text(self.scene.format())
+        }
+    }
+
+    fn theirs_node(&self, path:&str) -> Option<&'a Element> {
+        self.theirs.nodes.iter().find(|(theirs_path, _)| theirs_path == path).map(|(_, element)| *element)
+    }
+
+    // Whether the parent a node is added or moved to exists, and its name isn't held by a node theirs removes later.
+    fn node_ready(&self, change:&NodeChange, pending:&[NodeChange]) -> bool {
+        match change {
+            NodeChange::Added { path, .. } | NodeChange::Moved { to: path, .. } | NodeChange::Renamed { to: path, .. } => {
+                let target = tree::join_path(&self.merged_from_theirs(diff::parent_path(path)), diff::name_of(path));
+                self.exists(&self.merged_from_theirs(diff::parent_path(path))) && !pending.iter().any(|other| {
+                    matches!(other, NodeChange::Removed { path: removed, .. } if self.merged_from_base(removed) == target)
+                })
+            },
+            // Nodes theirs moved out of a removed one go first.
+            NodeChange::Removed { path, .. } => !pending.iter().any(|other| {
+                matches!(other, NodeChange::Moved { from, .. } | NodeChange::Renamed { from, .. } if from != path && tree::is_within(from, path))
+            }),
+        }
+    }
+
+    fn merge_nodes(&mut self) {
+        let mut pending = self.theirs_diff.nodes.clone();
+        loop {
+            let mut progress = false;
+            let mut index = 0;
+            while index < pending.len() {
+                if self.node_ready(&pending[index], &pending) {
+                    let change = pending.remove(index);
+                    self.merge_node(change);
+                    progress = true;
+                }
+                else {
+                    index += 1;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        // Left waiting on a parent that won't appear, most likely one ours removed.
+        for change in pending {
+            self.merge_node(change);
+        }
+    }
+
+    fn merge_node(&mut self, change:NodeChange) {
+        match change {
+            NodeChange::Added { path, .. } => self.add_node(&path),
+            NodeChange::Moved { from, to } | NodeChange::Renamed { from, to } => self.move_node(&from, &to),
+            NodeChange::Removed { path, .. } => {
+                if removed_in(&self.ours_diff, &path).is_some() {
+                    return;
+                }
+                if self.edited_by_ours(&path) {
+                    self.conflict(MergeConflict::Removed { path, removed_by: MergeSide::Theirs });
+                    return;
+                }
+                let merged = self.merged_from_base(&path);
+                let _ = self.scene.remove_node(&merged);
+            },
+        }
+    }
+
+    fn add_node(&mut self, path:&str) {
+        let Some(element) = self.theirs_node(path) else {
+            return;
+        };
+        let parent = self.merged_from_theirs(diff::parent_path(path));
+        if !self.exists(&parent) {
+            let base_parent = to_base(diff::parent_path(path), &self.theirs_moves);
+            if let Some(removed) = removed_in(&self.ours_diff, &base_parent).map(String::from) {
+                self.conflict(MergeConflict::Removed { path: removed, removed_by: MergeSide::Ours });
+            }
+            return;
+        }
+        let merged_path = tree::join_path(&parent, diff::name_of(path));
+        if self.exists(&merged_path) {
+            // Ours added a node at the same place, differing values are conflicts.
+            let mut changes = Vec::new();
+            {
+                let merged = Side::new(&self.scene);
+                if let Some((_, ours_element)) = merged.nodes.iter().find(|(merged, _)| *merged == merged_path) {
+                    diff::compare_elements(PropertyOwner::Node(merged_path.clone()), ours_element, &merged, element, &self.theirs, &mut changes);
+                }
+            }
+            let format = self.scene.format();
+            for PropertyChange { owner, name, header, old, new } in changes {
+                self.conflict(MergeConflict::Property { owner, name, header, base: None, ours: old, theirs: new, format });
+            }
+        }
+        else {
+            let node_type = tree::data_string(element, "type").unwrap_or_default();
+            if self.scene.add_child(&parent, diff::name_of(path), &node_type).is_err() {
+                return;
+            }
+            for data in element.element_data.iter().filter(|data| !["name", "type", "parent"].contains(&&data.0[..])) {
+                let value = self.import_text(&data.1, 0);
+                if let Some(index) = self.node_index(&merged_path) {
+                    self.scene.elements[index].set_data(&data.0, &value);
+                }
+            }
+            for property in element.properties.iter() {
+                let value = self.import_text(&property.1, 0);
+                if let Some(index) = self.node_index(&merged_path) {
+                    self.scene.elements[index].set_property(&property.0, &value);
+                }
+            }
+        }
+        self.added.push((String::from(path), merged_path, self.applied.len()));
+    }
+
+    fn move_node(&mut self, from:&str, to:&str) {
+        if let Some(removed) = removed_in(&self.ours_diff, from).map(String::from) {
+            self.conflict(MergeConflict::Removed { path: removed, removed_by: MergeSide::Ours });
+            return;
+        }
+        if let Some((_, ours_to)) = self.ours_moves.iter().find(|(ours_from, _)| ours_from == from) {
+            let same = diff::name_of(ours_to) == diff::name_of(to)
+                && to_base(diff::parent_path(ours_to), &self.ours_moves) == to_base(diff::parent_path(to), &self.theirs_moves);
+            if !same {
+                let conflict = MergeConflict::Moved { path: String::from(from), ours: ours_to.clone(), theirs: String::from(to) };
+                self.conflict(conflict);
+            }
+            return;
+        }
+        let current = self.merged_from_base(from);
+        let parent = self.merged_from_theirs(diff::parent_path(to));
+        if !self.exists(&parent) {
+            if let Some(removed) = removed_in(&self.ours_diff, &to_base(diff::parent_path(to), &self.theirs_moves)).map(String::from) {
+                self.conflict(MergeConflict::Removed { path: removed, removed_by: MergeSide::Ours });
+            }
+            return;
+        }
+        let mut path = current.clone();
+        if diff::parent_path(&path) != parent {
+            match self.scene.reparent(&path, &parent) {
+                Ok(moved) => path = moved,
+                Err(_) => {
+                    self.conflict(MergeConflict::Moved { path: String::from(from), ours: current, theirs: String::from(to) });
+                    return;
+                },
+            }
+        }
+        if diff::name_of(&path) != diff::name_of(to) {
+            match self.scene.rename_node(&path, diff::name_of(to)) {
+                Ok(renamed) => path = renamed,
+                Err(_) => self.conflict(MergeConflict::Moved { path: String::from(from), ours: current.clone(), theirs: String::from(to) }),
+            }
+        }
+        if path != current {
+            self.applied.push((current, path));
+        }
+    }
+
+    fn node_index(&self, path:&str) -> Option<usize> {
+        let tree = self.scene.tree();
+        let index = tree.get_node(path).and_then(|node| node.element_index());
+        index
+    }
+
+    fn sub_resource_index(&self, id:&ResourceId, resource_type:Option<&String>) -> Option<usize> {
+        let resources = self.scene.resources();
+        resources.get(ResourceKind::Sub, id).filter(|entry| entry.resource_type.as_ref() == resource_type).map(|entry| entry.element)
+    }
+
+    fn merge_properties(&mut self) {
+        for change in self.theirs_diff.properties.clone() {
+            let (source, ours_change, merged_owner) = match &change.owner {
+                PropertyOwner::Node(path) => {
+                    // Nodes theirs added were copied whole.
+                    if self.added.iter().any(|(added, ..)| tree::is_within(path, added)) {
+                        continue;
+                    }
+                    let base_path = to_base(path, &self.theirs_moves);
+                    if let Some(removed) = removed_in(&self.ours_diff, &base_path).map(String::from) {
+                        self.conflict(MergeConflict::Removed { path: removed, removed_by: MergeSide::Ours });
+                        continue;
+                    }
+                    let ours_change = self.ours_diff.properties.iter().find(|ours_change| {
+                        ours_change.name == change.name && ours_change.header == change.header
+                            && matches!(&ours_change.owner, PropertyOwner::Node(ours_path) if to_base(ours_path, &self.ours_moves) == base_path)
+                    });
+                    (self.theirs_node(path), ours_change.cloned(), PropertyOwner::Node(self.merged_from_base(&base_path)))
+                },
+                PropertyOwner::SubResource(id) => {
+                    let ours_change = self.ours_diff.properties.iter().find(|ours_change| ours_change.name == change.name && ours_change.header == change.header && ours_change.owner == change.owner);
+                    let source = self.theirs.resources.get(ResourceKind::Sub, id).map(|entry| self.theirs.resources.element(entry));
+                    (source, ours_change.cloned(), change.owner.clone())
+                },
+            };
+            if let Some(ours_change) = ours_change {
+                if !self.same_value(&ours_change.new, &change.new) {
+                    self.conflict(MergeConflict::Property { owner: merged_owner, name: change.name, header: change.header, base: change.old, ours: ours_change.new, theirs: change.new, format: self.scene.format() });
+                }
+                continue;
+            }
+            let Some(source) = source else {
+                continue;
+            };
+            let text = match change.header {
+                true => source.get_data_value(&change.name).ok(),
+                false => source.get_property_value(&change.name).ok(),
+            };
+            // Resources are imported before looking the element up, copying them moves elements around.
+            let value = text.map(|text| self.import_text(&text, 0));
+            let index = match &merged_owner {
+                PropertyOwner::Node(path) => self.node_index(path),
+                PropertyOwner::SubResource(id) => self.sub_resource_index(id, tree::data_string(source, "type").as_ref()),
+            };
+            let Some(index) = index else {
+                continue;
+            };
+            let element = &mut self.scene.elements[index];
+            match (value, change.header) {
+                (Some(value), true) => element.set_data(&change.name, &value),
+                (Some(value), false) => element.set_property(&change.name, &value),
+                (None, true) => {
+                    let _ = element.remove_data(&change.name);
+                },
+                (None, false) => {
+                    let _ = element.remove_property(&change.name);
+                },
+            }
+        }
+    }
+
+    fn merge_resources(&mut self) {
+        let format = self.scene.format();
+        for change in self.theirs_diff.resources.clone() {
+            match change {
+                ResourceChange::Moved { old, new } => {
+                    let index = {
+                        let resources = self.scene.resources();
+                        let index = resources.entries().iter().find(|entry| entry.kind == ResourceKind::Ext && diff::same_file(entry, &old) && entry.path == old.path).map(|entry| entry.element);
+                        index
+                    };
+                    if let (Some(index), Some(path)) = (index, new.path) {
+                        let _ = self.scene.elements[index].update_data("path", &Variant::String(path).to_text(format));
+                    }
+                },
+                // Kept while anything in the merged scene still uses it.
+                ResourceChange::Removed(removed) => {
+                    let index = {
+                        let resources = self.scene.resources();
+                        let index = resources.unused().into_iter().find(|entry| entry.kind == removed.kind && entry.resource_type == removed.resource_type && match removed.kind {
+                            ResourceKind::Ext => diff::same_file(entry, &removed),
+                            ResourceKind::Sub => entry.id == removed.id,
+                        }).map(|entry| entry.element);
+                        index
+                    };
+                    if let Some(index) = index {
+                        self.scene.remove_element(index);
+                    }
+                },
+                // Copied along with the values using them.
+                ResourceChange::Added(_) => {},
+            }
+        }
+    }
+
+    fn merge_connections(&mut self) {
+        let format = self.scene.format();
+        let ours_changed = self.ours_diff.connections.iter().filter_map(|change| match change {
+            ConnectionChange::Changed { new, .. } => Some(new.clone()),
+            _ => None,
+        }).collect::<Vec<Connection>>();
+        for change in self.theirs_diff.connections.clone() {
+            let mut connection = match &change {
+                ConnectionChange::Added(connection) | ConnectionChange::Removed(connection) | ConnectionChange::Changed { new: connection, .. } => connection.clone(),
+            };
+            let theirs_binds = connection.binds.clone();
+            connection.from = self.merged_from_theirs(&connection.from);
+            connection.to = self.merged_from_theirs(&connection.to);
+            let mut binds = Variant::Array(connection.binds.clone());
+            self.import_value(&mut binds, 0);
+            if let Variant::Array(binds) = binds {
+                connection.binds = binds;
+            }
+            let ours_change = ours_changed.iter().map(|ours| {
+                let mut ours = ours.clone();
+                ours.from = self.merged_from_ours(&ours.from);
+                ours.to = self.merged_from_ours(&ours.to);
+                ours
+            }).find(|ours| ours.is_same(&connection));
+            match change {
+                ConnectionChange::Added(_) => {
+                    if self.exists(&connection.from) && self.exists(&connection.to) {
+                        let _ = self.scene.connect(connection);
+                    }
+                },
+                ConnectionChange::Removed(_) => {
+                    if ours_change.is_none() {
+                        let _ = self.scene.disconnect(&connection);
+                    }
+                },
+                ConnectionChange::Changed { .. } => {
+                    if let Some(ours) = ours_change {
+                        if ours.flags != connection.flags || ours.unbinds != connection.unbinds || !diff::all_equivalent(&ours.binds, &self.ours, &theirs_binds, &self.theirs, 0) {
+                            self.conflict(MergeConflict::Connection { ours, theirs: connection, format: self.scene.format() });
+                        }
+                        continue;
+                    }
+                    let index = self.scene.elements.iter().position(|element| Connection::from_element(element).is_some_and(|existing| existing.is_same(&connection)));
+                    if let Some(index) = index {
+                        self.scene.elements[index] = connection.to_element(format);
+                        self.scene.ensure_section_break(index);
+                    }
+                },
+            }
+        }
+    }
+
+    // `load_steps` counts the resources plus the scene itself, Godot leaves it out when there are none.
+    fn update_header(&mut self) {
+        let load_steps = writer::load_steps(&self.scene.elements);
+        let Some(header) = self.scene.elements.iter_mut().find(|element| element.element_name == "gd_scene" || element.element_name == "gd_resource") else {
+            return;
+        };
+        writer::set_load_steps(header, load_steps);
+    }
+}
+
+// Applies the changes theirs made to base onto ours. Changes only one side made are taken as they are, and so are
+// the same changes made on both sides. Resources theirs added are copied over, with new ids if ours uses theirs.
+pub(crate) fn merge3(base:&Scene, ours:&Scene, theirs:&Scene) -> MergeResult {
+    let ours_diff = base.diff(ours);
+    let theirs_diff = base.diff(theirs);
+    let mut merge = Merge {
+        scene: ours.clone(),
+        ours: Side::new(ours),
+        theirs: Side::new(theirs),
+        ours_moves: moves_of(&ours_diff),
+        theirs_moves: moves_of(&theirs_diff),
+        ours_diff,
+        theirs_diff,
+        applied: Vec::new(),
+        added: Vec::new(),
+        imported: HashMap::new(),
+        conflicts: Vec::new(),
+    };
+    merge.merge_nodes();
+    merge.merge_properties();
+    merge.merge_resources();
+    merge.merge_connections();
+    merge.update_header();
+    MergeResult { scene: merge.scene, conflicts: merge.conflicts }
+}
@@ -0,0 +1,401 @@
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, fs, io, path::{Path, PathBuf}, rc::Rc};
+
+use crate::{loader, element::Element, instance::{self, SceneSource}, resource::Resource, scene::{Scene, SceneError}, tokenizer::Tokenizer, tree::data_string, uid::{self, PathRepair, Uid, UidIndex}, variant::Variant};
+
+// A loaded `.tscn` or `.tres` file.
+#[derive(Debug, Clone)]
+pub enum Document {
+    Scene(Rc<Scene>),
+    Resource(Rc<Resource>),
+}
+
+impl Document {
+    pub fn elements(&self) -> &[Element] {
+        match self {
+            Document::Scene(scene) => &scene.elements,
+            Document::Resource(resource) => &resource.elements,
+        }
+    }
+
+    pub fn uid(&self) -> Option<Uid> {
+        uid::header_uid(self.elements())
+    }
+
+    // The files this one's `[ext_resource]` sections point at.
+    pub fn dependencies(&self) -> Vec<Dependency> {
+        self.elements().iter().filter(|element| element.element_name == "ext_resource").filter_map(|element| {
+            Some(Dependency { path: data_string(element, "path")?, resource_type: data_string(element, "type") })
+        }).collect::<Vec<Dependency>>()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Dependency {
+    pub path:String, // `res://` path.
+    pub resource_type:Option<String>, // `PackedScene` for instanced scenes.
+}
+
+// A Godot project: the directory holding `project.godot`, which `res://` paths are relative to.
+// Scenes and resources are loaded the first time they are asked for.
+#[derive(Debug)]
+pub struct Project {
+    root:PathBuf,
+    settings:Vec<Element>,
+    documents:HashMap<String, Document>,
+}
+
+// Files that belong to the editor or the import system rather than to the game.
+fn is_project_metadata(relative_path:&str) -> bool {
+    relative_path == "project.godot" || relative_path == "export_presets.cfg" || relative_path.ends_with(".import") || relative_path.ends_with(".uid")
+}
+
+// The UID an `.import` file gives its source file, in its `[remap]` section.
+fn import_uid(text:&str) -> Option<Uid> {
+    let tokenizer = Tokenizer::tokenize(text.as_bytes()).ok()?;
+    let remap = tokenizer.elements.iter().find(|element| element.element_name == "remap")?;
+    Uid::from_text(remap.get_property_variant("uid").ok()?.as_str()?)
+}
+
+impl Project {
+    pub fn open(directory:impl Into<PathBuf>) -> Result<Self, SceneError> {
+        let root = directory.into();
+        let config_path = root.join("project.godot").to_string_lossy().into_owned();
+        let reader = loader::load(&config_path)?;
+        let tokenizer = Tokenizer::tokenize_named(reader, Some(&config_path)).map_err(SceneError::TokenizerError)?;
+        Ok(Project { root, settings: tokenizer.elements, documents: HashMap::new() })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // A value from `project.godot`, e.g. `setting("application", "run/main_scene")`. Values above the first section,
+    // like `config_version`, are in section `""`.
+    pub fn setting(&self, section:&str, key:&str) -> Option<Variant> {
+        let section = self.settings.iter().find(|element| element.element_name == section)?;
+        section.get_property_variant(key).ok()
+    }
+
+    pub fn main_scene(&self) -> Option<String> {
+        self.setting("application", "run/main_scene")?.as_str().map(String::from)
+    }
+
+    // Autoloaded scripts and scenes as (name, `res://` path).
+    pub fn autoloads(&self) -> Vec<(String, String)> {
+        match self.settings.iter().find(|element| element.element_name == "autoload") {
+            Some(section) => section.properties.iter().filter_map(|property| {
+                let path = property.value().ok()?.as_str()?.trim_start_matches('*').to_string();
+                Some((property.0.clone(), path))
+            }).collect::<Vec<(String, String)>>(),
+            None => Vec::new(),
+        }
+    }
+
+    // Filesystem path of a `res://` path, `None` for paths outside the project.
+    pub fn resolve(&self, res_path:&str) -> Option<PathBuf> {
+        instance::res_to_file(&self.root, res_path)
+    }
+
+    // `res://` path of a file inside the project directory.
+    pub fn res_path(&self, file_path:&Path) -> Option<String> {
+        let relative = file_path.strip_prefix(&self.root).ok()?;
+        let segments = relative.components().map(|component| component.as_os_str().to_string_lossy().into_owned()).collect::<Vec<String>>();
+        Some(format!("res://{}", segments.join("/")))
+    }
+
+    // Every game file in the project as a `res://` path, skipping hidden directories such as `.godot`.
+    pub fn files(&self) -> Result<Vec<String>, SceneError> {
+        Ok(self.all_files()?.into_iter().filter(|res_path| !is_project_metadata(&res_path["res://".len()..])).collect::<Vec<String>>())
+    }
+
+    // Like `files`, but with `project.godot` and the `.import` and `.uid` sidecars.
+    fn all_files(&self) -> Result<Vec<String>, SceneError> {
+        let mut files = Vec::new();
+        let mut directories = vec![self.root.clone()];
+        while let Some(directory) = directories.pop() {
+            let entries = fs::read_dir(&directory).map_err(|error| SceneError::LoadFailed(directory.to_string_lossy().into_owned(), error))?;
+            for entry in entries {
+                let entry = entry.map_err(|error| SceneError::LoadFailed(directory.to_string_lossy().into_owned(), error))?;
+                let path = entry.path();
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                if path.is_dir() {
+                    directories.push(path);
+                }
+                else if let Some(res_path) = self.res_path(&path) {
+                    files.push(res_path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    // Whether `res_path` is a `.tscn` or `.tres` file, the kinds `load` reads.
+    pub fn is_document(res_path:&str) -> bool {
+        res_path.ends_with(".tscn") || res_path.ends_with(".tres")
+    }
+
+    pub fn load(&mut self, res_path:&str) -> Result<Document, SceneError> {
+        if let Some(document) = self.documents.get(res_path) {
+            return Ok(document.clone());
+        }
+        let file_path = match self.resolve(res_path) {
+            Some(file_path) if Project::is_document(res_path) => file_path.to_string_lossy().into_owned(),
+            _ => return Err(SceneError::LoadFailed(String::from(res_path), io::Error::from(io::ErrorKind::InvalidInput))),
+        };
+        let document = if res_path.ends_with(".tscn") {
+            Document::Scene(Rc::new(Scene::from_tscn_file(&file_path)?))
+        }
+        else {
+            Document::Resource(Rc::new(Resource::from_tres_file(&file_path)?))
+        };
+        self.documents.insert(String::from(res_path), document.clone());
+        Ok(document)
+    }
+
+    // Loads every scene and resource in the project and anything they reference.
+    // Files that fail to load are recorded in the graph instead of stopping it.
+    pub fn dependency_graph(&mut self) -> Result<DependencyGraph, SceneError> {
+        let mut graph = DependencyGraph::default();
+        let mut pending = self.files()?;
+        pending.reverse();
+        while let Some(path) = pending.pop() {
+            if graph.dependencies.contains_key(&path) || graph.failed.iter().any(|(failed, _)| *failed == path) {
+                continue;
+            }
+            if !self.resolve(&path).is_some_and(|file_path| file_path.exists()) {
+                graph.missing.insert(path.clone());
+            }
+            if !Project::is_document(&path) || graph.missing.contains(&path) {
+                graph.dependencies.insert(path, Vec::new());
+                continue;
+            }
+            match self.load(&path) {
+                Ok(document) => {
+                    let dependencies = document.dependencies();
+                    pending.extend(dependencies.iter().map(|dependency| dependency.path.clone()));
+                    graph.dependencies.insert(path, dependencies);
+                },
+                Err(error) => {
+                    graph.failed.push((path, error));
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    // Every UID in the project: from scene and resource headers, `.uid` files next to scripts and shaders,
+    // and the `[remap]` section of `.import` files. Files that fail to parse are left out.
+    pub fn uid_index(&mut self) -> Result<UidIndex, SceneError> {
+        let mut index = UidIndex::new();
+        for res_path in self.all_files()? {
+            let found = if Project::is_document(&res_path) {
+                self.load(&res_path).ok().and_then(|document| document.uid()).map(|uid| (uid, res_path.as_str()))
+            }
+            else if let Some(owner) = res_path.strip_suffix(".uid") {
+                self.read_file(&res_path).and_then(|text| Uid::from_text(&text)).map(|uid| (uid, owner))
+            }
+            else if let Some(owner) = res_path.strip_suffix(".import") {
+                self.read_file(&res_path).and_then(|text| import_uid(&text)).map(|uid| (uid, owner))
+            }
+            else {
+                None
+            };
+            if let Some((uid, owner)) = found {
+                index.insert(uid, owner);
+            }
+        }
+        Ok(index)
+    }
+
+    fn read_file(&self, res_path:&str) -> Option<String> {
+        fs::read_to_string(self.resolve(res_path)?).ok()
+    }
+
+    // Rewrites stale `path=` entries of every scene and resource whose ext resources have a UID that now
+    // belongs to another file, saving the files that changed. Returns the repairs made in each file.
+    pub fn repair_paths(&mut self) -> Result<Vec<(String, Vec<PathRepair>)>, SceneError> {
+        let index = self.uid_index()?;
+        let mut repaired = Vec::new();
+        for res_path in self.files()?.into_iter().filter(|res_path| Project::is_document(res_path)) {
+            let file_path = match self.resolve(&res_path) {
+                Some(file_path) => file_path.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            // Read again rather than from the cache, the documents there are shared.
+            let (document, repairs, text) = if res_path.ends_with(".tscn") {
+                let mut scene = Scene::from_tscn_file(&file_path)?;
+                let repairs = scene.repair_paths(&index);
+                let text = scene.to_tscn();
+                (Document::Scene(Rc::new(scene)), repairs, text)
+            }
+            else {
+                let mut resource = Resource::from_tres_file(&file_path)?;
+                let repairs = resource.repair_paths(&index);
+                let text = resource.to_tres();
+                (Document::Resource(Rc::new(resource)), repairs, text)
+            };
+            if !repairs.is_empty() {
+                fs::write(&file_path, text).map_err(|error| SceneError::SaveFailed(file_path.clone(), error))?;
+                self.documents.insert(res_path.clone(), document);
+                repaired.push((res_path, repairs));
+            }
+        }
+        Ok(repaired)
+    }
+
+    // Files nothing reachable from the main scene, the autoloads or the project icon depends on.
+    pub fn orphans(&mut self) -> Result<Vec<String>, SceneError> {
+        let graph = self.dependency_graph()?;
+        let mut roots = self.autoloads().into_iter().map(|(_, path)| path).collect::<Vec<String>>();
+        roots.extend(self.main_scene());
+        roots.extend(self.setting("application", "config/icon").and_then(|icon| icon.as_str().map(String::from)));
+        let reachable = graph.reachable(&roots);
+        Ok(self.files()?.into_iter().filter(|path| !reachable.contains(path)).collect::<Vec<String>>())
+    }
+}
+
+impl SceneSource for Project {
+    fn load_scene(&mut self, res_path:&str) -> Result<Rc<Scene>, SceneError> {
+        match self.load(res_path)? {
+            Document::Scene(scene) => Ok(scene),
+            Document::Resource(_) => Err(SceneError::LoadFailed(String::from(res_path), io::Error::from(io::ErrorKind::InvalidData))),
+        }
+    }
+}
+
+// Which project files depend on which, keyed by `res://` path.
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    dependencies:BTreeMap<String, Vec<Dependency>>,
+    missing:BTreeSet<String>, // Referenced but not on disk.
+    failed:Vec<(String, SceneError)>, // Scenes and resources that couldn't be read.
+}
+
+impl DependencyGraph {
+    // Every file in the graph, in path order.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.dependencies.keys().map(|path| path.as_str())
+    }
+
+    pub fn dependencies(&self, path:&str) -> &[Dependency] {
+        self.dependencies.get(path).map_or(&[], |dependencies| &dependencies[..])
+    }
+
+    // Files that depend on `path` directly.
+    pub fn dependents(&self, path:&str) -> Vec<&str> {
+        self.dependencies.iter().filter(|(_, dependencies)| dependencies.iter().any(|dependency| dependency.path == path)).map(|(file, _)| file.as_str()).collect::<Vec<&str>>()
+    }
+
+    // Scenes that `scene` instances.
+    pub fn instances(&self, scene:&str) -> Vec<&str> {
+        self.dependencies(scene).iter().filter(|dependency| dependency.resource_type.as_deref() == Some("PackedScene")).map(|dependency| dependency.path.as_str()).collect::<Vec<&str>>()
+    }
+
+    // Scenes that instance `scene`.
+    pub fn instanced_by(&self, scene:&str) -> Vec<&str> {
+        self.dependencies.iter().filter(|(_, dependencies)| {
+            dependencies.iter().any(|dependency| dependency.path == scene && dependency.resource_type.as_deref() == Some("PackedScene"))
+        }).map(|(file, _)| file.as_str()).collect::<Vec<&str>>()
+    }
+
+    // Files used by more than one other file, with their dependents.
+    pub fn shared(&self) -> Vec<(&str, Vec<&str>)> {
+        self.files().map(|path| (path, self.dependents(path))).filter(|(_, dependents)| dependents.len() > 1).collect::<Vec<(&str, Vec<&str>)>>()
+    }
+
+    pub fn missing(&self) -> impl Iterator<Item = &str> {
+        self.missing.iter().map(|path| path.as_str())
+    }
+
+    pub fn failed(&self) -> &[(String, SceneError)] {
+        &self.failed
+    }
+
+    // `changed` and every file depending on it, directly or not: what needs rebuilding when it changes.
+    pub fn rebuild_set(&self, changed:&str) -> BTreeSet<String> {
+        let mut set = BTreeSet::from([String::from(changed)]);
+        let mut pending = vec![String::from(changed)];
+        while let Some(path) = pending.pop() {
+            for dependent in self.dependents(&path) {
+                if set.insert(String::from(dependent)) {
+                    pending.push(String::from(dependent));
+                }
+            }
+        }
+        set
+    }
+
+    // `roots` and everything they depend on.
+    pub fn reachable(&self, roots:&[String]) -> BTreeSet<String> {
+        let mut set = BTreeSet::new();
+        let mut pending = roots.to_vec();
+        while let Some(path) = pending.pop() {
+            if set.insert(path.clone()) {
+                pending.extend(self.dependencies(&path).iter().map(|dependency| dependency.path.clone()));
+            }
+        }
+        set
+    }
+
+    // Groups of files that depend on each other in a loop, which Godot fails to load.
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let mut cycles = Vec::new();
+        let mut state = Tarjan::default();
+        for path in self.dependencies.keys() {
+            if !state.index.contains_key(path.as_str()) {
+                self.strong_connect(path, &mut state, &mut cycles);
+            }
+        }
+        cycles
+    }
+
+    // Tarjan's strongly connected components, components with more than one file or a self reference are cycles.
+    fn strong_connect<'a>(&'a self, path:&'a str, state:&mut Tarjan<'a>, cycles:&mut Vec<Vec<String>>) {
+        let index = state.index.len();
+        state.index.insert(path, index);
+        state.low_link.insert(path, index);
+        state.stack.push(path);
+        state.on_stack.insert(path);
+        for dependency in self.dependencies(path) {
+            let next = dependency.path.as_str();
+            if !self.dependencies.contains_key(next) {
+                continue;
+            }
+            if !state.index.contains_key(next) {
+                self.strong_connect(next, state, cycles);
+                let low_link = state.low_link[path].min(state.low_link[next]);
+                state.low_link.insert(path, low_link);
+            }
+            else if state.on_stack.contains(next) {
+                let low_link = state.low_link[path].min(state.index[next]);
+                state.low_link.insert(path, low_link);
+            }
+        }
+        if state.low_link[path] == index {
+            let mut component = Vec::new();
+            while let Some(member) = state.stack.pop() {
+                state.on_stack.remove(member);
+                component.push(String::from(member));
+                if member == path {
+                    break;
+                }
+            }
+            let self_reference = self.dependencies(path).iter().any(|dependency| dependency.path == path);
+            if component.len() > 1 || self_reference {
+                component.sort();
+                cycles.push(component);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Tarjan<'a> {
+    index:HashMap<&'a str, usize>,
+    low_link:HashMap<&'a str, usize>,
+    stack:Vec<&'a str>,
+    on_stack:BTreeSet<&'a str>,
+}
@@ -0,0 +1,163 @@
+use std::{io::{BufRead, BufReader, Read}, str::FromStr};
+
+use crate::{loader, writer};
+use crate::convert::{self, ConversionError, ConversionReport, RenameTable};
+use crate::element::{Element, ElementType, Property};
+use crate::events::{self, EventReader};
+use crate::resource_table::ResourceTable;
+use crate::scene::{self, NodePathError, SceneError};
+use crate::tokenizer::Tokenizer;
+use crate::tree::data_string;
+use crate::uid::{self, PathRepair, UidIndex};
+use crate::variant::{Format, ResourceId, Variant};
+
+// A `.tres` text resource: a `[gd_resource]` header, ext and sub resources, and the `[resource]` section itself.
+#[derive(Debug)]
+pub struct Resource {
+    pub elements:Vec<Element>,
+    pub tokenizer:Tokenizer, // What the file was read with, the tokens themselves are kept by `elements`.
+}
+
+impl Resource {
+    pub fn header(&self) -> Option<&Element> {
+        self.elements.iter().find(|element| element.element_name == "gd_resource")
+    }
+
+    // The class of the main resource, e.g. `StandardMaterial3D`.
+    pub fn resource_type(&self) -> Option<String> {
+        data_string(self.header()?, "type")
+    }
+
+    pub fn script_class(&self) -> Option<String> {
+        data_string(self.header()?, "script_class")
+    }
+
+    pub fn uid(&self) -> Option<String> {
+        data_string(self.header()?, "uid")
+    }
+
+    // The `[resource]` section holding the main resource's properties.
+    pub fn main_resource(&self) -> Option<&Element> {
+        self.elements.iter().find(|element| element.element_type == ElementType::MAIN_RESOURCE)
+    }
+
+    pub fn properties(&self) -> &[Property] {
+        match self.main_resource() {
+            Some(element) => &element.properties,
+            None => &[],
+        }
+    }
+
+    pub fn get_property(&self, property_name:&str) -> Result<String, NodePathError> {
+        self.main_resource().ok_or(NodePathError::PropertyNotFound)?.get_property_value(property_name)
+    }
+
+    pub fn get_property_variant(&self, property_name:&str) -> Result<Variant, NodePathError> {
+        self.main_resource().ok_or(NodePathError::PropertyNotFound)?.get_property_variant(property_name)
+    }
+
+    pub fn resources(&self) -> ResourceTable<'_> {
+        ResourceTable::new(&self.elements)
+    }
+
+    // Converts a Godot 3 (`format=2`) resource to Godot 4 (`format=3`), `Err` if it isn't a Godot 3 one.
+    pub fn upgrade_to_godot4(&mut self, table:&RenameTable) -> Result<ConversionReport, ConversionError> {
+        convert::upgrade_elements(&mut self.elements, table)
+    }
+
+    // Rewrites `path=` of ext resources whose UID now belongs to another file.
+    pub fn repair_paths(&mut self, index:&UidIndex) -> Vec<PathRepair> {
+        uid::repair_paths(&mut self.elements, index)
+    }
+
+    pub fn ext_resources(&self) -> Vec<&Element> {
+        self.elements.iter().filter(|element| element.element_name == "ext_resource").collect::<Vec<&Element>>()
+    }
+
+    pub fn sub_resources(&self) -> Vec<&Element> {
+        self.elements.iter().filter(|element| element.element_name == "sub_resource").collect::<Vec<&Element>>()
+    }
+
+    pub fn get_ext_resource(&self, id:&ResourceId) -> Option<&Element> {
+        self.ext_resources().into_iter().find(|element| element.get_data_value("id").is_ok_and(|value| ResourceId::from_data_value(&value) == *id))
+    }
+
+    pub fn get_sub_resource(&self, id:&ResourceId) -> Option<&Element> {
+        self.sub_resources().into_iter().find(|element| element.get_data_value("id").is_ok_and(|value| ResourceId::from_data_value(&value) == *id))
+    }
+
+    // Follows an `ExtResource(..)` or `SubResource(..)` value to the element it points at.
+    pub fn get_referenced(&self, value:&Variant) -> Option<&Element> {
+        match value {
+            Variant::ExtResource(id) => self.get_ext_resource(id),
+            Variant::SubResource(id) => self.get_sub_resource(id),
+            _ => None,
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        scene::file_format(&self.elements)
+    }
+
+    pub fn to_tres(&self) -> String {
+        scene::elements_to_text(&self.elements)
+    }
+
+    pub fn to_canonical_tres(&self) -> String {
+        writer::write_elements(&self.elements, self.format())
+    }
+
+    pub fn from_tres_file(file_path:&str) -> Result<Self, SceneError> {
+        let reader = loader::load(file_path)?;
+        Resource::from_events(EventReader::named(reader, Some(file_path)))
+    }
+
+    pub fn from_reader(reader:impl Read) -> Result<Self, SceneError> {
+        Resource::from_events(EventReader::new(BufReader::new(reader)))
+    }
+
+    // Like `from_str`, with `file_name` reported in error locations.
+    pub fn from_str_named(string:&str, file_name:Option<&str>) -> Result<Self, SceneError> {
+        Resource::from_events(EventReader::named(string.as_bytes(), file_name))
+    }
+
+    fn from_events(events:EventReader<impl BufRead>) -> Result<Self, SceneError> {
+        let (elements, tokenizer, errors) = events::read_elements(events);
+        match errors.into_iter().next() {
+            Some(error) => Err(SceneError::TokenizerError(error)),
+            None => Ok(Self { elements, tokenizer }),
+        }
+    }
+}
+
+impl FromStr for Resource {
+    type Err = SceneError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Resource::from_str_named(string, None)
+    }
+}
+
+// Serialized as its elements, like `Scene`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Resource {
+    fn serialize<S:serde::Serializer>(&self, serializer:S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("Resource", 1)?;
+        state.serialize_field("elements", &self.elements)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Resource {
+    fn deserialize<D:serde::Deserializer<'de>>(deserializer:D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            elements:Vec<Element>,
+        }
+        let elements = Fields::deserialize(deserializer)?.elements;
+        let tokenizer = Tokenizer::tokenize_str(&scene::elements_to_text(&elements)).map_err(serde::de::Error::custom)?;
+        Ok(Resource { elements, tokenizer })
+    }
+}
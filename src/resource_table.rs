@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use crate::{element::Element, tree::data_string, variant::{ResourceId, Variant}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResourceKind {
+    Ext, // `[ext_resource]`, a separate file referenced with `ExtResource(id)`.
+    Sub, // `[sub_resource]`, embedded in the file and referenced with `SubResource(id)`.
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceEntry {
+    pub kind:ResourceKind,
+    pub id:ResourceId,
+    pub resource_type:Option<String>,
+    pub path:Option<String>, // `res://` path of external resources.
+    pub uid:Option<String>,
+    pub element:usize, // Index into the elements the table was built from.
+}
+
+// An `ExtResource(..)` or `SubResource(..)` value found in an element.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceReference {
+    pub element:usize,
+    pub name:String, // The property, or header data such as `instance`, holding the reference.
+    pub kind:ResourceKind,
+    pub id:ResourceId,
+}
+
+// Ext and sub resources of a scene or resource file keyed by id, with every reference to them.
+#[derive(Debug)]
+pub struct ResourceTable<'a> {
+    elements:&'a [Element],
+    entries:Vec<ResourceEntry>,
+    ids:HashMap<(ResourceKind, ResourceId), usize>,
+    references:Vec<ResourceReference>,
+}
+
+// Collects the resources referenced anywhere inside `value`, including nested arrays and dictionaries.
+fn collect_references(value:&Variant, found:&mut Vec<(ResourceKind, ResourceId)>) {
+    match value {
+        Variant::ExtResource(id) => found.push((ResourceKind::Ext, id.clone())),
+        Variant::SubResource(id) => found.push((ResourceKind::Sub, id.clone())),
+        Variant::Array(values) | Variant::TypedArray(_, values) => {
+            for value in values.iter() {
+                collect_references(value, found);
+            }
+        },
+        Variant::Dictionary(pairs) | Variant::TypedDictionary(_, pairs) => {
+            for (key, value) in pairs.iter() {
+                collect_references(key, found);
+                collect_references(value, found);
+            }
+        },
+        Variant::Object(_, properties) => {
+            for (_, value) in properties.iter() {
+                collect_references(value, found);
+            }
+        },
+        _ => {}
+    }
+}
+
+fn references_in(text:&str) -> Vec<(ResourceKind, ResourceId)> {
+    let mut found = Vec::new();
+    // Most values can't hold a reference, skip parsing them.
+    if text.contains("Resource") {
+        if let Ok(value) = Variant::parse(text) {
+            collect_references(&value, &mut found);
+        }
+    }
+    found
+}
+
+impl<'a> ResourceTable<'a> {
+    pub fn new(elements:&'a [Element]) -> Self {
+        let mut table = ResourceTable { elements, entries: Vec::new(), ids: HashMap::new(), references: Vec::new() };
+        for (element_index, element) in elements.iter().enumerate() {
+            let kind = match &element.element_name[..] {
+                "ext_resource" => Some(ResourceKind::Ext),
+                "sub_resource" => Some(ResourceKind::Sub),
+                _ => None,
+            };
+            if let (Some(kind), Ok(id)) = (kind, element.get_data_value("id")) {
+                let id = ResourceId::from_data_value(&id);
+                table.ids.entry((kind, id.clone())).or_insert(table.entries.len());
+                table.entries.push(ResourceEntry {
+                    kind,
+                    id,
+                    resource_type: data_string(element, "type"),
+                    path: data_string(element, "path"),
+                    uid: data_string(element, "uid"),
+                    element: element_index,
+                });
+            }
+            let names_and_values = element.element_data.iter().map(|data| (&data.0, &data.1)).chain(element.properties.iter().map(|property| (&property.0, &property.1)));
+            for (name, value) in names_and_values {
+                for (kind, id) in references_in(value) {
+                    table.references.push(ResourceReference { element: element_index, name: name.clone(), kind, id });
+                }
+            }
+        }
+        table
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Entries in file order.
+    pub fn entries(&self) -> &[ResourceEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, kind:ResourceKind, id:&ResourceId) -> Option<&ResourceEntry> {
+        self.ids.get(&(kind, id.clone())).map(|&index| &self.entries[index])
+    }
+
+    // The entry an `ExtResource(..)` or `SubResource(..)` value points at.
+    pub fn resolve(&self, value:&Variant) -> Option<&ResourceEntry> {
+        match value {
+            Variant::ExtResource(id) => self.get(ResourceKind::Ext, id),
+            Variant::SubResource(id) => self.get(ResourceKind::Sub, id),
+            _ => None,
+        }
+    }
+
+    pub fn element(&self, entry:&ResourceEntry) -> &'a Element {
+        &self.elements[entry.element]
+    }
+
+    // Every reference in the file, in file order.
+    pub fn references(&self) -> &[ResourceReference] {
+        &self.references
+    }
+
+    pub fn references_to(&self, entry:&ResourceEntry) -> Vec<&ResourceReference> {
+        self.references.iter().filter(|reference| reference.kind == entry.kind && reference.id == entry.id).collect::<Vec<&ResourceReference>>()
+    }
+
+    // Elements holding at least one reference to `entry`, each listed once.
+    pub fn referencing_elements(&self, entry:&ResourceEntry) -> Vec<usize> {
+        let mut elements = self.references_to(entry).iter().map(|reference| reference.element).collect::<Vec<usize>>();
+        elements.dedup();
+        elements
+    }
+
+    // References to ids that no `[ext_resource]` or `[sub_resource]` declares.
+    pub fn dangling(&self) -> Vec<&ResourceReference> {
+        self.references.iter().filter(|reference| self.get(reference.kind, &reference.id).is_none()).collect::<Vec<&ResourceReference>>()
+    }
+
+    // Declared resources nothing in the file refers to.
+    pub fn unused(&self) -> Vec<&ResourceEntry> {
+        self.entries.iter().filter(|entry| !self.references.iter().any(|reference| reference.kind == entry.kind && reference.id == entry.id)).collect::<Vec<&ResourceEntry>>()
+    }
+}
@@ -1,69 +1,293 @@
 
-use std::{io};
+use std::{fmt, io::{self, BufRead, BufReader, Read}, str::FromStr};
 
-use crate::loader;
-use crate::tokenizer::{Token, Tokenizer, TokenizerError, };
-use crate::element::{Element, ElementType,};
+use crate::{loader, writer};
+use crate::connection::Connection;
+use crate::events::{self, EventReader};
+use crate::convert::{self, ConversionError, ConversionReport, RenameTable};
+use crate::diff::SceneDiff;
+use crate::instance::SceneSource;
+use crate::interchange::{self, JsonError};
+use crate::merge::{self, MergeResult};
+use crate::tokenizer::{Location, Token, Tokenizer, TokenizerError, };
+use crate::element::{Element, ElementData, ElementType, Property};
+use crate::resource_table::{ResourceEntry, ResourceTable};
+use crate::tree::{self, data_string, SceneTree};
+use crate::uid::{self, PathRepair, UidIndex};
+use crate::variant::{Format, Variant, VariantError};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Scene {
     pub elements:Vec<Element>,
-    pub tokenizer:Tokenizer,
+    pub tokenizer:Tokenizer, // What the file was read with, the tokens themselves are kept by `elements`.
 }
 
 #[derive(Debug)]
 pub enum SceneError {
     TokenizerError(TokenizerError),
-    LoadFailed(io::Error),
+    LoadFailed(String, io::Error), // 0: File path
+    SaveFailed(String, io::Error), // 0: File path
     UnexpectedErr,
 }
 
+impl SceneError {
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            SceneError::TokenizerError(error) => Some(error.location()),
+            _ => None,
+        }
+    }
+
+    // The error message, followed by a caret-annotated snippet of `source` when the error has a location.
+    pub fn render(&self, source:&str) -> String {
+        match self {
+            SceneError::TokenizerError(error) => error.render(source),
+            _ => format!("error: {}", self),
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::TokenizerError(error) => write!(f, "{}", error),
+            SceneError::LoadFailed(path, error) => write!(f, "failed to load {}: {}", path, error),
+            SceneError::SaveFailed(path, error) => write!(f, "failed to save {}: {}", path, error),
+            SceneError::UnexpectedErr => write!(f, "unexpected error"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::TokenizerError(error) => Some(error),
+            SceneError::LoadFailed(_, error) | SceneError::SaveFailed(_, error) => Some(error),
+            SceneError::UnexpectedErr => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum NodePathError {
     NodeNotFound,
     PropertyNotFound,
+    InvalidValue(VariantError),
+    NameTaken(String), // A sibling already has this name.
+    InvalidEdit(String), // 0: Why the edit isn't possible, e.g. reparenting a node under itself.
+    InstanceUnavailable(String, Option<Box<SceneError>>), // 0: Instance value or scene path, 1: Why loading it failed.
 }
 
-#[derive(Debug)]
+impl fmt::Display for NodePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodePathError::NodeNotFound => write!(f, "node not found"),
+            NodePathError::PropertyNotFound => write!(f, "property not found"),
+            NodePathError::InvalidValue(error) => write!(f, "invalid property value: {}", error),
+            NodePathError::NameTaken(name) => write!(f, "a sibling is already named {}", name),
+            NodePathError::InvalidEdit(reason) => write!(f, "invalid edit: {}", reason),
+            NodePathError::InstanceUnavailable(instance, Some(error)) => write!(f, "instanced scene {} is unavailable: {}", instance, error),
+            NodePathError::InstanceUnavailable(instance, None) => write!(f, "instanced scene {} is unavailable", instance),
+        }
+    }
+}
+
+impl std::error::Error for NodePathError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NodePathError::InvalidValue(error) => Some(error),
+            NodePathError::InstanceUnavailable(_, Some(error)) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub enum NodePathStatus {
+    #[default]
     VALID,
     INVALID(String),
 }
 
-#[derive(Debug)]
+// A Godot NodePath such as `../Sibling`, `/root/Main/Player`, `%UniqueName` or `Sprite2D:position:x`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct NodePath {
-    path: Vec<String>,
-    node_name: String,
+    absolute: bool,
+    names: Vec<String>, // Node names, including `.`, `..` and `%UniqueName` segments.
+    subnames: Vec<String>, // Property names after the first `:`.
     status: NodePathStatus,
 }
 
 impl NodePath {
     fn return_invalid(reason:&str) -> NodePath {
-        NodePath { path: Vec::default(), node_name: String::default(), status: NodePathStatus::INVALID(reason.into()) }
+        NodePath { status: NodePathStatus::INVALID(reason.into()), ..NodePath::default() }
+    }
+
+    pub fn new(absolute:bool, names:Vec<String>, subnames:Vec<String>) -> NodePath {
+        NodePath { absolute, names, subnames, status: NodePathStatus::VALID }
+    }
+
+    pub fn status(&self) -> &NodePathStatus {
+        &self.status
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.status == NodePathStatus::VALID
+    }
+
+    pub fn is_absolute(&self) -> bool {
+        self.absolute
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.absolute && self.names.is_empty() && self.subnames.is_empty()
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn subnames(&self) -> &[String] {
+        &self.subnames
+    }
+
+    // The node part without subnames, e.g. `Sprite2D` for `Sprite2D:position:x`.
+    pub fn concatenated_names(&self) -> String {
+        let names = self.names.join("/");
+        if self.absolute { "/".to_owned() + &names } else { names }
+    }
+
+    // The property part, e.g. `position:x` for `Sprite2D:position:x`.
+    pub fn concatenated_subnames(&self) -> String {
+        self.subnames.join(":")
+    }
+
+    // Only the node part, dropping any property subnames.
+    pub fn without_subnames(&self) -> NodePath {
+        NodePath { subnames: Vec::new(), ..self.clone() }
+    }
+
+    // Removes `.` segments and `name/..` pairs, like Godot's `NodePath::simplified`.
+    pub fn simplified(&self) -> NodePath {
+        let mut names:Vec<String> = Vec::new();
+        for name in self.names.iter() {
+            match &name[..] {
+                "." => {},
+                ".." => {
+                    if names.last().is_some_and(|last| last != "..") {
+                        names.pop();
+                    }
+                    else if !self.absolute {
+                        names.push(name.clone());
+                    }
+                },
+                _ => {
+                    names.push(name.clone());
+                }
+            }
+        }
+        if names.is_empty() && !self.absolute && !self.names.is_empty() {
+            names.push(String::from("."));
+        }
+        NodePath { names, ..self.clone() }
     }
 }
 
 impl From<&str> for NodePath {
     fn from(string: &str) -> Self {
-        if string.contains('.') {
-            return NodePath::return_invalid("Path cannot be relative.")
-        }
-        let mut path = string.split(|c| c == '/' || c == '\\').map(|string| string.to_string()).collect::<Vec<String>>();
-        let mut node_name:String = String::new();
-        match path.pop() {
-            Some(name) => {
-                node_name = name;
-            },
-            None => {
-                return NodePath::return_invalid("Path not formatted correctly.")
+        let (absolute, string) = match string.strip_prefix('/') {
+            Some(rest) => (true, rest),
+            None => (false, string),
+        };
+        let (path, properties) = match string.split_once(':') {
+            Some((path, properties)) => (path, Some(properties)),
+            None => (string, None),
+        };
+        let names = path.split('/').filter(|name| !name.is_empty()).map(|name| name.to_string()).collect::<Vec<String>>();
+        if names.iter().any(|name| name.starts_with('%') && name.len() == 1) {
+            return NodePath::return_invalid("Unique name is empty.")
+        }
+        let mut subnames = Vec::new();
+        if let Some(properties) = properties {
+            subnames = properties.split(':').map(|name| name.to_string()).collect::<Vec<String>>();
+            if subnames.iter().any(|name| name.is_empty()) {
+                return NodePath::return_invalid("Property name is empty.")
+            }
+        }
+        NodePath::new(absolute, names, subnames)
+    }
+}
+
+impl fmt::Display for NodePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.concatenated_names())?;
+        for subname in self.subnames.iter() {
+            write!(f, ":{}", subname)?;
+        }
+        Ok(())
+    }
+}
+
+// Writes elements back using their tokens, shared by scenes and resources.
+pub(crate) fn elements_to_text(elements:&[Element]) -> String {
+    let mut tokens:Vec<Token> = Vec::new();
+    for element in elements.iter() {
+        if element.tokens.is_empty() {
+            // Elements built in code have no source formatting to keep.
+            let mut element = element.clone();
+            element.force_update_tokens();
+            tokens.append(&mut element.tokens);
+        }
+        else {
+            tokens.extend(element.tokens.iter().cloned());
+        }
+    }
+    Tokenizer::reconstruct_tscn_from_tokens(tokens)
+}
+
+pub(crate) fn file_format(elements:&[Element]) -> Format {
+    for element in elements.iter() {
+        if element.element_name == "gd_scene" || element.element_name == "gd_resource" {
+            if let Ok(Variant::Int(format)) = element.get_data_variant("format") {
+                return Format::from_file_format(format);
             }
         }
-        NodePath { path, node_name, status: NodePathStatus::VALID }
+    }
+    Format::Godot4
+}
+
+// How many instanced scenes deep property lookups follow before assuming a cycle.
+const MAX_INSTANCE_DEPTH:usize = 64;
+
+// A `NodePath` property and the node it points at, both as paths from the root.
+struct NodePathLink {
+    holder:String,
+    property:String,
+    target:String,
+}
+
+// Godot doesn't allow these characters in node names.
+fn check_node_name(name:&str) -> Result<(), NodePathError> {
+    if name.is_empty() || name.contains(['.', ':', '@', '/', '"', '%']) {
+        return Err(NodePathError::InvalidEdit(format!("invalid node name {:?}", name)));
+    }
+    Ok(())
+}
+
+// Whether the tokens end with an empty line, a `\r` whitespace before the last `NewLine` is part of the line break.
+fn ends_with_blank_line(tokens:&[Token]) -> bool {
+    match tokens {
+        [.., Token::NewLine, Token::NewLine] => true,
+        [.., Token::NewLine, Token::Whitespace(whitespace), Token::NewLine] => whitespace == "\r",
+        _ => false,
     }
 }
 
 impl Scene {
-    pub fn filter_elements(elements:&Vec<Element>, element_type:ElementType) -> Vec<&Element> {
+    pub fn filter_elements(elements:&[Element], element_type:ElementType) -> Vec<&Element> {
         elements.iter().filter(|element| element.element_type == element_type).collect::<Vec<&Element>>()
     }
 
@@ -71,47 +295,570 @@ impl Scene {
         self.elements.append(&mut elements);
     }
 
-    pub fn get_node_property(&self, node_path:NodePath, property_name:&str) -> Result<String, NodePathError> {
-        let parent:String = if !node_path.path.is_empty() { "\"".to_owned() + &node_path.path.join("/") + "\""  } else { ".".into() };
-        println!("{}", parent);
-        for node in Scene::filter_elements(&self.elements, ElementType::NODE).into_iter() {
-            if let Ok(node_parent) = node.get_data_value("parent") {
-                println!("{}", node_parent);
-                if node_parent == parent {
-                    println!("FOUND OPARENT");
-                    if let Ok(node_name) = node.get_data_value("name") {
-                        println!("{}", Tokenizer::reconstruct_tscn_from_tokens(node.tokens.clone()));
-                        if node_name == "\"".to_owned() + &node_path.node_name + "\"" {
-                            return node.get_property_value(property_name);
-                        }
+    pub fn tree(&self) -> SceneTree<'_> {
+        SceneTree::new(self)
+    }
+
+    pub fn resources(&self) -> ResourceTable<'_> {
+        ResourceTable::new(&self.elements)
+    }
+
+    // What changed from this scene to `other`, see `SceneDiff`.
+    pub fn diff(&self, other:&Scene) -> SceneDiff {
+        SceneDiff::new(self, other)
+    }
+
+    // Combines the changes `ours` and `theirs` each made to `base`, see `MergeConflict` for the changes that can't be.
+    pub fn merge3(base:&Scene, ours:&Scene, theirs:&Scene) -> MergeResult {
+        merge::merge3(base, ours, theirs)
+    }
+
+    // Converts a Godot 3 (`format=2`) scene to Godot 4 (`format=3`), see `RenameTable`. The original layout is
+    // kept, `to_canonical_tscn` writes the scene the way Godot 4 would. `Err` if the scene isn't a Godot 3 one.
+    pub fn upgrade_to_godot4(&mut self, table:&RenameTable) -> Result<ConversionReport, ConversionError> {
+        convert::upgrade_elements(&mut self.elements, table)
+    }
+
+    // Rewrites `path=` of ext resources whose UID now belongs to another file.
+    pub fn repair_paths(&mut self, index:&UidIndex) -> Vec<PathRepair> {
+        uid::repair_paths(&mut self.elements, index)
+    }
+
+    // Paths, in the form used by `parent=`, of the nodes that reference `entry` in a property or header.
+    pub fn nodes_referencing(&self, entry:&ResourceEntry) -> Vec<String> {
+        let elements = self.resources().referencing_elements(entry);
+        let tree = self.tree();
+        tree.depth_first().filter(|node| node.element_index().is_some_and(|index| elements.contains(&index))).map(|node| node.path()).collect::<Vec<String>>()
+    }
+
+    // Resolves `node_path` relative to the root node, returning the index of its element.
+    fn find_node_index(&self, node_path:&NodePath) -> Result<usize, NodePathError> {
+        let tree = self.tree();
+        match tree.root().and_then(|root| root.get_node(node_path)).and_then(|node| node.element_index()) {
+            Some(index) => Ok(index),
+            None => Err(NodePathError::NodeNotFound),
+        }
+    }
+
+    fn find_node(&self, node_path:&NodePath) -> Result<&Element, NodePathError> {
+        Ok(&self.elements[self.find_node_index(node_path)?])
+    }
+
+    // Godot separates sections with a blank line, except consecutive ext_resource, connection and editable lines.
+    // The last element ends with a single line break.
+    pub(crate) fn ensure_section_break(&mut self, index:usize) {
+        let grouped = match (self.elements.get(index), self.elements.get(index + 1)) {
+            (Some(element), Some(next)) => element.element_name == next.element_name && writer::is_grouped(&element.element_name),
+            (Some(_), None) => true,
+            _ => return,
+        };
+        let crlf = self.tokenizer.crlf();
+        let element = &mut self.elements[index];
+        if element.tokens.is_empty() {
+            element.force_update_tokens();
+            if crlf {
+                element.use_crlf();
+            }
+        }
+        if grouped {
+            while ends_with_blank_line(&element.tokens) {
+                element.tokens.pop();
+                if element.tokens.last() == Some(&Token::Whitespace(String::from('\r'))) {
+                    element.tokens.pop();
+                }
+            }
+        }
+        else {
+            while !ends_with_blank_line(&element.tokens) {
+                if crlf {
+                    element.tokens.push(Token::Whitespace(String::from('\r')));
+                }
+                element.tokens.push(Token::NewLine);
+            }
+        }
+    }
+
+    pub(crate) fn insert_element(&mut self, index:usize, mut element:Element) {
+        if self.tokenizer.crlf() {
+            if element.tokens.is_empty() {
+                element.force_update_tokens();
+            }
+            element.use_crlf();
+        }
+        self.elements.insert(index, element);
+        if index > 0 {
+            self.ensure_section_break(index - 1);
+        }
+        self.ensure_section_break(index);
+    }
+
+    pub(crate) fn remove_element(&mut self, index:usize) -> Element {
+        let element = self.elements.remove(index);
+        if index > 0 {
+            self.ensure_section_break(index - 1);
+        }
+        element
+    }
+
+    // Moves the elements at `indices` (ascending) so they sit before the element currently at `position`.
+    fn move_elements(&mut self, indices:&[usize], position:usize) {
+        let mut moving = Vec::new();
+        for &index in indices.iter().rev() {
+            moving.push(self.elements.remove(index));
+        }
+        moving.reverse();
+        let position = position - indices.iter().filter(|&&index| index < position).count();
+        let count = moving.len();
+        self.elements.splice(position..position, moving);
+        // Only the elements between the old and new places can have different neighbours.
+        let first = indices.first().copied().unwrap_or(position).min(position).saturating_sub(1);
+        let last = indices.last().copied().unwrap_or(position).max(position + count);
+        for index in first..=last.min(self.elements.len().saturating_sub(1)) {
+            self.ensure_section_break(index);
+        }
+    }
+
+    // Where new elements named `element_name` go: after the existing ones, before later kinds of section.
+    pub(crate) fn section_end(&self, element_name:&str) -> usize {
+        let rank = writer::section_rank(element_name);
+        self.elements.iter().position(|element| writer::section_rank(&element.element_name) > rank).unwrap_or(self.elements.len())
+    }
+
+    pub fn connections(&self) -> Vec<Connection> {
+        self.elements.iter().filter_map(Connection::from_element).collect::<Vec<Connection>>()
+    }
+
+    // Connections of signals emitted by the node at `node_path`, given in the form used by `parent=`.
+    pub fn connections_from(&self, node_path:&str) -> Vec<Connection> {
+        self.connections().into_iter().filter(|connection| connection.from == node_path).collect::<Vec<Connection>>()
+    }
+
+    // Connections calling a method on the node at `node_path`.
+    pub fn connections_to(&self, node_path:&str) -> Vec<Connection> {
+        self.connections().into_iter().filter(|connection| connection.to == node_path).collect::<Vec<Connection>>()
+    }
+
+    // Adds a `[connection]` after the existing ones, `Err` if the same connection already exists.
+    pub fn connect(&mut self, connection:Connection) -> Result<(), NodePathError> {
+        if self.connections().iter().any(|existing| existing.is_same(&connection)) {
+            return Err(NodePathError::InvalidEdit(String::from("the connection already exists")));
+        }
+        let index = self.section_end("connection");
+        let element = connection.to_element(self.format());
+        self.insert_element(index, element);
+        Ok(())
+    }
+
+    // Removes the connection with the same signal, source, target and method as `connection`.
+    pub fn disconnect(&mut self, connection:&Connection) -> Result<(), NodePathError> {
+        let index = self.elements.iter().position(|element| Connection::from_element(element).is_some_and(|existing| existing.is_same(connection)));
+        match index {
+            Some(index) => {
+                self.remove_element(index);
+                Ok(())
+            },
+            None => Err(NodePathError::InvalidEdit(String::from("the connection doesn't exist")))
+        }
+    }
+
+    // Points `from` and `to` at the new path of a node that was renamed or moved from `old_path`, including its descendants.
+    pub fn retarget_connections(&mut self, old_path:&str, new_path:&str) {
+        let format = self.format();
+        for element in self.elements.iter_mut().filter(|element| element.element_type == ElementType::CONNECTION) {
+            for data_name in ["from", "to"] {
+                let moved = data_string(element, data_name).and_then(|path| tree::moved_path(&path, old_path, new_path));
+                if let Some(moved) = moved {
+                    let _ = element.update_data(data_name, &Variant::String(moved).to_text(format));
+                }
+            }
+        }
+    }
+
+    // `NodePath` properties and the nodes they point at, recorded before an edit so the ones it breaks can be repaired.
+    fn node_path_links(&self) -> Vec<NodePathLink> {
+        let tree = self.tree();
+        let mut links = Vec::new();
+        for node in tree.depth_first() {
+            let Some(element) = node.element() else {
+                continue;
+            };
+            for property in element.properties.iter().filter(|property| property.1.contains("NodePath")) {
+                if let Ok(Variant::NodePath(path)) = property.value() {
+                    if let Some(target) = node.get_node(&NodePath::from(&path[..])) {
+                        links.push(NodePathLink { holder: node.path(), property: property.0.clone(), target: target.path() });
                     }
                 }
             }
         }
-        Err(NodePathError::NodeNotFound)
+        links
     }
 
-    pub fn to_tscn(self) -> String {
-        let mut tokens:Vec<Token> = Vec::new();
-        for mut element in self.elements.into_iter() {
-            element.force_update_tokens();
-            tokens.append(&mut element.tokens);
+    // Rewrites the properties in `links` that no longer reach their node after `old_path` moved to `new_path`.
+    fn repair_node_paths(&mut self, links:Vec<NodePathLink>, old_path:&str, new_path:&str) {
+        let format = self.format();
+        let mut updates = Vec::new();
+        {
+            let tree = self.tree();
+            for link in links {
+                let holder = tree::moved_path(&link.holder, old_path, new_path).unwrap_or(link.holder);
+                let target = tree::moved_path(&link.target, old_path, new_path).unwrap_or(link.target);
+                let (Some(holder), Some(target)) = (tree.get_node(&holder), tree.get_node(&target)) else {
+                    continue;
+                };
+                let (Some(index), Some(Ok(Variant::NodePath(path)))) = (holder.element_index(), holder.element().map(|element| element.get_property_variant(&link.property))) else {
+                    continue;
+                };
+                let path = NodePath::from(&path[..]);
+                if holder.get_node(&path) == Some(target) {
+                    continue;
+                }
+                let repaired = if path.is_absolute() { target.absolute_path() } else { holder.path_to(&target) };
+                let repaired = NodePath::new(repaired.is_absolute(), repaired.names().to_vec(), path.subnames().to_vec());
+                updates.push((index, link.property, Variant::NodePath(repaired.to_string()).to_text(format)));
+            }
+        }
+        for (index, property, value) in updates {
+            let _ = self.elements[index].update_property(&property, &value);
         }
-        Tokenizer::reconstruct_tscn_from_tokens(tokens)
     }
 
-    pub fn from_tscn_file(file_path:&str) -> Result<Self, SceneError> {
-        let r = loader::load(file_path)?;
-        match Tokenizer::tokenize(r.0, r.1) {
-            Ok(tokenizer) => {
-                Ok(Self {
-                    elements:tokenizer.elements.clone(),
-                    tokenizer
-                })
+    // Rewrites `parent=`, `[editable]` paths and connections under a node that moved from `old_path` to `new_path`.
+    fn move_paths(&mut self, old_path:&str, new_path:&str) {
+        let format = self.format();
+        for element in self.elements.iter_mut() {
+            let data_name = match &element.element_name[..] {
+                "node" => "parent",
+                "editable" => "path",
+                _ => continue,
+            };
+            if let Some(moved) = data_string(element, data_name).and_then(|path| tree::moved_path(&path, old_path, new_path)) {
+                let _ = element.update_data(data_name, &Variant::String(moved).to_text(format));
+            }
+        }
+        self.retarget_connections(old_path, new_path);
+    }
+
+    // Adds a `[node]` as the last child of the node at `parent`, returning the new node's path.
+    // `node_type` may be empty, like nodes that only override an instanced scene.
+    pub fn add_child(&mut self, parent:&str, name:&str, node_type:&str) -> Result<String, NodePathError> {
+        check_node_name(name)?;
+        let (parent_path, index) = {
+            let tree = self.tree();
+            let parent = tree.get_node(parent).ok_or(NodePathError::NodeNotFound)?;
+            if parent.children().any(|child| child.name() == name) {
+                return Err(NodePathError::NameTaken(String::from(name)));
+            }
+            // After the parent and all of its descendants, children are ordered as they appear in the file.
+            let last = parent.depth_first().filter_map(|node| node.element_index()).max();
+            (parent.path(), last.map_or_else(|| self.section_end("node"), |last| last + 1))
+        };
+        let format = self.format();
+        let mut element = Element::empty();
+        element.element_name = String::from("node");
+        element.element_type = ElementType::NODE;
+        element.element_data.push(ElementData(String::from("name"), Variant::String(String::from(name)).to_text(format)));
+        if !node_type.is_empty() {
+            element.element_data.push(ElementData(String::from("type"), Variant::String(String::from(node_type)).to_text(format)));
+        }
+        element.element_data.push(ElementData(String::from("parent"), Variant::String(parent_path.clone()).to_text(format)));
+        element.force_update_tokens();
+        self.insert_element(index, element);
+        Ok(tree::join_path(&parent_path, name))
+    }
+
+    // Removes the node at `path` with its descendants, their connections and `[editable]` entries.
+    pub fn remove_node(&mut self, path:&str) -> Result<(), NodePathError> {
+        let (node_path, mut indices) = {
+            let tree = self.tree();
+            let node = tree.get_node(path).ok_or(NodePathError::NodeNotFound)?;
+            if node.is_root() {
+                return Err(NodePathError::InvalidEdit(String::from("the root node can't be removed")));
+            }
+            (node.path(), node.depth_first().filter_map(|node| node.element_index()).collect::<Vec<usize>>())
+        };
+        for (index, element) in self.elements.iter().enumerate() {
+            let removed = match Connection::from_element(element) {
+                Some(connection) => tree::is_within(&connection.from, &node_path) || tree::is_within(&connection.to, &node_path),
+                None => element.element_name == "editable" && data_string(element, "path").is_some_and(|path| tree::is_within(&path, &node_path)),
+            };
+            if removed {
+                indices.push(index);
+            }
+        }
+        indices.sort();
+        indices.dedup();
+        for index in indices.into_iter().rev() {
+            self.remove_element(index);
+        }
+        Ok(())
+    }
+
+    // Renames the node at `path`, returning its new path.
+    pub fn rename_node(&mut self, path:&str, new_name:&str) -> Result<String, NodePathError> {
+        check_node_name(new_name)?;
+        let links = self.node_path_links();
+        let (old_path, new_path, index) = {
+            let tree = self.tree();
+            let node = tree.get_node(path).ok_or(NodePathError::NodeNotFound)?;
+            let index = node.element_index().ok_or_else(|| NodePathError::InvalidEdit(String::from("nodes of an instanced scene can't be renamed")))?;
+            let new_path = match node.parent() {
+                Some(parent) => {
+                    if parent.children().any(|child| child.name() == new_name && child != node) {
+                        return Err(NodePathError::NameTaken(String::from(new_name)));
+                    }
+                    tree::join_path(&parent.path(), new_name)
+                },
+                None => String::from("."),
+            };
+            (node.path(), new_path, index)
+        };
+        let format = self.format();
+        let _ = self.elements[index].update_data("name", &Variant::String(String::from(new_name)).to_text(format));
+        self.move_paths(&old_path, &new_path);
+        self.repair_node_paths(links, &old_path, &new_path);
+        Ok(new_path)
+    }
+
+    // Moves the node at `path` and its descendants under `new_parent` as its last child, returning the new path.
+    pub fn reparent(&mut self, path:&str, new_parent:&str) -> Result<String, NodePathError> {
+        let links = self.node_path_links();
+        let (old_path, new_path, parent_path, index, subtree, position) = {
+            let tree = self.tree();
+            let node = tree.get_node(path).ok_or(NodePathError::NodeNotFound)?;
+            let parent = tree.get_node(new_parent).ok_or(NodePathError::NodeNotFound)?;
+            if node.is_root() {
+                return Err(NodePathError::InvalidEdit(String::from("the root node can't be moved")));
+            }
+            let index = node.element_index().ok_or_else(|| NodePathError::InvalidEdit(String::from("nodes of an instanced scene can't be moved")))?;
+            if node.depth_first().any(|descendant| descendant == parent) {
+                return Err(NodePathError::InvalidEdit(String::from("a node can't be moved under itself")));
+            }
+            if parent.children().any(|child| child.name() == node.name() && child != node) {
+                return Err(NodePathError::NameTaken(String::from(node.name())));
+            }
+            let mut subtree = node.depth_first().filter_map(|node| node.element_index()).collect::<Vec<usize>>();
+            subtree.sort();
+            let last = parent.depth_first().filter_map(|node| node.element_index()).filter(|index| !subtree.contains(index)).max();
+            let position = last.map_or_else(|| self.section_end("node"), |last| last + 1);
+            (node.path(), tree::join_path(&parent.path(), node.name()), parent.path(), index, subtree, position)
+        };
+        let format = self.format();
+        let _ = self.elements[index].update_data("parent", &Variant::String(parent_path).to_text(format));
+        self.move_paths(&old_path, &new_path);
+        self.move_elements(&subtree, position);
+        self.repair_node_paths(links, &old_path, &new_path);
+        Ok(new_path)
+    }
+
+    // Moves the node at `path` to position `index` among its siblings, Godot orders children as they appear in the file.
+    pub fn move_child(&mut self, path:&str, index:usize) -> Result<(), NodePathError> {
+        let (subtree, position) = {
+            let tree = self.tree();
+            let node = tree.get_node(path).ok_or(NodePathError::NodeNotFound)?;
+            let parent = node.parent().ok_or_else(|| NodePathError::InvalidEdit(String::from("the root node can't be moved")))?;
+            if node.element_index().is_none() {
+                return Err(NodePathError::InvalidEdit(String::from("nodes of an instanced scene can't be moved")));
+            }
+            let siblings = parent.children().filter(|child| *child != node).collect::<Vec<_>>();
+            if index > siblings.len() {
+                return Err(NodePathError::InvalidEdit(format!("child index {} is out of range", index)));
+            }
+            let mut subtree = node.depth_first().filter_map(|node| node.element_index()).collect::<Vec<usize>>();
+            subtree.sort();
+            let position = match siblings.get(index) {
+                Some(sibling) => sibling.depth_first().filter_map(|node| node.element_index()).min(),
+                None => siblings.iter().flat_map(|sibling| sibling.depth_first()).filter_map(|node| node.element_index()).max().map(|last| last + 1),
+            };
+            (subtree, position)
+        };
+        if let Some(position) = position {
+            self.move_elements(&subtree, position);
+        }
+        Ok(())
+    }
+
+    pub fn get_node_property(&self, node_path:NodePath, property_name:&str) -> Result<String, NodePathError> {
+        self.find_node(&node_path)?.get_property_value(property_name)
+    }
+
+    pub fn get_node_property_variant(&self, node_path:NodePath, property_name:&str) -> Result<Variant, NodePathError> {
+        self.find_node(&node_path)?.get_property_variant(property_name)
+    }
+
+    // Properties of the node with those of the scene it instances (or the node it overrides in one) underneath,
+    // local values replacing instanced ones. Nested instances and inherited scenes are followed through `source`.
+    pub fn effective_properties(&self, node_path:NodePath, source:&mut impl SceneSource) -> Result<Vec<Property>, NodePathError> {
+        let path = {
+            let tree = self.tree();
+            let node = tree.root().and_then(|root| root.get_node(&node_path)).ok_or(NodePathError::NodeNotFound)?;
+            node.path()
+        };
+        self.effective_properties_at(&path, source, 0)
+    }
+
+    fn effective_properties_at(&self, path:&str, source:&mut impl SceneSource, depth:usize) -> Result<Vec<Property>, NodePathError> {
+        let (local, instance) = {
+            let tree = self.tree();
+            let node = tree.get_node(path).ok_or(NodePathError::NodeNotFound)?;
+            let local = node.element().map(|element| element.properties.clone()).unwrap_or_default();
+            let instance = match node.instance_owner() {
+                Some(owner) => {
+                    let instance_path = owner.instance_path().ok_or_else(|| NodePathError::InstanceUnavailable(owner.instance().map(|value| value.to_string()).unwrap_or_default(), None))?;
+                    // Path of this node inside the instanced scene.
+                    let inner_path = match owner.path() {
+                        owner_path if owner_path == node.path() => String::from("."),
+                        owner_path if owner_path == "." => node.path(),
+                        owner_path => node.path()[owner_path.len() + 1..].to_string(),
+                    };
+                    Some((instance_path, inner_path))
+                },
+                None => None,
+            };
+            (local, instance)
+        };
+        let mut properties = match instance {
+            Some((instance_path, _)) if depth >= MAX_INSTANCE_DEPTH => {
+                // Scenes instancing themselves, directly or through others.
+                return Err(NodePathError::InstanceUnavailable(instance_path, None));
+            },
+            Some((instance_path, inner_path)) => {
+                let scene = source.load_scene(&instance_path).map_err(|error| NodePathError::InstanceUnavailable(instance_path.clone(), Some(Box::new(error))))?;
+                scene.effective_properties_at(&inner_path, source, depth + 1)?
             },
-            Err(error) => {
-                Err(SceneError::TokenizerError(error))
+            None => Vec::new(),
+        };
+        for property in local {
+            match properties.iter_mut().find(|existing| existing.0 == property.0) {
+                Some(existing) => existing.1 = property.1,
+                None => properties.push(property),
             }
         }
+        Ok(properties)
+    }
+
+    // Like `get_node_property`, falling back to the value in the instanced scene when the node doesn't override it.
+    pub fn get_effective_property(&self, node_path:NodePath, property_name:&str, source:&mut impl SceneSource) -> Result<String, NodePathError> {
+        match self.effective_properties(node_path, source)?.into_iter().find(|property| property.0 == property_name) {
+            Some(property) => Ok(property.1),
+            None => Err(NodePathError::PropertyNotFound),
+        }
+    }
+
+    pub fn get_effective_property_variant(&self, node_path:NodePath, property_name:&str, source:&mut impl SceneSource) -> Result<Variant, NodePathError> {
+        let value = self.get_effective_property(node_path, property_name, source)?;
+        Variant::parse(&value).map_err(NodePathError::InvalidValue)
+    }
+
+    // Sets the property, adding it if the node doesn't have it yet. `value` is in Godot's text format.
+    pub fn set_node_property(&mut self, node_path:NodePath, property_name:&str, value:&str) -> Result<(), NodePathError> {
+        let index = self.find_node_index(&node_path)?;
+        self.elements[index].set_property(property_name, value);
+        Ok(())
+    }
+
+    // Removes the property, so the node falls back to its default value.
+    pub fn remove_node_property(&mut self, node_path:NodePath, property_name:&str) -> Result<Property, NodePathError> {
+        let index = self.find_node_index(&node_path)?;
+        self.elements[index].remove_property(property_name).map_err(|_| NodePathError::PropertyNotFound)
+    }
+
+    pub fn to_tscn(&self) -> String {
+        elements_to_text(&self.elements)
+    }
+
+    // Godot 3 or Godot 4 conventions, from the `format=` value of the file header.
+    pub fn format(&self) -> Format {
+        file_format(&self.elements)
+    }
+
+    pub fn to_canonical_tscn(&self) -> String {
+        writer::write_canonical(self)
+    }
+
+    // The scene in the JSON schema described in `interchange`, for tools that don't read `.tscn`.
+    pub fn to_json(&self) -> String {
+        interchange::to_json(self)
+    }
+
+    // Reads a scene back from `to_json` output, or JSON other tools wrote in the same schema.
+    pub fn from_json(json:&str) -> Result<Scene, JsonError> {
+        interchange::from_json(json)
+    }
+
+    pub fn from_tscn_file(file_path:&str) -> Result<Self, SceneError> {
+        let reader = loader::load(file_path)?;
+        Scene::from_events(EventReader::named(reader, Some(file_path)))
     }
-}
\ No newline at end of file
+
+    pub fn from_reader(reader:impl Read) -> Result<Self, SceneError> {
+        Scene::from_events(EventReader::new(BufReader::new(reader)))
+    }
+
+    // Parses as much of the file as possible, sections with errors are left out and every error is returned.
+    pub fn from_tscn_file_recovering(file_path:&str) -> Result<(Self, Vec<TokenizerError>), SceneError> {
+        let reader = loader::load(file_path)?;
+        Ok(Scene::from_recovered(EventReader::recovering(reader, Some(file_path))))
+    }
+
+    pub fn from_reader_recovering(reader:impl Read) -> (Self, Vec<TokenizerError>) {
+        Scene::from_recovered(EventReader::recovering(BufReader::new(reader), None))
+    }
+
+    pub fn from_str_recovering(string:&str) -> (Self, Vec<TokenizerError>) {
+        Scene::from_str_named_recovering(string, None)
+    }
+
+    // Like `from_str`, with `file_name` reported in error locations.
+    pub fn from_str_named(string:&str, file_name:Option<&str>) -> Result<Self, SceneError> {
+        Scene::from_events(EventReader::named(string.as_bytes(), file_name))
+    }
+
+    pub fn from_str_named_recovering(string:&str, file_name:Option<&str>) -> (Self, Vec<TokenizerError>) {
+        Scene::from_recovered(EventReader::recovering(string.as_bytes(), file_name))
+    }
+
+    fn from_recovered(events:EventReader<impl BufRead>) -> (Self, Vec<TokenizerError>) {
+        let (elements, tokenizer, errors) = events::read_elements(events);
+        (Self { elements, tokenizer }, errors)
+    }
+
+    fn from_events(events:EventReader<impl BufRead>) -> Result<Self, SceneError> {
+        let (elements, tokenizer, errors) = events::read_elements(events);
+        match errors.into_iter().next() {
+            Some(error) => Err(SceneError::TokenizerError(error)),
+            None => Ok(Self { elements, tokenizer }),
+        }
+    }
+}
+
+impl FromStr for Scene {
+    type Err = SceneError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Scene::from_str_named(string, None)
+    }
+}
+
+// A scene serializes as its elements. The tokenizer state is rebuilt from their text when deserializing.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Scene {
+    fn serialize<S:serde::Serializer>(&self, serializer:S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("Scene", 1)?;
+        state.serialize_field("elements", &self.elements)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Scene {
+    fn deserialize<D:serde::Deserializer<'de>>(deserializer:D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            elements:Vec<Element>,
+        }
+        let elements = Fields::deserialize(deserializer)?.elements;
+        let tokenizer = Tokenizer::tokenize_str(&elements_to_text(&elements)).map_err(serde::de::Error::custom)?;
+        Ok(Scene { elements, tokenizer })
+    }
+}
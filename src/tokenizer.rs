@@ -1,19 +1,10 @@
-use std::{ops::Deref, rc::Rc, io::{BufReader, BufRead}, fs::File};
+use std::{fmt, ops::Range, io::{self, BufRead}};
 
 use crate::{element::{Element, ExpectedType, ElementData, ElementType, Property}};
 
-// #[derive(PartialEq, Clone)]
-// pub enum Value {
-//     Undefined,
-//     String(String),
-//     Resource(String),
-//     Int(i32),
-// }
-
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(PartialEq, Clone, Debug)]
 pub enum Token {
-    Unresolved,
-    Invalid,
     BracketLeft,
     BracketRight,
     NewLine,
@@ -24,13 +15,16 @@ pub enum Token {
     // Element properties
     PropertyName(Option<String>),
     PropertyValue(Option<String>),
-    //Control
-    SkipTo(Rc<Token>),
+    // Formatting, kept so untouched parts of a file are written back exactly as they were read.
+    Equals,
+    Whitespace(String),
+    Comment(String),
+    Skipped(String), // Text the recovering tokenizer could not parse.
 }
 
-impl Token {
-    pub fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let string = match self {
             Token::BracketLeft => {
                 String::from('[')
             },
@@ -40,9 +34,6 @@ impl Token {
             Token::NewLine => {
                 String::from('\n')
             },
-            Token::Invalid => {
-                String::from("{Invalid}")
-            },
             Token::ElementName(val) => {
                 if let Some(string) = val {
                     String::from(string)
@@ -53,9 +44,7 @@ impl Token {
             },
             Token::ElementDataName(val) => {
                 if let Some(string) = val {
-                    let mut n = string.clone();
-                    n.push('=');
-                    String::from(&n)
+                    String::from(string)
                 }
                 else {
                     String::from("{Unresolved}")
@@ -71,9 +60,7 @@ impl Token {
             },
             Token::PropertyName(val) => {
                 if let Some(string) = val {
-                    let mut n = string.clone();
-                    n += " = ";
-                    String::from(&n)
+                    String::from(string)
                 }
                 else {
                     String::from("{Unresolved}")
@@ -87,259 +74,772 @@ impl Token {
                     String::from("{Unresolved}")
                 }
             },
-            Token::Unresolved | _ => {
-                 String::from("{UNDEFINED}")
-            }
-        }
-    }
-    fn requires_space_suffix(&self) -> bool {
-        match self {
-            Token::ElementName(..) | Token::ElementDataValue(..) => {
-                true
+            Token::Equals => {
+                String::from('=')
             },
-            _ => {
-                false
-            }
-        }
+            Token::Whitespace(string) | Token::Comment(string) | Token::Skipped(string) => {
+                string.clone()
+            },
+        };
+        f.write_str(&string)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Tokenizer {
     pub elements:Vec<Element>,
     pub tokens:Vec<Token>,
-    current_string:Option<String>,
-    in_quote:bool,
-    current_string_completed:bool,
+    pub spans:Vec<Span>, // Source position of each token in `tokens`.
+    pub file_name:Option<String>,
+    lexer:Lexer,
+    pending:String, // Source text from where the name or value being read began, the ranges of new tokens point into it.
+    pending_offset:usize, // Byte offset of `pending`.
+    line_offset:usize, // Byte offset of the next line.
+    skipping:bool, // Set after an error, lines are skipped until the next section header.
+}
+
+// Byte range of a token in the source, with the 1-based line and column it starts at.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start:usize,
+    pub end:usize,
+    pub line:usize,
+    pub column:usize,
+}
+
+impl Span {
+    // Spans of `tokens`, which concatenate back to the exact source text.
+    pub fn of_tokens(tokens:&[Token]) -> Vec<Span> {
+        let mut cursor = Span::start_of_file();
+        tokens.iter().map(|token| cursor.advance(&token.to_string())).collect()
+    }
+
+    // An empty span at line 1, column 1, for `advance` to continue from.
+    pub(crate) fn start_of_file() -> Span {
+        Span { start: 0, end: 0, line: 1, column: 1 }
+    }
+
+    // The span of `text` when it follows this one, which moves past it.
+    pub(crate) fn advance(&mut self, text:&str) -> Span {
+        let span = Span { start: self.end, end: self.end + text.len(), line: self.line, column: self.column };
+        self.end += text.len();
+        for c in text.chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            }
+            else {
+                self.column += 1;
+            }
+        }
+        span
+    }
+}
+
+// Where an error occurred: file name, 1-based line and column, and the byte range in the source.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Location {
+    pub file:Option<String>,
+    pub line:usize,
+    pub column:usize,
+    pub span:Range<usize>,
+}
+
+impl Location {
+    pub fn new(file:Option<String>, span:&Span) -> Self {
+        Location { file, line: span.line, column: span.column, span: span.start..span.end }
+    }
+
+    // The offending source line with carets under the span, e.g.
+    //   --> scene.tscn:3:5
+    //    |
+    //  3 | pos = Vector2(1, 2
+    //    |       ^^^^^^^^^^^^
+    pub fn snippet(&self, source:&str) -> String {
+        let line_text = source.lines().nth(self.line.saturating_sub(1)).unwrap_or("").trim_end_matches('\r');
+        let gutter = " ".repeat(self.line.to_string().len());
+        let prefix = line_text.chars().take(self.column.saturating_sub(1)).map(|c| if c == '\t' { '\t' } else { ' ' }).collect::<String>();
+        let line_start = source.lines().take(self.line.saturating_sub(1)).map(|line| line.len() + 1).sum::<usize>();
+        let line_end = line_start + line_text.len();
+        let caret_end = self.span.end.min(line_end).max(self.span.start + 1);
+        let carets = "^".repeat(source.get(self.span.start.min(source.len())..caret_end.min(source.len())).map_or(1, |text| text.chars().count().max(1)));
+        format!("{gutter}--> {self}\n{gutter} |\n{} | {}\n{gutter} | {}{}", self.line, line_text, prefix, carets)
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.as_deref().unwrap_or("<string>"), self.line, self.column)
+    }
 }
 
-type Index = usize;
 #[derive(Debug)]
 pub enum TokenizerError {
+    NotFound(ExpectedType, Location),
+    InvalidChar(char, Location),
+    EarlyEOF(Location),
+    ReadFailed(io::Error, Location),
+    UnexpectedErr(Location),
+}
+
+impl TokenizerError {
+    pub fn location(&self) -> &Location {
+        match self {
+            TokenizerError::NotFound(_, location) | TokenizerError::InvalidChar(_, location) | TokenizerError::EarlyEOF(location) | TokenizerError::ReadFailed(_, location) | TokenizerError::UnexpectedErr(location) => location,
+        }
+    }
+
+    // The error message followed by a caret-annotated snippet of `source`.
+    pub fn render(&self, source:&str) -> String {
+        format!("error: {}\n{}", self, self.location().snippet(source))
+    }
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenizerError::NotFound(expected, location) => write!(f, "expected {} at {}", expected, location),
+            TokenizerError::InvalidChar('\n', location) => write!(f, "unexpected line break at {}", location),
+            TokenizerError::InvalidChar(c, location) => write!(f, "unexpected character '{}' at {}", c, location),
+            TokenizerError::EarlyEOF(location) => write!(f, "file ended before the value starting at {} was closed", location),
+            TokenizerError::ReadFailed(error, location) => write!(f, "failed to read {}: {}", location, error),
+            TokenizerError::UnexpectedErr(location) => write!(f, "unexpected error at {}", location),
+        }
+    }
+}
+
+impl std::error::Error for TokenizerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TokenizerError::ReadFailed(error, _) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Tracks string, comment and bracket state so values may contain spaces, `]` and newlines.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Nesting {
+    depth:usize,
+    in_quote:bool,
+    in_comment:bool,
+    escaped:bool,
+}
+
+impl Nesting {
+    pub(crate) fn feed(&mut self, c:char) {
+        if self.in_comment {
+            self.in_comment = c != '\n';
+            return;
+        }
+        if self.in_quote {
+            if self.escaped {
+                self.escaped = false;
+            }
+            else if c == '\\' {
+                self.escaped = true;
+            }
+            else if c == '"' {
+                self.in_quote = false;
+            }
+            return;
+        }
+        match c {
+            '"' => self.in_quote = true,
+            ';' => self.in_comment = true,
+            '(' | '[' | '{' => self.depth += 1,
+            ')' | ']' | '}' => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn is_nested(&self) -> bool {
+        self.in_quote || self.in_comment || self.depth > 0
+    }
+}
+
+pub(crate) fn is_whitespace(c:char) -> bool {
+    c.is_whitespace() || c == '\u{feff}'
+}
+
+// What the upcoming characters belong to, `None` when between properties or elements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Next {
+    ElementName,
+    ElementDataName,
+    Equals,
+    ElementDataValue,
+    PropertyName,
+    PropertyValue,
+}
+
+// The tokens `Lexer` finds, their text is the byte range of the source they come with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TokenKind {
+    BracketLeft,
+    BracketRight,
+    NewLine,
+    Equals,
+    ElementName,
+    ElementDataName,
+    ElementDataValue,
+    PropertyName,
+    PropertyValue,
+    Whitespace,
+    Comment,
+}
+
+// Receives the tokens of `Lexer`. Whitespace may come in several pieces in a row, which make up one token.
+pub(crate) trait TokenSink {
+    fn push_token(&mut self, kind:TokenKind, range:Range<usize>);
+}
+
+// Errors of `Lexer`, located by the caller.
+enum LexError {
     NotFound(ExpectedType),
-    InvalidChar(Index),
-    EarlyEOF,
-    UnexpectedErr,
+    InvalidChar(char),
 }
 
-impl Tokenizer {
-    fn append_current_string(&mut self, character:char, end_chars:&[char]) {
-        if character == '"' || character == '\'' {
-            self.in_quote = !self.in_quote; // If we're not in a quote, now we are. If we were already in a quote, now we aren't :O
+// The name or value being read.
+#[derive(Debug, Clone)]
+struct Current {
+    start:Span,
+    trimmed_end:usize, // End of its last character that isn't whitespace.
+}
+
+// The character rules of the format, shared by `Tokenizer` and `borrowed::SceneRef`. It is fed the file a line at
+// a time and only hands out byte ranges, so each of them can take the token text its own way.
+#[derive(Debug, Clone)]
+pub(crate) struct Lexer {
+    next:Option<Next>,
+    current:Option<Current>,
+    nesting:Nesting,
+    value:Next, // What `=` leads to, the kind of the last name.
+    cursor:Span, // The character being read.
+    pub(crate) crlf:bool, // The first line ends with `\r\n`, edits use the same line breaks.
+}
+
+impl Lexer {
+    pub(crate) fn new() -> Lexer {
+        Lexer {
+            next: None,
+            current: None,
+            nesting: Nesting::default(),
+            value: Next::PropertyValue,
+            cursor: Span { start: 0, end: 0, line: 0, column: 1 },
+            crlf: false,
         }
-        if let Some(mut string) = self.current_string.clone() {
-            if string.chars().into_iter().filter(|c| !end_chars.contains(c)).count() > 0 { // Check if string has chars other than space or equals.
-                if end_chars.contains(&character) {
-                    if !self.in_quote {
-                        self.current_string_completed = true;
-                        return;
-                    }
-                }
-                else {
-                    self.current_string_completed = false;
-                }
+    }
+
+    pub(crate) fn location(&self, file_name:Option<&str>) -> Location {
+        Location::new(file_name.map(String::from), &self.cursor)
+    }
+
+    // Start of the name or value being read.
+    fn current_start(&self) -> Option<usize> {
+        self.current.as_ref().map(|current| current.start.start)
+    }
+
+    // Forgets the header or property being read, after an error.
+    fn reset(&mut self) {
+        self.next = None;
+        self.current = None;
+        self.nesting = Nesting::default();
+    }
+
+    // `Some` when the file ended before the header or property being read was finished.
+    pub(crate) fn unfinished(&self, file_name:Option<&str>) -> Option<TokenizerError> {
+        self.next?;
+        let location = match &self.current {
+            Some(current) => Location::new(file_name.map(String::from), &current.start),
+            None => self.location(file_name),
+        };
+        Some(TokenizerError::EarlyEOF(location))
+    }
+
+    // Feeds `line`, which starts `line_offset` bytes into the file and ends with its line break unless it's the last
+    // line. The error comes with the index in `line` of the character it is about.
+    pub(crate) fn read_line(&mut self, sink:&mut impl TokenSink, line:&str, line_offset:usize, file_name:Option<&str>) -> Result<(), (TokenizerError, usize)> {
+        let line_number = self.cursor.line + 1;
+        if line_number == 1 {
+            self.crlf = line.ends_with("\r\n");
+        }
+        // A last line without a line break still needs its values terminated, the break itself isn't a token.
+        let missing_line_break = (!line.ends_with('\n')).then_some((line.len(), '\n'));
+        for (column, (index, c)) in line.char_indices().chain(missing_line_break).enumerate() {
+            self.cursor = Span { start: line_offset + index, end: line_offset + index + c.len_utf8(), line: line_number, column: column + 1 };
+            let result = match self.read_char(sink, line, line_offset, index, c) {
+                Ok(read_on) => read_on,
+                Err(LexError::NotFound(expected)) => return Err((TokenizerError::NotFound(expected, self.location(file_name)), index)),
+                Err(LexError::InvalidChar(c)) => return Err((TokenizerError::InvalidChar(c, self.location(file_name)), index)),
+            };
+            if !result {
+                break;
             }
-            string.push(character);
-            self.current_string = Some(string.to_string());
         }
-        else {
-            self.current_string = Some(String::from(character));
+        Ok(())
+    }
+
+    fn append_current(&mut self, c:char) {
+        let current = self.current.get_or_insert_with(|| Current { start: self.cursor.clone(), trimmed_end: self.cursor.start });
+        if !is_whitespace(c) {
+            current.trimmed_end = self.cursor.end;
         }
+        self.nesting.feed(c);
     }
-    
-    fn consume_current_string(&mut self) -> Option<String> {
-        let mut new = self.current_string.clone();
-        if let Some(string) = new {
-            new = Some(string.trim().to_string());
+
+    fn push_char(&self, sink:&mut impl TokenSink, kind:TokenKind) {
+        sink.push_token(kind, self.cursor.start..self.cursor.end);
+    }
+
+    // Pushes the name or value ending before the cursor, trailing whitespace is kept as its own token.
+    fn push_current(&mut self, sink:&mut impl TokenSink, kind:TokenKind) {
+        let (start, trimmed_end) = match self.current.take() {
+            Some(current) => (current.start.start, current.trimmed_end),
+            None => (self.cursor.start, self.cursor.start),
+        };
+        self.nesting = Nesting::default();
+        sink.push_token(kind, start..trimmed_end);
+        if trimmed_end < self.cursor.start {
+            sink.push_token(TokenKind::Whitespace, trimmed_end..self.cursor.start);
         }
-        self.current_string = None;
-        self.current_string_completed = false;
-        self.in_quote = false;
-        new
-    }
-
-    pub fn tokenize(mut reader:BufReader<File>, line_count:usize) -> Result<Tokenizer, TokenizerError> {
-        let mut tokenizer = Tokenizer { elements: Vec::new(), tokens: Vec::new(), current_string:None, in_quote: false, current_string_completed: false, };
-        let mut next_token:Option<Token> = None;
-        let mut current_line:u16 = 0;
-        let mut line_beginning_char_index:usize = 0;
-        let _index:usize = 0;
-        // Todo: Allow brackets in ElementDataValues
-        'lines: for _ in 0..line_count {
-            let mut line = String::new();
-            reader.read_line(&mut line);
-            'chars: for (index, c) in line.chars().enumerate() {
-                let mut current_token:Token = Token::Unresolved;
-
-                if let Some(next) = &next_token {
-                    match next {
-                        Token::Invalid => {
-                            return Err(TokenizerError::InvalidChar(index));
-                        },
-                        Token::SkipTo(new_next_token) => {
-                            next_token = Some(new_next_token.deref().clone());
-                            continue 'chars;
-                        },
-                        _ => {}
+        match kind {
+            TokenKind::ElementDataName => self.value = Next::ElementDataValue,
+            TokenKind::PropertyName => self.value = Next::PropertyValue,
+            _ => {},
+        }
+    }
+
+    // Pushes a `;` comment running to the end of the line, followed by the line break.
+    fn push_comment(&self, sink:&mut impl TokenSink, line:&str, line_offset:usize, index:usize) {
+        let rest_of_line = &line[index..];
+        let comment = rest_of_line.trim_end_matches(['\n', '\r']);
+        let comment_end = line_offset + index + comment.len();
+        sink.push_token(TokenKind::Comment, line_offset + index..comment_end);
+        if rest_of_line.len() > comment.len() + 1 {
+            sink.push_token(TokenKind::Whitespace, comment_end..comment_end + 1);
+        }
+        if rest_of_line.ends_with('\n') {
+            sink.push_token(TokenKind::NewLine, line_offset + line.len() - 1..line_offset + line.len());
+        }
+    }
+
+    // A line break, unless it's the one added to a last line without one.
+    fn push_line_break(&self, sink:&mut impl TokenSink, line:&str, index:usize) {
+        if index < line.len() {
+            self.push_char(sink, TokenKind::NewLine);
+        }
+    }
+
+    // After `name` or `name ` the next token is `=`, followed by a value of the same kind as the name.
+    fn push_equals(&self, sink:&mut impl TokenSink) -> Option<Next> {
+        self.push_char(sink, TokenKind::Equals);
+        Some(self.value)
+    }
+
+    // Feeds one character of `line`, returns `Ok(false)` when the rest of the line has already been consumed.
+    fn read_char(&mut self, sink:&mut impl TokenSink, line:&str, line_offset:usize, index:usize, c:char) -> Result<bool, LexError> {
+        match self.next {
+            None => {
+                match c {
+                    '[' => {
+                        self.push_char(sink, TokenKind::BracketLeft);
+                        self.next = Some(Next::ElementName);
+                    },
+                    '\n' => {
+                        self.push_line_break(sink, line, index);
+                    },
+                    ';' => {
+                        self.push_comment(sink, line, line_offset, index);
+                        return Ok(false);
+                    },
+                    // Can't start a property name, e.g. the `=======` of a merge conflict.
+                    '=' | ']' => {
+                        return Err(LexError::InvalidChar(c));
+                    },
+                    _ if is_whitespace(c) => {
+                        self.push_char(sink, TokenKind::Whitespace);
+                    },
+                    _ => {
+                        self.append_current(c);
+                        self.next = Some(Next::PropertyName);
                     }
                 }
+            },
+            Some(Next::ElementName) => {
+                if c == '\n' {
+                    return Err(LexError::NotFound(ExpectedType::ElementName));
+                }
+                if !is_whitespace(c) && c != ']' {
+                    self.append_current(c);
+                    return Ok(true);
+                }
+                if self.current.is_none() {
+                    if c == ']' {
+                        return Err(LexError::NotFound(ExpectedType::ElementName));
+                    }
+                    self.push_char(sink, TokenKind::Whitespace);
+                    return Ok(true);
+                }
+                self.push_current(sink, TokenKind::ElementName);
+                if c == ']' {
+                    self.push_char(sink, TokenKind::BracketRight);
+                    self.next = None;
+                }
+                else {
+                    self.push_char(sink, TokenKind::Whitespace);
+                    self.next = Some(Next::ElementDataName);
+                }
+            },
+            Some(Next::ElementDataName) => {
                 match c {
-                    '[' => {
-                        let mut is_value:bool = false;
-                        if let Some(next) = &next_token {
-                            if let Token::PropertyValue(_) | Token::ElementDataValue(..) = next {
-                                is_value = true;
-                            }
+                    '=' => {
+                        if self.current.is_none() {
+                            return Err(LexError::NotFound(ExpectedType::ElementDataName));
                         }
-                    if !is_value {
-                            current_token = Token::BracketLeft;
-                            next_token = Some(Token::ElementName(None));
-                    }
+                        self.push_current(sink, TokenKind::ElementDataName);
+                        self.next = self.push_equals(sink);
                     },
-                    ']' => {
-                        if let Some(next) = &next_token {
-                            match next {
-                                Token::ElementDataValue(..) => {
-                                    next_token = None;
-                                    let token_value = tokenizer.consume_current_string();
-                                    tokenizer.tokens.push(Token::ElementDataValue(token_value));
-                                },
-                                Token::PropertyValue(..) => {
-                                    next_token = None;
-                                    let token_value = tokenizer.consume_current_string();
-                                    tokenizer.tokens.push(Token::PropertyValue(token_value));
-                                }
-                                _ => {}
-                            }
-                        }
-                        current_token = Token::BracketRight;
+                    ']' if self.current.is_none() => {
+                        self.push_char(sink, TokenKind::BracketRight);
+                        self.next = None;
                     },
                     '\n' => {
-                        current_line += 1;
-                        line_beginning_char_index = index;
-                        let mut token_mutated:bool = false;
-                        if let Some(last) = tokenizer.tokens.last() {
-                            match last {
-                                Token::BracketRight => {
-                                    next_token = Some(Token::PropertyName(None));
-                                    token_mutated = true;
-                                },
-                                _ => {}
-                            }
+                        return Err(LexError::InvalidChar(c));
+                    },
+                    _ if is_whitespace(c) => {
+                        if self.current.is_some() {
+                            self.push_current(sink, TokenKind::ElementDataName);
+                            self.next = Some(Next::Equals);
                         }
-                        current_token = Token::NewLine;
+                        self.push_char(sink, TokenKind::Whitespace);
+                    },
+                    _ => {
+                        self.append_current(c);
                     }
+                }
+            },
+            Some(Next::Equals) => {
+                match c {
+                    '=' => {
+                        self.next = self.push_equals(sink);
+                    },
+                    '\n' => {
+                        return Err(LexError::InvalidChar(c));
+                    },
+                    _ if is_whitespace(c) => {
+                        self.push_char(sink, TokenKind::Whitespace);
+                    },
                     _ => {
-                        if let Some(next) = &next_token {
-                            match next {
-                                Token::ElementName(..) => {
-                                    tokenizer.append_current_string(c, &[' ']);
-                                    if tokenizer.current_string_completed {
-                                        next_token = Some(Token::ElementDataName(None));
-                                        current_token = Token::ElementName(tokenizer.consume_current_string());
-                                    }
-                                    else {
-                                        continue 'chars;
-                                    }
-                                },
-                                Token::ElementDataName(..) => {
-                                    tokenizer.append_current_string(c, &[' ', '=']);
-                                    if tokenizer.current_string_completed {
-                                        // skip '=' and jump to ElementDataValue
-                                        next_token = Some(Token::ElementDataValue(None));
-                                        current_token = Token::ElementDataName(tokenizer.consume_current_string());
-                                    }
-                                    else {
-                                        continue 'chars;
-                                    }
-                                },
-                                Token::ElementDataValue(..) => {
-                                    tokenizer.append_current_string(c,  &[' ']);
-                                    if tokenizer.current_string_completed {
-                                        next_token = Some(Token::ElementDataName(None));
-                                        current_token = Token::ElementDataValue(tokenizer.consume_current_string());
-                                    }
-                                    else {
-                                        continue 'chars;
-                                    }
-                                },
-                                Token::PropertyName(..) => {
-                                    tokenizer.append_current_string(c, &[' ', '=']);
-                                    if tokenizer.current_string_completed {
-                                        if let Some(next_char) = line.chars().nth(index+1) {
-                                            if next_char == '=' {
-                                                // skip '=' and jump to PropertyValue
-                                                next_token = Some(Token::SkipTo(Rc::new(Token::PropertyValue(None))));
-                                                current_token = Token::PropertyName(tokenizer.consume_current_string());
-                                            }
-                                        }
-                                        else {
-                                            return Err(TokenizerError::EarlyEOF);
-                                        }
-                                    }
-                                    else {
-                                        continue 'chars;
-                                    }
-                                },
-                                Token::PropertyValue(..) => {
-                                    // Read entire line starting at prop value start, then continue to next.
-                                    // Saves time from reading chars we don't need to analyze.
-                                    tokenizer.current_string = Some(String::from(&line[index..]));
-                                    tokenizer.current_string_completed = true;
-                                    next_token = Some(Token::PropertyName(None));
-                                    let prop_value = tokenizer.consume_current_string();
-                                    tokenizer.tokens.push(Token::PropertyValue(prop_value));
-                                    tokenizer.tokens.push(Token::NewLine);
-                                    continue 'lines;
-                                },
-                                _ => {
-                                    continue;
-                                }
-                            }
-                        }
+                        return Err(LexError::InvalidChar(c));
                     }
                 }
-                tokenizer.in_quote = false;
-                tokenizer.tokens.push(current_token);
+            },
+            Some(Next::ElementDataValue) => {
+                let ends_value = !self.nesting.is_nested() && (is_whitespace(c) || c == ']');
+                if !ends_value {
+                    self.append_current(c);
+                    return Ok(true);
+                }
+                if c == '\n' {
+                    return Err(LexError::InvalidChar(c));
+                }
+                if self.current.is_none() {
+                    if c == ']' {
+                        return Err(LexError::NotFound(ExpectedType::ElementDataValue));
+                    }
+                    self.push_char(sink, TokenKind::Whitespace);
+                    return Ok(true);
+                }
+                self.push_current(sink, TokenKind::ElementDataValue);
+                if c == ']' {
+                    self.push_char(sink, TokenKind::BracketRight);
+                    self.next = None;
+                }
+                else {
+                    self.push_char(sink, TokenKind::Whitespace);
+                    self.next = Some(Next::ElementDataName);
+                }
+            },
+            Some(Next::PropertyName) => {
+                if self.nesting.is_nested() {
+                    self.append_current(c);
+                }
+                else if c == '=' {
+                    self.push_current(sink, TokenKind::PropertyName);
+                    self.next = self.push_equals(sink);
+                }
+                else if c == '\n' {
+                    return Err(LexError::InvalidChar(c));
+                }
+                else if is_whitespace(c) {
+                    self.push_current(sink, TokenKind::PropertyName);
+                    self.push_char(sink, TokenKind::Whitespace);
+                    self.next = Some(Next::Equals);
+                }
+                else {
+                    self.append_current(c);
+                }
+            },
+            Some(Next::PropertyValue) => {
+                if self.current.is_none() && is_whitespace(c) {
+                    if c == '\n' {
+                        return Err(LexError::NotFound(ExpectedType::PropertyValue));
+                    }
+                    self.push_char(sink, TokenKind::Whitespace);
+                    return Ok(true);
+                }
+                // Values end at the first line break or comment that isn't inside a string or brackets.
+                if !self.nesting.is_nested() && (c == '\n' || c == ';') {
+                    self.push_current(sink, TokenKind::PropertyValue);
+                    self.next = None;
+                    if c == ';' {
+                        self.push_comment(sink, line, line_offset, index);
+                        return Ok(false);
+                    }
+                    self.push_line_break(sink, line, index);
+                }
+                else {
+                    self.append_current(c);
+                }
+            },
+        }
+        Ok(true)
+    }
+}
+
+// Builds owned tokens from the ranges of `Lexer`, `text` is the source starting at `text_offset`.
+struct TokenBuilder<'t> {
+    tokens:&'t mut Vec<Token>,
+    text:&'t str,
+    text_offset:usize,
+}
+
+impl TokenSink for TokenBuilder<'_> {
+    fn push_token(&mut self, kind:TokenKind, range:Range<usize>) {
+        let text = &self.text[range.start - self.text_offset..range.end - self.text_offset];
+        let token = match kind {
+            TokenKind::BracketLeft => Token::BracketLeft,
+            TokenKind::BracketRight => Token::BracketRight,
+            TokenKind::NewLine => Token::NewLine,
+            TokenKind::Equals => Token::Equals,
+            TokenKind::ElementName => Token::ElementName(Some(String::from(text))),
+            TokenKind::ElementDataName => Token::ElementDataName(Some(String::from(text))),
+            TokenKind::ElementDataValue => Token::ElementDataValue(Some(String::from(text))),
+            TokenKind::PropertyName => Token::PropertyName(Some(String::from(text))),
+            TokenKind::PropertyValue => Token::PropertyValue(Some(String::from(text))),
+            TokenKind::Whitespace => {
+                if let Some(Token::Whitespace(string)) = self.tokens.last_mut() {
+                    string.push_str(text);
+                    return;
+                }
+                Token::Whitespace(String::from(text))
+            },
+            TokenKind::Comment => Token::Comment(String::from(text)),
+        };
+        self.tokens.push(token);
+    }
+}
+
+impl Tokenizer {
+    pub(crate) fn location(&self) -> Location {
+        self.lexer.location(self.file_name.as_deref())
+    }
+
+    pub(crate) fn crlf(&self) -> bool {
+        self.lexer.crlf
+    }
+
+    fn token_location(&self, index:usize) -> Location {
+        Location::new(self.file_name.clone(), &self.spans.get(index).cloned().unwrap_or_default())
+    }
+
+    pub fn tokenize_str(string:&str) -> Result<Tokenizer, TokenizerError> {
+        Tokenizer::tokenize(string.as_bytes())
+    }
+
+    pub fn tokenize(reader:impl BufRead) -> Result<Tokenizer, TokenizerError> {
+        Tokenizer::tokenize_named(reader, None)
+    }
+
+    // Like `tokenize`, with `file_name` reported in error locations.
+    pub fn tokenize_named(reader:impl BufRead, file_name:Option<&str>) -> Result<Tokenizer, TokenizerError> {
+        Tokenizer::run(reader, file_name, None)
+    }
+
+    // Keeps going after an error: the broken section is skipped up to the next line starting with `[`,
+    // and every error is returned alongside the sections that did parse.
+    pub fn tokenize_recovering(reader:impl BufRead, file_name:Option<&str>) -> (Tokenizer, Vec<TokenizerError>) {
+        let mut errors = Vec::new();
+        match Tokenizer::run(reader, file_name, Some(&mut errors)) {
+            Ok(tokenizer) => (tokenizer, errors),
+            Err(error) => {
+                // `run` only returns errors when it isn't recovering.
+                errors.push(error);
+                (Tokenizer::new(file_name), errors)
             }
         }
+    }
+
+    pub(crate) fn new(file_name:Option<&str>) -> Tokenizer {
+        Tokenizer::after(file_name, Lexer::new(), 0)
+    }
+
+    // A tokenizer that has read `length` bytes with `lexer`, without keeping their tokens.
+    pub(crate) fn after(file_name:Option<&str>, lexer:Lexer, length:usize) -> Tokenizer {
+        Tokenizer {
+            elements: Vec::new(),
+            tokens: Vec::new(),
+            spans: Vec::new(),
+            file_name: file_name.map(String::from),
+            lexer,
+            pending: String::new(),
+            pending_offset: length,
+            line_offset: length,
+            skipping: false,
+        }
+    }
+
+    // Errors are returned right away, or collected into `recovered` when recovering.
+    fn run(mut reader:impl BufRead, file_name:Option<&str>, mut recovered:Option<&mut Vec<TokenizerError>>) -> Result<Tokenizer, TokenizerError> {
+        let mut tokenizer = Tokenizer::new(file_name);
+        let mut line = String::new();
+        while tokenizer.read_line(&mut reader, &mut line, recovered.as_deref_mut())? {}
+        tokenizer.spans = Span::of_tokens(&tokenizer.tokens);
         match tokenizer.elements_from_tokens() {
             Ok(elements) => {
                 tokenizer.elements = elements;
             },
             Err(error) => {
-                return Err(error);
+                match recovered.as_mut() {
+                    Some(errors) => errors.push(error),
+                    None => return Err(error),
+                }
             },
         }
         Ok(tokenizer)
     }
 
+    // Tokenizes the next line of `reader` into `tokens`, using `line` as the buffer. `Ok(false)` once the file
+    // has ended. Tokens up to the last line break or `]` are final, later ones may still change.
+    pub(crate) fn read_line(&mut self, reader:&mut impl BufRead, line:&mut String, mut recovered:Option<&mut Vec<TokenizerError>>) -> Result<bool, TokenizerError> {
+        line.clear();
+        match reader.read_line(line) {
+            Ok(0) => {
+                self.finish(recovered)?;
+                return Ok(false);
+            },
+            Ok(_) => {},
+            Err(error) => {
+                self.lexer.cursor = Span { start: self.line_offset, end: self.line_offset, line: self.lexer.cursor.line + 1, column: 1 };
+                let error = TokenizerError::ReadFailed(error, self.location());
+                match recovered.as_mut() {
+                    Some(errors) => {
+                        errors.push(error);
+                        self.finish(recovered)?;
+                        return Ok(false);
+                    },
+                    None => return Err(error),
+                }
+            }
+        }
+        if self.skipping && !line.trim_start_matches(is_whitespace).starts_with('[') {
+            self.lexer.cursor.line += 1;
+            self.push_skipped(line);
+            self.line_offset += line.len();
+            return Ok(true);
+        }
+        self.skipping = false;
+        // Only a name or value spanning lines needs text from before this line.
+        match self.lexer.current_start() {
+            Some(start) => {
+                self.pending.drain(..start - self.pending_offset);
+                self.pending_offset = start;
+            },
+            None => {
+                self.pending.clear();
+                self.pending_offset = self.line_offset;
+            },
+        }
+        self.pending.push_str(line);
+        let mut builder = TokenBuilder { tokens: &mut self.tokens, text: &self.pending, text_offset: self.pending_offset };
+        if let Err((error, index)) = self.lexer.read_line(&mut builder, line, self.line_offset, self.file_name.as_deref()) {
+            match recovered.as_mut() {
+                Some(errors) => {
+                    errors.push(error);
+                    self.recover(self.line_offset + index, &line[index..]);
+                    self.skipping = true;
+                },
+                None => return Err(error),
+            }
+        }
+        self.line_offset += line.len();
+        Ok(true)
+    }
+
+    // A value still open at the end of the file is an error.
+    fn finish(&mut self, recovered:Option<&mut Vec<TokenizerError>>) -> Result<(), TokenizerError> {
+        let Some(error) = self.lexer.unfinished(self.file_name.as_deref()) else {
+            self.pending.clear();
+            self.pending_offset = self.line_offset;
+            return Ok(());
+        };
+        match recovered {
+            Some(errors) => {
+                errors.push(error);
+                self.recover(self.line_offset, "");
+                Ok(())
+            },
+            None => Err(error),
+        }
+    }
+
+    // Where `recover` would cut the tokens, everything before it is final.
+    pub(crate) fn settled_len(&self) -> usize {
+        let boundary = self.tokens.iter().rposition(|token| matches!(token, Token::BracketLeft | Token::BracketRight | Token::NewLine));
+        match boundary {
+            Some(index) if self.tokens[index] == Token::BracketLeft => index,
+            Some(index) => index + 1,
+            None => 0,
+        }
+    }
+
+    fn push_skipped(&mut self, text:&str) {
+        if let Some(Token::Skipped(string)) = self.tokens.last_mut() {
+            string.push_str(text);
+        }
+        else if !text.is_empty() {
+            self.tokens.push(Token::Skipped(text.to_string()));
+        }
+    }
+
+    // Turns the unfinished header or property into a `Skipped` token ending with `rest_of_line`, which starts at
+    // byte `error_start`. A header is dropped as a whole, finished headers and properties before the error are kept.
+    fn recover(&mut self, error_start:usize, rest_of_line:&str) {
+        let cut = self.settled_len();
+        let mut text = self.tokens.split_off(cut).iter().map(|token| token.to_string()).collect::<String>();
+        if let Some(start) = self.lexer.current_start() {
+            text.push_str(&self.pending[start - self.pending_offset..error_start - self.pending_offset]);
+        }
+        text.push_str(rest_of_line);
+        self.push_skipped(&text);
+        self.lexer.reset();
+    }
+
     pub fn elements_from_tokens(&self) -> Result<Vec<Element>, TokenizerError> {
         let mut elements:Vec<Element> = Vec::new();
         let mut current_element:Element = Element::empty();
-        let mut element_finished:bool = false;
-        for token in self.tokens.iter() {
+        let mut element_started:bool = false;
+        for (index, token) in self.tokens.iter().enumerate() {
             match token {
                 Token::ElementName(name) => {
                     if let Some(string) = name {
-                        match &string[..] { // Convert to &[slice] to match against &str 
-                            "gd_scene" | "connection" => {
-                                current_element.element_type = ElementType::SCENE_DATA;
-                            },
-                            "ext_resource" | "sub_resource" => {
-                                current_element.element_type = ElementType::RESOURCE;
-                            },
-                            "node" => {
-                                current_element.element_type = ElementType::NODE;
-                            },
-                            _ => {
-                                current_element.element_type = ElementType::UNKOWN;
-                            }
-                        }
+                        current_element.element_type = ElementType::from_element_name(string);
                         current_element.element_name = string.to_string();
                     }
                     else {
-                        return Err(TokenizerError::NotFound(ExpectedType::ElementDataName));
+                        return Err(TokenizerError::NotFound(ExpectedType::ElementDataName, self.token_location(index)));
                     }
                 },
                 Token::ElementDataName(name) => {
@@ -347,7 +847,7 @@ impl Tokenizer {
                         current_element.element_data.push(ElementData(string.to_string(), String::new()));
                     }
                     else {
-                        return Err(TokenizerError::NotFound(ExpectedType::ElementDataName));
+                        return Err(TokenizerError::NotFound(ExpectedType::ElementDataName, self.token_location(index)));
                     }
                 },
                 Token::ElementDataValue(value) => {
@@ -356,11 +856,11 @@ impl Tokenizer {
                             data.1 = string.to_string();
                         }
                         else {
-                            return Err(TokenizerError::UnexpectedErr)
+                            return Err(TokenizerError::UnexpectedErr(self.token_location(index)))
                         }
                     }
                     else {
-                        return Err(TokenizerError::NotFound(ExpectedType::ElementDataValue));
+                        return Err(TokenizerError::NotFound(ExpectedType::ElementDataValue, self.token_location(index)));
                     }
                 },
                 Token::PropertyName(name) => {
@@ -368,7 +868,7 @@ impl Tokenizer {
                         current_element.properties.push(Property(string.to_string(), String::new()));
                     }
                     else {
-                        return Err(TokenizerError::NotFound(ExpectedType::PropertyName));
+                        return Err(TokenizerError::NotFound(ExpectedType::PropertyName, self.token_location(index)));
                     }
                 },
                 Token::PropertyValue(value) => {
@@ -377,24 +877,30 @@ impl Tokenizer {
                             data.1 = string.to_string();
                         }
                         else {
-                            return Err(TokenizerError::UnexpectedErr)
+                            return Err(TokenizerError::UnexpectedErr(self.token_location(index)))
                         }
                     }
                     else {
-                        return Err(TokenizerError::NotFound(ExpectedType::PropertyValue));
+                        return Err(TokenizerError::NotFound(ExpectedType::PropertyValue, self.token_location(index)));
                     }
                 },
-                Token::BracketRight => {
-                    element_finished = true;
+                Token::BracketLeft => {
+                    // A new header closes the previous element, properties belong to the header above them.
+                    // Properties above the first header are an element of their own, see `Element::is_preamble`.
+                    if element_started || !current_element.properties.is_empty() {
+                        elements.push(current_element);
+                        current_element = Element::empty();
+                    }
+                    element_started = true;
                 }
                 _ => {}
             }
             current_element.tokens.push(token.clone());
-            if element_finished {
-                element_finished = false;
-                elements.push(current_element);
-                current_element = Element::empty();
-            }
+            current_element.spans.push(self.spans.get(index).cloned().unwrap_or_default());
+        }
+        // A file without headers is kept whole, so it still writes back.
+        if element_started || !current_element.tokens.is_empty() {
+            elements.push(current_element);
         }
         Ok(elements)
     }
@@ -404,8 +910,6 @@ impl Tokenizer {
     }
 
     pub fn reconstruct_tscn_from_tokens(tokens:Vec<Token>) -> String {
-        tokens.iter().map(|token| {
-            token.to_string() + if token.requires_space_suffix() { " " } else { "" }
-        }).collect::<String>()
+        tokens.iter().map(|token| token.to_string()).collect::<String>()
     }
 }
\ No newline at end of file
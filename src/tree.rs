@@ -0,0 +1,390 @@
+use std::{collections::{HashMap, VecDeque}, fmt};
+
+use crate::{element::{Element, ElementType}, scene::{NodePath, Scene}, variant::Variant};
+
+#[derive(Debug)]
+struct TreeNode {
+    name:String,
+    element:Option<usize>, // Index into `Scene::elements`, `None` for nodes that only exist in an instanced scene.
+    parent:Option<usize>,
+    children:Vec<usize>,
+}
+
+// Parent/child view of the `node` elements of a scene.
+#[derive(Debug)]
+pub struct SceneTree<'a> {
+    scene:&'a Scene,
+    nodes:Vec<TreeNode>,
+    paths:HashMap<String, usize>, // Path as written in `parent=`, "." for the root.
+}
+
+#[derive(Clone, Copy)]
+pub struct NodeRef<'a> {
+    tree:&'a SceneTree<'a>,
+    index:usize,
+}
+
+// Reads a quoted data value such as `name="Player"`.
+pub(crate) fn data_string(element:&Element, data_name:&str) -> Option<String> {
+    match element.get_data_variant(data_name) {
+        Ok(Variant::String(string)) | Ok(Variant::NodePath(string)) | Ok(Variant::StringName(string)) => Some(string),
+        _ => None,
+    }
+}
+
+pub(crate) fn join_path(parent_path:&str, name:&str) -> String {
+    if parent_path == "." {
+        String::from(name)
+    }
+    else {
+        format!("{}/{}", parent_path, name)
+    }
+}
+
+// Rewrites `path`, or the start of it, when the node at `old_path` moved to `new_path`.
+// Paths are relative to the root, so the root itself never moves.
+pub(crate) fn moved_path(path:&str, old_path:&str, new_path:&str) -> Option<String> {
+    if old_path == "." {
+        return None;
+    }
+    if path == old_path {
+        return Some(String::from(new_path));
+    }
+    let rest = path.strip_prefix(old_path)?.strip_prefix('/')?;
+    Some(join_path(new_path, rest))
+}
+
+// Whether `path` is `ancestor` or one of its descendants, both in the form used by `parent=`.
+pub(crate) fn is_within(path:&str, ancestor:&str) -> bool {
+    ancestor == "." || path == ancestor || path.strip_prefix(ancestor).is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl<'a> SceneTree<'a> {
+    pub fn new(scene:&'a Scene) -> Self {
+        let mut tree = SceneTree { scene, nodes: Vec::new(), paths: HashMap::new() };
+        for (element_index, element) in scene.elements.iter().enumerate() {
+            if element.element_type != ElementType::NODE {
+                continue;
+            }
+            let name = data_string(element, "name").unwrap_or_default();
+            match data_string(element, "parent") {
+                None => {
+                    if tree.nodes.is_empty() {
+                        tree.push_node(name, Some(element_index), None, String::from("."));
+                    }
+                    else if tree.nodes[0].element.is_none() {
+                        tree.nodes[0].name = name;
+                        tree.nodes[0].element = Some(element_index);
+                    }
+                },
+                Some(parent_path) => {
+                    let parent = tree.get_or_insert_path(&parent_path);
+                    let path = join_path(&tree.path_of(parent), &name);
+                    match tree.paths.get(&path) {
+                        // Already created as a placeholder for one of this node's children.
+                        Some(&existing) => tree.nodes[existing].element = Some(element_index),
+                        None => {
+                            tree.push_node(name, Some(element_index), Some(parent), path);
+                        }
+                    }
+                }
+            }
+        }
+        tree
+    }
+
+    fn push_node(&mut self, name:String, element:Option<usize>, parent:Option<usize>, path:String) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(TreeNode { name, element, parent, children: Vec::new() });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(index);
+        }
+        self.paths.insert(path, index);
+        index
+    }
+
+    // Nodes referenced as parents but not declared in this file (children of instanced scenes) become placeholders.
+    fn get_or_insert_path(&mut self, path:&str) -> usize {
+        if let Some(&index) = self.paths.get(path) {
+            return index;
+        }
+        if self.nodes.is_empty() {
+            self.push_node(String::new(), None, None, String::from("."));
+            if path == "." {
+                return 0;
+            }
+        }
+        let (parent_path, name) = match path.rsplit_once('/') {
+            Some((parent_path, name)) => (parent_path, name),
+            None => (".", path),
+        };
+        let parent = self.get_or_insert_path(parent_path);
+        self.push_node(String::from(name), None, Some(parent), String::from(path))
+    }
+
+    fn path_of(&self, index:usize) -> String {
+        match self.nodes[index].parent {
+            None => String::from("."),
+            Some(parent) => join_path(&self.path_of(parent), &self.nodes[index].name),
+        }
+    }
+
+    pub fn scene(&self) -> &'a Scene {
+        self.scene
+    }
+
+    pub fn root(&'a self) -> Option<NodeRef<'a>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        Some(NodeRef { tree: self, index: 0 })
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Looks up a node by its path from the root, as written in `parent=`: "." is the root, "A/B" a grandchild.
+    pub fn get_node(&'a self, path:&str) -> Option<NodeRef<'a>> {
+        if let Some(&index) = self.paths.get(path) {
+            return Some(NodeRef { tree: self, index });
+        }
+        self.root()?.get_node(&NodePath::from(path))
+    }
+
+    // Finds a node marked `unique_name_in_owner`, the target of `%Name` paths.
+    pub fn find_unique(&'a self, name:&str) -> Option<NodeRef<'a>> {
+        self.depth_first().find(|node| {
+            node.name() == name && node.element().is_some_and(|element| element.get_property_variant("unique_name_in_owner").is_ok_and(|value| value == Variant::Bool(true)))
+        })
+    }
+
+    pub fn depth_first(&'a self) -> DepthFirst<'a> {
+        DepthFirst { tree: self, stack: if self.nodes.is_empty() { Vec::new() } else { vec![0] } }
+    }
+
+    pub fn breadth_first(&'a self) -> BreadthFirst<'a> {
+        BreadthFirst { tree: self, queue: if self.nodes.is_empty() { VecDeque::new() } else { VecDeque::from([0]) } }
+    }
+}
+
+impl<'a> NodeRef<'a> {
+    pub fn name(&self) -> &'a str {
+        &self.tree.nodes[self.index].name
+    }
+
+    // The `[node]` element, `None` if the node is only referenced as a parent and comes from an instanced scene.
+    pub fn element(&self) -> Option<&'a Element> {
+        self.tree.nodes[self.index].element.map(|index| &self.tree.scene.elements[index])
+    }
+
+    pub fn element_index(&self) -> Option<usize> {
+        self.tree.nodes[self.index].element
+    }
+
+    pub fn node_type(&self) -> Option<String> {
+        self.element().and_then(|element| data_string(element, "type"))
+    }
+
+    // The `instance=` value, usually `ExtResource(id)` pointing at a PackedScene.
+    pub fn instance(&self) -> Option<Variant> {
+        self.element()?.get_data_variant("instance").ok()
+    }
+
+    // Instanced scene, or for the root of an inherited scene the scene it inherits from.
+    pub fn is_instance(&self) -> bool {
+        self.instance().is_some()
+    }
+
+    // `res://` path of the instanced scene.
+    pub fn instance_path(&self) -> Option<String> {
+        let resources = self.tree.scene.resources();
+        resources.resolve(&self.instance()?)?.path.clone()
+    }
+
+    // Scene path of an `instance_placeholder=` node, only loaded by the game when requested.
+    pub fn placeholder_path(&self) -> Option<String> {
+        data_string(self.element()?, "instance_placeholder")
+    }
+
+    pub fn is_placeholder(&self) -> bool {
+        self.placeholder_path().is_some()
+    }
+
+    // Position among the children declared by the instanced scene, written as `index=` when it changed.
+    pub fn instance_index(&self) -> Option<i64> {
+        match self.element()?.get_data_variant("index").ok()? {
+            Variant::Int(index) => Some(index),
+            Variant::String(index) => index.parse::<i64>().ok(),
+            _ => None,
+        }
+    }
+
+    // The instance this node comes from: itself if it is one, or the closest instanced ancestor when the node
+    // only overrides properties of a node from that scene. `None` for nodes declared with a type in this file.
+    pub fn instance_owner(&self) -> Option<NodeRef<'a>> {
+        if self.is_instance() {
+            return Some(*self);
+        }
+        if self.element().is_some_and(|element| element.get_data_value("type").is_ok()) {
+            return None;
+        }
+        let mut node = self.parent();
+        while let Some(ancestor) = node {
+            if ancestor.is_instance() {
+                return Some(ancestor);
+            }
+            node = ancestor.parent();
+        }
+        None
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.tree.nodes[self.index].parent.is_none()
+    }
+
+    pub fn parent(&self) -> Option<NodeRef<'a>> {
+        self.tree.nodes[self.index].parent.map(|index| NodeRef { tree: self.tree, index })
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = NodeRef<'a>> + 'a {
+        let tree = self.tree;
+        tree.nodes[self.index].children.iter().map(move |&index| NodeRef { tree, index })
+    }
+
+    pub fn child_count(&self) -> usize {
+        self.tree.nodes[self.index].children.len()
+    }
+
+    pub fn find_child(&self, name:&str, recursive:bool) -> Option<NodeRef<'a>> {
+        if recursive {
+            return self.depth_first().skip(1).find(|node| node.name() == name);
+        }
+        self.children().find(|node| node.name() == name)
+    }
+
+    // Path from the root in the form used by `parent=`.
+    pub fn path(&self) -> String {
+        self.tree.path_of(self.index)
+    }
+
+    // Resolves a NodePath relative to this node, property subnames are ignored.
+    pub fn get_node(&self, path:&NodePath) -> Option<NodeRef<'a>> {
+        if !path.is_valid() {
+            return None;
+        }
+        let mut node = *self;
+        let mut names = path.names();
+        if path.is_absolute() {
+            // Scenes are rooted at `/root/<SceneRoot>` when running in the engine.
+            let root = self.tree.root()?;
+            match names {
+                [root_viewport, root_name, rest @ ..] if root_viewport == "root" && root_name == root.name() => {
+                    node = root;
+                    names = rest;
+                },
+                _ => {
+                    return None;
+                }
+            }
+        }
+        for name in names.iter() {
+            node = match &name[..] {
+                "." => node,
+                ".." => node.parent()?,
+                _ if name.starts_with('%') => self.tree.find_unique(&name[1..])?,
+                _ => node.children().find(|child| child.name() == name)?,
+            };
+        }
+        Some(node)
+    }
+
+    // Relative path from this node to `other`, as Godot writes NodePath properties.
+    pub fn path_to(&self, other:&NodeRef<'a>) -> NodePath {
+        let from = self.root_to_self();
+        let to = other.root_to_self();
+        let common = from.iter().zip(to.iter()).take_while(|(a, b)| a == b).count();
+        let mut names = vec![String::from(".."); from.len() - common];
+        names.extend(to[common..].iter().map(|node| node.name().to_string()));
+        if names.is_empty() {
+            names.push(String::from("."));
+        }
+        NodePath::new(false, names, Vec::new())
+    }
+
+    // Absolute path of this node when the scene runs as the main scene, e.g. `/root/Room/Tree`.
+    pub fn absolute_path(&self) -> NodePath {
+        let mut names = vec![String::from("root")];
+        names.extend(self.root_to_self().iter().map(|node| node.name().to_string()));
+        NodePath::new(true, names, Vec::new())
+    }
+
+    // Ancestors starting at the root, ending with this node.
+    fn root_to_self(&self) -> Vec<NodeRef<'a>> {
+        let mut chain = vec![*self];
+        while let Some(parent) = chain[chain.len() - 1].parent() {
+            chain.push(parent);
+        }
+        chain.reverse();
+        chain
+    }
+
+    pub fn depth(&self) -> usize {
+        self.root_to_self().len() - 1
+    }
+
+    // This node followed by its descendants, parents before children.
+    pub fn depth_first(&self) -> DepthFirst<'a> {
+        DepthFirst { tree: self.tree, stack: vec![self.index] }
+    }
+
+    pub fn breadth_first(&self) -> BreadthFirst<'a> {
+        BreadthFirst { tree: self.tree, queue: VecDeque::from([self.index]) }
+    }
+}
+
+impl fmt::Debug for NodeRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NodeRef({})", self.path())
+    }
+}
+
+impl PartialEq for NodeRef<'_> {
+    fn eq(&self, other:&Self) -> bool {
+        std::ptr::eq(self.tree, other.tree) && self.index == other.index
+    }
+}
+
+pub struct DepthFirst<'a> {
+    tree:&'a SceneTree<'a>,
+    stack:Vec<usize>,
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = NodeRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.stack.pop()?;
+        self.stack.extend(self.tree.nodes[index].children.iter().rev());
+        Some(NodeRef { tree: self.tree, index })
+    }
+}
+
+pub struct BreadthFirst<'a> {
+    tree:&'a SceneTree<'a>,
+    queue:VecDeque<usize>,
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = NodeRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.queue.pop_front()?;
+        self.queue.extend(self.tree.nodes[index].children.iter());
+        Some(NodeRef { tree: self.tree, index })
+    }
+}
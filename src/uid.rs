@@ -0,0 +1,148 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use crate::{element::Element, scene, tree::data_string, variant::{ResourceId, Variant}};
+
+// Godot writes UIDs in base 34: `a` to `y`, then `0` to `8`.
+const LETTERS:u64 = 25;
+const BASE:u64 = LETTERS + 9;
+
+// A Godot 4 resource UID, written as `uid://` followed by the id in base 34.
+// Files keep their UID when moved, so it finds a resource whose `path=` has gone stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Uid(u64);
+
+impl Uid {
+    pub fn from_id(id:u64) -> Self {
+        Uid(id & i64::MAX as u64)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+
+    // Parses `uid://...` the way Godot does, `None` for `uid://<invalid>` and other malformed text.
+    pub fn from_text(text:&str) -> Option<Self> {
+        let digits = text.trim().strip_prefix("uid://")?;
+        if digits.is_empty() {
+            return None;
+        }
+        let mut id:u64 = 0;
+        for c in digits.chars() {
+            // Like Godot, `z` and `9` are read although they are never written.
+            let digit = match c {
+                'a'..='z' => c as u64 - 'a' as u64,
+                '0'..='9' => c as u64 - '0' as u64 + LETTERS,
+                _ => return None,
+            };
+            id = id.wrapping_mul(BASE).wrapping_add(digit);
+        }
+        Some(Uid::from_id(id))
+    }
+}
+
+impl FromStr for Uid {
+    type Err = ();
+
+    fn from_str(s:&str) -> Result<Self, Self::Err> {
+        Uid::from_text(s).ok_or(())
+    }
+}
+
+impl fmt::Display for Uid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut digits = Vec::new();
+        let mut id = self.0;
+        loop {
+            let digit = (id % BASE) as u8;
+            digits.push(if (digit as u64) < LETTERS { b'a' + digit } else { b'0' + digit - LETTERS as u8 });
+            id /= BASE;
+            if id == 0 {
+                break;
+            }
+        }
+        digits.reverse();
+        write!(f, "uid://{}", String::from_utf8_lossy(&digits))
+    }
+}
+
+// Which file each UID belongs to, keyed by `res://` path.
+#[derive(Debug, Default, Clone)]
+pub struct UidIndex {
+    paths:BTreeMap<Uid, Vec<String>>,
+}
+
+impl UidIndex {
+    pub fn new() -> Self {
+        UidIndex::default()
+    }
+
+    pub fn insert(&mut self, uid:Uid, res_path:&str) {
+        let paths = self.paths.entry(uid).or_default();
+        if !paths.iter().any(|path| path == res_path) {
+            paths.push(String::from(res_path));
+            paths.sort();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    // The file holding `uid`, `None` if no file or more than one claims it.
+    pub fn path(&self, uid:&Uid) -> Option<&str> {
+        match self.paths.get(uid).map(|paths| &paths[..]) {
+            Some([path]) => Some(path),
+            _ => None,
+        }
+    }
+
+    pub fn uid(&self, res_path:&str) -> Option<Uid> {
+        self.paths.iter().find(|(_, paths)| paths.iter().any(|path| path == res_path)).map(|(uid, _)| *uid)
+    }
+
+    // UIDs claimed by several files, usually a scene copied outside the editor.
+    pub fn duplicates(&self) -> Vec<(Uid, &[String])> {
+        self.paths.iter().filter(|(_, paths)| paths.len() > 1).map(|(uid, paths)| (*uid, &paths[..])).collect::<Vec<(Uid, &[String])>>()
+    }
+}
+
+// An `[ext_resource]` whose `path=` was rewritten to where its UID now points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathRepair {
+    pub element:usize, // Index of the `[ext_resource]` element.
+    pub id:ResourceId,
+    pub uid:Uid,
+    pub old_path:String,
+    pub new_path:String,
+}
+
+// The UID of a scene or resource, from its `[gd_scene]` or `[gd_resource]` header.
+pub(crate) fn header_uid(elements:&[Element]) -> Option<Uid> {
+    let header = elements.iter().find(|element| element.element_name == "gd_scene" || element.element_name == "gd_resource")?;
+    Uid::from_text(&data_string(header, "uid")?)
+}
+
+// Points `path=` of every ext resource at the file its UID belongs to.
+// Ext resources without a UID, or with one the index doesn't know, are left alone.
+pub(crate) fn repair_paths(elements:&mut [Element], index:&UidIndex) -> Vec<PathRepair> {
+    let format = scene::file_format(elements);
+    let mut repairs = Vec::new();
+    for (element_index, element) in elements.iter_mut().enumerate() {
+        if element.element_name != "ext_resource" {
+            continue;
+        }
+        let uid = data_string(element, "uid").and_then(|uid| Uid::from_text(&uid));
+        let (Some(uid), Some(old_path), Ok(id)) = (uid, data_string(element, "path"), element.get_data_value("id")) else {
+            continue;
+        };
+        if let Some(new_path) = index.path(&uid).filter(|new_path| *new_path != old_path) {
+            let _ = element.update_data("path", &Variant::String(String::from(new_path)).to_text(format));
+            repairs.push(PathRepair { element: element_index, id: ResourceId::from_data_value(&id), uid, old_path, new_path: String::from(new_path) });
+        }
+    }
+    repairs
+}
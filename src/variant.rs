@@ -0,0 +1,907 @@
+use std::{fmt, str::FromStr};
+
+// Resource ids are integers in Godot 3 (`ExtResource( 1 )`) and strings in Godot 4 (`ExtResource("1_abc")`).
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum ResourceId {
+    Int(i64),
+    String(String),
+}
+
+impl ResourceId {
+    pub fn from_data_value(value:&str) -> Self {
+        let unquoted = value.trim().trim_matches('"');
+        if value.trim().starts_with('"') {
+            return ResourceId::String(unescape_string(unquoted));
+        }
+        match unquoted.parse::<i64>() {
+            Ok(int) => ResourceId::Int(int),
+            Err(_) => ResourceId::String(unquoted.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceId::Int(int) => write!(f, "{}", int),
+            ResourceId::String(string) => write!(f, "\"{}\"", escape_string(string)),
+        }
+    }
+}
+
+// Typed view of a value written in Godot's text serialization format.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Variant {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    StringName(String),
+    NodePath(String),
+    Vector2(f64, f64),
+    Vector2i(i64, i64),
+    Vector3(f64, f64, f64),
+    Vector3i(i64, i64, i64),
+    Vector4(f64, f64, f64, f64),
+    Vector4i(i64, i64, i64, i64),
+    Rect2(f64, f64, f64, f64),
+    Rect2i(i64, i64, i64, i64),
+    Transform2D([f64; 6]),
+    Transform3D([f64; 12]),
+    Basis([f64; 9]),
+    Quaternion(f64, f64, f64, f64),
+    Aabb([f64; 6]),
+    Plane(f64, f64, f64, f64),
+    Projection([f64; 16]),
+    Color(f64, f64, f64, f64),
+    Array(Vec<Variant>),
+    TypedArray(String, Vec<Variant>), // 0: Element type as written, e.g. `int` or `ExtResource("1_abc")`
+    Dictionary(Vec<(Variant, Variant)>),
+    TypedDictionary(String, Vec<(Variant, Variant)>), // 0: Key and value types as written, e.g. `String, int`
+    PackedByteArray(Vec<u8>),
+    PackedInt32Array(Vec<i32>),
+    PackedInt64Array(Vec<i64>),
+    PackedFloat32Array(Vec<f32>),
+    PackedFloat64Array(Vec<f64>),
+    PackedStringArray(Vec<String>),
+    PackedVector2Array(Vec<(f64, f64)>),
+    PackedVector3Array(Vec<(f64, f64, f64)>),
+    PackedVector4Array(Vec<(f64, f64, f64, f64)>),
+    PackedColorArray(Vec<(f64, f64, f64, f64)>),
+    ExtResource(ResourceId),
+    SubResource(ResourceId),
+    Resource(String), // Godot 3 `Resource( "res://..." )`
+    Object(String, Vec<(String, Variant)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantError {
+    UnexpectedChar(char, usize),
+    UnexpectedEnd,
+    UnknownIdentifier(String),
+    InvalidNumber(String),
+    InvalidArguments(String),
+}
+
+impl fmt::Display for VariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VariantError::UnexpectedChar(c, index) => write!(f, "unexpected character '{}' at offset {}", c, index),
+            VariantError::UnexpectedEnd => write!(f, "unexpected end of value"),
+            VariantError::UnknownIdentifier(ident) => write!(f, "unknown identifier '{}'", ident),
+            VariantError::InvalidNumber(number) => write!(f, "invalid number '{}'", number),
+            VariantError::InvalidArguments(constructor) => write!(f, "invalid arguments for {}", constructor),
+        }
+    }
+}
+
+impl std::error::Error for VariantError {}
+
+impl Variant {
+    pub fn parse(string:&str) -> Result<Variant, VariantError> {
+        let mut parser = Parser { src: string, pos: 0 };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if let Some(c) = parser.peek() {
+            return Err(VariantError::UnexpectedChar(c, parser.pos));
+        }
+        Ok(value)
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Variant::Nil => "Nil",
+            Variant::Bool(..) => "bool",
+            Variant::Int(..) => "int",
+            Variant::Float(..) => "float",
+            Variant::String(..) => "String",
+            Variant::StringName(..) => "StringName",
+            Variant::NodePath(..) => "NodePath",
+            Variant::Vector2(..) => "Vector2",
+            Variant::Vector2i(..) => "Vector2i",
+            Variant::Vector3(..) => "Vector3",
+            Variant::Vector3i(..) => "Vector3i",
+            Variant::Vector4(..) => "Vector4",
+            Variant::Vector4i(..) => "Vector4i",
+            Variant::Rect2(..) => "Rect2",
+            Variant::Rect2i(..) => "Rect2i",
+            Variant::Transform2D(..) => "Transform2D",
+            Variant::Transform3D(..) => "Transform3D",
+            Variant::Basis(..) => "Basis",
+            Variant::Quaternion(..) => "Quaternion",
+            Variant::Aabb(..) => "AABB",
+            Variant::Plane(..) => "Plane",
+            Variant::Projection(..) => "Projection",
+            Variant::Color(..) => "Color",
+            Variant::Array(..) | Variant::TypedArray(..) => "Array",
+            Variant::Dictionary(..) | Variant::TypedDictionary(..) => "Dictionary",
+            Variant::PackedByteArray(..) => "PackedByteArray",
+            Variant::PackedInt32Array(..) => "PackedInt32Array",
+            Variant::PackedInt64Array(..) => "PackedInt64Array",
+            Variant::PackedFloat32Array(..) => "PackedFloat32Array",
+            Variant::PackedFloat64Array(..) => "PackedFloat64Array",
+            Variant::PackedStringArray(..) => "PackedStringArray",
+            Variant::PackedVector2Array(..) => "PackedVector2Array",
+            Variant::PackedVector3Array(..) => "PackedVector3Array",
+            Variant::PackedVector4Array(..) => "PackedVector4Array",
+            Variant::PackedColorArray(..) => "PackedColorArray",
+            Variant::ExtResource(..) => "ExtResource",
+            Variant::SubResource(..) => "SubResource",
+            Variant::Resource(..) => "Resource",
+            Variant::Object(..) => "Object",
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Variant::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Variant::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Ints are accepted too, Godot writes whole floats inside constructors without a decimal point.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Variant::Float(value) => Some(*value),
+            Variant::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Variant::String(string) | Variant::StringName(string) | Variant::NodePath(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Variant>> {
+        match self {
+            Variant::Array(array) | Variant::TypedArray(_, array) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub fn as_dictionary(&self) -> Option<&Vec<(Variant, Variant)>> {
+        match self {
+            Variant::Dictionary(dictionary) | Variant::TypedDictionary(_, dictionary) => Some(dictionary),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Variant {
+    type Err = VariantError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Variant::parse(string)
+    }
+}
+
+impl From<bool> for Variant {
+    fn from(value: bool) -> Self {
+        Variant::Bool(value)
+    }
+}
+
+impl From<i64> for Variant {
+    fn from(value: i64) -> Self {
+        Variant::Int(value)
+    }
+}
+
+impl From<f64> for Variant {
+    fn from(value: f64) -> Self {
+        Variant::Float(value)
+    }
+}
+
+impl From<&str> for Variant {
+    fn from(value: &str) -> Self {
+        Variant::String(value.to_string())
+    }
+}
+
+impl From<String> for Variant {
+    fn from(value: String) -> Self {
+        Variant::String(value)
+    }
+}
+
+struct Parser<'a> {
+    src:&'a str,
+    pos:usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    // Skips whitespace and `;` comments, which may appear between the lines of multi-line values.
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ';' {
+                let rest = &self.src[self.pos..];
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+                continue;
+            }
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn expect(&mut self, expected:char) -> Result<(), VariantError> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(VariantError::UnexpectedChar(c, self.pos - c.len_utf8())),
+            None => Err(VariantError::UnexpectedEnd),
+        }
+    }
+
+    // Consumes `c` if it is the next non-whitespace char.
+    fn eat(&mut self, c:char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            return true;
+        }
+        false
+    }
+
+    fn parse_value(&mut self) -> Result<Variant, VariantError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') => Ok(Variant::String(self.parse_string()?)),
+            Some('&') => {
+                self.next();
+                Ok(Variant::StringName(self.parse_string()?))
+            },
+            Some('^') => {
+                self.next();
+                Ok(Variant::NodePath(self.parse_string()?))
+            },
+            Some('[') => Ok(Variant::Array(self.parse_array()?)),
+            Some('{') => Ok(Variant::Dictionary(self.parse_dictionary()?)),
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => self.parse_number(),
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let ident = self.parse_identifier();
+                self.parse_identifier_value(ident)
+            },
+            Some(c) => Err(VariantError::UnexpectedChar(c, self.pos)),
+            None => Err(VariantError::UnexpectedEnd),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, VariantError> {
+        self.expect('"')?;
+        let start = self.pos;
+        let mut escaped = false;
+        while let Some(c) = self.next() {
+            if escaped {
+                escaped = false;
+            }
+            else if c == '\\' {
+                escaped = true;
+            }
+            else if c == '"' {
+                return Ok(unescape_string(&self.src[start..self.pos - 1]));
+            }
+        }
+        Err(VariantError::UnexpectedEnd)
+    }
+
+    fn parse_identifier(&mut self) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !(c.is_alphanumeric() || c == '_') {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        self.src[start..self.pos].to_string()
+    }
+
+    fn parse_number(&mut self) -> Result<Variant, VariantError> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            let in_exponent = matches!(self.src[start..self.pos].chars().last(), Some('e' | 'E'));
+            if c.is_ascii_alphanumeric() || c == '.' || c == '_' || ((c == '-' || c == '+') && (self.pos == start || in_exponent)) {
+                self.pos += c.len_utf8();
+            }
+            else {
+                break;
+            }
+        }
+        let number = &self.src[start..self.pos];
+        match number {
+            "-inf" | "inf_neg" => return Ok(Variant::Float(f64::NEG_INFINITY)),
+            "+inf" => return Ok(Variant::Float(f64::INFINITY)),
+            _ => {}
+        }
+        let is_float = number.contains(['.', 'e', 'E']) && !number.contains(['x', 'X']);
+        if !is_float {
+            if let Some(int) = parse_int(number) {
+                return Ok(Variant::Int(int));
+            }
+        }
+        match number.parse::<f64>() {
+            Ok(float) => Ok(Variant::Float(float)),
+            Err(_) => Err(VariantError::InvalidNumber(number.to_string())),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Vec<Variant>, VariantError> {
+        self.expect('[')?;
+        let mut array = Vec::new();
+        if self.eat(']') {
+            return Ok(array);
+        }
+        loop {
+            array.push(self.parse_value()?);
+            if self.eat(']') {
+                return Ok(array);
+            }
+            self.expect(',')?;
+            // Tolerate a trailing comma.
+            if self.eat(']') {
+                return Ok(array);
+            }
+        }
+    }
+
+    fn parse_dictionary(&mut self) -> Result<Vec<(Variant, Variant)>, VariantError> {
+        self.expect('{')?;
+        let mut dictionary = Vec::new();
+        if self.eat('}') {
+            return Ok(dictionary);
+        }
+        loop {
+            let key = self.parse_value()?;
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            dictionary.push((key, value));
+            if self.eat('}') {
+                return Ok(dictionary);
+            }
+            self.expect(',')?;
+            if self.eat('}') {
+                return Ok(dictionary);
+            }
+        }
+    }
+
+    // Reads the raw text of a `[...]` type annotation as used by typed arrays and dictionaries.
+    fn parse_type_annotation(&mut self) -> Result<String, VariantError> {
+        self.expect('[')?;
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            match c {
+                '[' | '(' => depth += 1,
+                ')' => depth -= 1,
+                ']' if depth == 0 => return Ok(self.src[start..self.pos - 1].trim().to_string()),
+                ']' => depth -= 1,
+                _ => {}
+            }
+        }
+        Err(VariantError::UnexpectedEnd)
+    }
+
+    fn parse_arguments(&mut self) -> Result<Vec<Variant>, VariantError> {
+        self.expect('(')?;
+        let mut args = Vec::new();
+        if self.eat(')') {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.parse_value()?);
+            if self.eat(')') {
+                return Ok(args);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn parse_identifier_value(&mut self, ident:String) -> Result<Variant, VariantError> {
+        match &ident[..] {
+            "true" => return Ok(Variant::Bool(true)),
+            "false" => return Ok(Variant::Bool(false)),
+            "null" | "nil" => return Ok(Variant::Nil),
+            "inf" => return Ok(Variant::Float(f64::INFINITY)),
+            "inf_neg" => return Ok(Variant::Float(f64::NEG_INFINITY)),
+            "nan" => return Ok(Variant::Float(f64::NAN)),
+            "Array" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    let element_type = self.parse_type_annotation()?;
+                    self.expect('(')?;
+                    let array = self.parse_array()?;
+                    self.expect(')')?;
+                    return Ok(Variant::TypedArray(element_type, array));
+                }
+            },
+            "Dictionary" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    let types = self.parse_type_annotation()?;
+                    self.expect('(')?;
+                    let dictionary = self.parse_dictionary()?;
+                    self.expect(')')?;
+                    return Ok(Variant::TypedDictionary(types, dictionary));
+                }
+            },
+            "Object" => return self.parse_object(),
+            _ => {}
+        }
+
+        self.skip_whitespace();
+        if self.peek() != Some('(') {
+            return Err(VariantError::UnknownIdentifier(ident));
+        }
+        let args = self.parse_arguments()?;
+        let invalid = || VariantError::InvalidArguments(ident.clone());
+        let variant = match &ident[..] {
+            "Vector2" => {
+                let [x, y] = floats::<2>(&args).ok_or_else(invalid)?;
+                Variant::Vector2(x, y)
+            },
+            "Vector2i" => {
+                let [x, y] = ints::<2>(&args).ok_or_else(invalid)?;
+                Variant::Vector2i(x, y)
+            },
+            "Vector3" => {
+                let [x, y, z] = floats::<3>(&args).ok_or_else(invalid)?;
+                Variant::Vector3(x, y, z)
+            },
+            "Vector3i" => {
+                let [x, y, z] = ints::<3>(&args).ok_or_else(invalid)?;
+                Variant::Vector3i(x, y, z)
+            },
+            "Vector4" => {
+                let [x, y, z, w] = floats::<4>(&args).ok_or_else(invalid)?;
+                Variant::Vector4(x, y, z, w)
+            },
+            "Vector4i" => {
+                let [x, y, z, w] = ints::<4>(&args).ok_or_else(invalid)?;
+                Variant::Vector4i(x, y, z, w)
+            },
+            "Rect2" => {
+                let [x, y, w, h] = floats::<4>(&args).ok_or_else(invalid)?;
+                Variant::Rect2(x, y, w, h)
+            },
+            "Rect2i" => {
+                let [x, y, w, h] = ints::<4>(&args).ok_or_else(invalid)?;
+                Variant::Rect2i(x, y, w, h)
+            },
+            "Transform2D" => Variant::Transform2D(floats::<6>(&args).ok_or_else(invalid)?),
+            "Transform3D" | "Transform" => Variant::Transform3D(floats::<12>(&args).ok_or_else(invalid)?),
+            "Basis" => Variant::Basis(floats::<9>(&args).ok_or_else(invalid)?),
+            "Quaternion" | "Quat" => {
+                let [x, y, z, w] = floats::<4>(&args).ok_or_else(invalid)?;
+                Variant::Quaternion(x, y, z, w)
+            },
+            "AABB" => Variant::Aabb(floats::<6>(&args).ok_or_else(invalid)?),
+            "Plane" => {
+                let [a, b, c, d] = floats::<4>(&args).ok_or_else(invalid)?;
+                Variant::Plane(a, b, c, d)
+            },
+            "Projection" => Variant::Projection(floats::<16>(&args).ok_or_else(invalid)?),
+            "Color" => {
+                if args.len() == 3 {
+                    let [r, g, b] = floats::<3>(&args).ok_or_else(invalid)?;
+                    Variant::Color(r, g, b, 1.0)
+                }
+                else {
+                    let [r, g, b, a] = floats::<4>(&args).ok_or_else(invalid)?;
+                    Variant::Color(r, g, b, a)
+                }
+            },
+            "NodePath" => Variant::NodePath(single_string(&args).ok_or_else(invalid)?),
+            "StringName" => Variant::StringName(single_string(&args).ok_or_else(invalid)?),
+            "Resource" => Variant::Resource(single_string(&args).ok_or_else(invalid)?),
+            "ExtResource" => Variant::ExtResource(resource_id(&args).ok_or_else(invalid)?),
+            "SubResource" => Variant::SubResource(resource_id(&args).ok_or_else(invalid)?),
+            "PackedByteArray" | "PoolByteArray" => {
+                // Godot 4.3+ may store byte arrays as a single base64 string.
+                if let [Variant::String(encoded)] = &args[..] {
+                    Variant::PackedByteArray(base64_decode(encoded).ok_or_else(invalid)?)
+                }
+                else {
+                    Variant::PackedByteArray(args.iter().map(|arg| arg.as_int().and_then(|int| u8::try_from(int).ok())).collect::<Option<Vec<u8>>>().ok_or_else(invalid)?)
+                }
+            },
+            "PackedInt32Array" | "PoolIntArray" => {
+                Variant::PackedInt32Array(args.iter().map(|arg| arg.as_int().and_then(|int| i32::try_from(int).ok())).collect::<Option<Vec<i32>>>().ok_or_else(invalid)?)
+            },
+            "PackedInt64Array" => {
+                Variant::PackedInt64Array(args.iter().map(|arg| arg.as_int()).collect::<Option<Vec<i64>>>().ok_or_else(invalid)?)
+            },
+            "PackedFloat32Array" | "PoolRealArray" => {
+                Variant::PackedFloat32Array(args.iter().map(|arg| arg.as_float().map(|float| float as f32)).collect::<Option<Vec<f32>>>().ok_or_else(invalid)?)
+            },
+            "PackedFloat64Array" => {
+                Variant::PackedFloat64Array(args.iter().map(|arg| arg.as_float()).collect::<Option<Vec<f64>>>().ok_or_else(invalid)?)
+            },
+            "PackedStringArray" | "PoolStringArray" => {
+                Variant::PackedStringArray(args.iter().map(|arg| match arg {
+                    Variant::String(string) => Some(string.clone()),
+                    _ => None,
+                }).collect::<Option<Vec<String>>>().ok_or_else(invalid)?)
+            },
+            "PackedVector2Array" | "PoolVector2Array" => {
+                Variant::PackedVector2Array(chunks::<2>(&args).ok_or_else(invalid)?.into_iter().map(|[x, y]| (x, y)).collect())
+            },
+            "PackedVector3Array" | "PoolVector3Array" => {
+                Variant::PackedVector3Array(chunks::<3>(&args).ok_or_else(invalid)?.into_iter().map(|[x, y, z]| (x, y, z)).collect())
+            },
+            "PackedVector4Array" => {
+                Variant::PackedVector4Array(chunks::<4>(&args).ok_or_else(invalid)?.into_iter().map(|[x, y, z, w]| (x, y, z, w)).collect())
+            },
+            "PackedColorArray" | "PoolColorArray" => {
+                Variant::PackedColorArray(chunks::<4>(&args).ok_or_else(invalid)?.into_iter().map(|[r, g, b, a]| (r, g, b, a)).collect())
+            },
+            _ => return Err(VariantError::UnknownIdentifier(ident)),
+        };
+        Ok(variant)
+    }
+
+    // Object(ClassName,"property":value,...)
+    fn parse_object(&mut self) -> Result<Variant, VariantError> {
+        self.expect('(')?;
+        self.skip_whitespace();
+        let class = self.parse_identifier();
+        let mut properties = Vec::new();
+        loop {
+            if self.eat(')') {
+                return Ok(Variant::Object(class, properties));
+            }
+            self.expect(',')?;
+            self.skip_whitespace();
+            let name = self.parse_string()?;
+            self.expect(':')?;
+            properties.push((name, self.parse_value()?));
+        }
+    }
+}
+
+fn parse_int(number:&str) -> Option<i64> {
+    let (negative, digits) = match number.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, number.strip_prefix('+').unwrap_or(number)),
+    };
+    let (radix, digits) = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        (16, hex)
+    }
+    else if let Some(bin) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        (2, bin)
+    }
+    else {
+        (10, digits)
+    };
+    let digits = digits.replace('_', "");
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+    let magnitude = u64::from_str_radix(&digits, radix).ok()?;
+    if radix != 10 {
+        // Hex and binary are bit patterns, `0xFFFFFFFFFFFFFFFF` is -1.
+        let value = magnitude as i64;
+        return Some(if negative { value.wrapping_neg() } else { value });
+    }
+    if negative {
+        0i64.checked_sub_unsigned(magnitude)
+    }
+    else {
+        i64::try_from(magnitude).ok()
+    }
+}
+
+fn floats<const N:usize>(args:&[Variant]) -> Option<[f64; N]> {
+    if args.len() != N {
+        return None;
+    }
+    let mut out = [0.0; N];
+    for (slot, arg) in out.iter_mut().zip(args) {
+        *slot = arg.as_float()?;
+    }
+    Some(out)
+}
+
+fn ints<const N:usize>(args:&[Variant]) -> Option<[i64; N]> {
+    if args.len() != N {
+        return None;
+    }
+    let mut out = [0; N];
+    for (slot, arg) in out.iter_mut().zip(args) {
+        *slot = arg.as_int()?;
+    }
+    Some(out)
+}
+
+fn chunks<const N:usize>(args:&[Variant]) -> Option<Vec<[f64; N]>> {
+    if !args.len().is_multiple_of(N) {
+        return None;
+    }
+    args.chunks(N).map(floats::<N>).collect()
+}
+
+fn single_string(args:&[Variant]) -> Option<String> {
+    match args {
+        [Variant::String(string)] => Some(string.clone()),
+        _ => None,
+    }
+}
+
+fn resource_id(args:&[Variant]) -> Option<ResourceId> {
+    match args {
+        [Variant::Int(int)] => Some(ResourceId::Int(*int)),
+        [Variant::String(string)] => Some(ResourceId::String(string.clone())),
+        _ => None,
+    }
+}
+
+fn base64_decode(encoded:&str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut buffer:u32 = 0;
+    let mut bits = 0;
+    for c in encoded.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+pub fn unescape_string(string:&str) -> String {
+    let mut out = String::with_capacity(string.len());
+    let mut chars = string.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('b') => out.push('\u{8}'),
+            Some('f') => out.push('\u{c}'),
+            Some('u') => {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    out.push(c);
+                }
+            },
+            Some('U') => {
+                let hex = chars.by_ref().take(6).collect::<String>();
+                if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    out.push(c);
+                }
+            },
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+// Godot only escapes backslashes and quotes when writing strings, newlines are kept as-is.
+pub fn escape_string(string:&str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Which engine's text conventions to follow when writing values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    Godot3, // format=2 files, e.g. `Vector2( 0, 1 )`, `PoolStringArray( "a" )`, `[ 1, 2 ]`
+    #[default]
+    Godot4, // format=3 files, e.g. `Vector2(0, 1)`, `PackedStringArray("a")`, `[1, 2]`
+}
+
+impl Format {
+    // Maps the `format=` value of a file header to the engine that writes it.
+    pub fn from_file_format(format:i64) -> Self {
+        if format <= 2 {
+            Format::Godot3
+        }
+        else {
+            Format::Godot4
+        }
+    }
+}
+
+// Formats a float the way it appears inside constructors, e.g. the `1` in `Vector2(1, 0.5)`.
+pub fn format_real(value:f64) -> String {
+    format_shortest(value)
+}
+
+// Like `format_real` for a value stored as `f32`, which is written as the shortest text of the `f32` and not of
+// its `f64` widening, e.g. `0.1` instead of `0.10000000149011612`.
+pub fn format_real32(value:f32) -> String {
+    format_shortest(value)
+}
+
+fn format_shortest<T:Copy + Into<f64> + fmt::Display + fmt::LowerExp>(value:T) -> String {
+    let wide:f64 = value.into();
+    if wide.is_nan() {
+        return String::from("nan");
+    }
+    if wide.is_infinite() {
+        return String::from(if wide > 0.0 { "inf" } else { "inf_neg" });
+    }
+    // Same switch to exponent notation as the `%g` printf format Godot uses.
+    let abs = wide.abs();
+    if abs != 0.0 && !(1e-4..1e6).contains(&abs) {
+        // Rust writes `1.5e-7`, Godot writes `1.5e-07`.
+        let formatted = format!("{:e}", value);
+        if let Some((mantissa, exponent)) = formatted.split_once('e') {
+            let (sign, digits) = match exponent.strip_prefix('-') {
+                Some(digits) => ('-', digits),
+                None => ('+', exponent),
+            };
+            return format!("{}e{}{:0>2}", mantissa, sign, digits);
+        }
+        return formatted;
+    }
+    format!("{}", value)
+}
+
+// Formats a standalone float, which Godot always writes with a decimal point.
+pub fn format_float(value:f64) -> String {
+    let mut formatted = format_real(value);
+    if value.is_finite() && !formatted.contains(['.', 'e']) {
+        formatted += ".0";
+    }
+    formatted
+}
+
+fn join<T>(items:&[T], format:impl Fn(&T) -> String) -> String {
+    items.iter().map(format).collect::<Vec<String>>().join(", ")
+}
+
+fn reals(values:&[f64]) -> String {
+    join(values, |v| format_real(*v))
+}
+
+// Godot 3 pads constructor arguments with spaces, Godot 4 doesn't.
+fn constructor(name:&str, args:&str, format:Format) -> String {
+    match format {
+        Format::Godot3 => format!("{}( {} )", name, args),
+        Format::Godot4 => format!("{}({})", name, args),
+    }
+}
+
+impl Variant {
+    pub fn to_text(&self, format:Format) -> String {
+        let godot3 = format == Format::Godot3;
+        // Picks the Godot 3 or Godot 4 name of a type that was renamed between versions.
+        let name = |godot3_name:&'static str, godot4_name:&'static str| if godot3 { godot3_name } else { godot4_name };
+        match self {
+            Variant::Nil => String::from("null"),
+            Variant::Bool(value) => value.to_string(),
+            Variant::Int(value) => value.to_string(),
+            Variant::Float(value) => format_float(*value),
+            Variant::String(string) => format!("\"{}\"", escape_string(string)),
+            Variant::StringName(string) if godot3 => format!("\"{}\"", escape_string(string)),
+            Variant::StringName(string) => format!("&\"{}\"", escape_string(string)),
+            Variant::NodePath(string) => format!("NodePath(\"{}\")", escape_string(string)),
+            Variant::Vector2(x, y) => constructor("Vector2", &reals(&[*x, *y]), format),
+            Variant::Vector2i(x, y) => constructor("Vector2i", &join(&[*x, *y], |v| v.to_string()), format),
+            Variant::Vector3(x, y, z) => constructor("Vector3", &reals(&[*x, *y, *z]), format),
+            Variant::Vector3i(x, y, z) => constructor("Vector3i", &join(&[*x, *y, *z], |v| v.to_string()), format),
+            Variant::Vector4(x, y, z, w) => constructor("Vector4", &reals(&[*x, *y, *z, *w]), format),
+            Variant::Vector4i(x, y, z, w) => constructor("Vector4i", &join(&[*x, *y, *z, *w], |v| v.to_string()), format),
+            Variant::Rect2(x, y, w, h) => constructor("Rect2", &reals(&[*x, *y, *w, *h]), format),
+            Variant::Rect2i(x, y, w, h) => constructor("Rect2i", &join(&[*x, *y, *w, *h], |v| v.to_string()), format),
+            Variant::Transform2D(values) => constructor("Transform2D", &reals(values), format),
+            Variant::Transform3D(values) => constructor(name("Transform", "Transform3D"), &reals(values), format),
+            Variant::Basis(values) => constructor("Basis", &reals(values), format),
+            Variant::Quaternion(x, y, z, w) => constructor(name("Quat", "Quaternion"), &reals(&[*x, *y, *z, *w]), format),
+            Variant::Aabb(values) => constructor("AABB", &reals(values), format),
+            Variant::Plane(a, b, c, d) => constructor("Plane", &reals(&[*a, *b, *c, *d]), format),
+            Variant::Projection(values) => constructor("Projection", &reals(values), format),
+            Variant::Color(r, g, b, a) => constructor("Color", &reals(&[*r, *g, *b, *a]), format),
+            Variant::Array(array) => format_array(array, format),
+            Variant::TypedArray(_, array) if godot3 => format_array(array, format),
+            Variant::TypedArray(element_type, array) => format!("Array[{}]({})", element_type, format_array(array, format)),
+            Variant::Dictionary(dictionary) => format_dictionary(dictionary, format),
+            Variant::TypedDictionary(_, dictionary) if godot3 => format_dictionary(dictionary, format),
+            Variant::TypedDictionary(types, dictionary) => format!("Dictionary[{}]({})", types, format_dictionary(dictionary, format)),
+            Variant::PackedByteArray(bytes) => constructor(name("PoolByteArray", "PackedByteArray"), &join(bytes, |v| v.to_string()), format),
+            Variant::PackedInt32Array(ints) => constructor(name("PoolIntArray", "PackedInt32Array"), &join(ints, |v| v.to_string()), format),
+            Variant::PackedInt64Array(ints) => constructor(name("PoolIntArray", "PackedInt64Array"), &join(ints, |v| v.to_string()), format),
+            Variant::PackedFloat32Array(floats) => constructor(name("PoolRealArray", "PackedFloat32Array"), &join(floats, |v| format_real32(*v)), format),
+            Variant::PackedFloat64Array(floats) => constructor(name("PoolRealArray", "PackedFloat64Array"), &reals(floats), format),
+            Variant::PackedStringArray(strings) => constructor(name("PoolStringArray", "PackedStringArray"), &join(strings, |v| format!("\"{}\"", escape_string(v))), format),
+            Variant::PackedVector2Array(vectors) => constructor(name("PoolVector2Array", "PackedVector2Array"), &join(vectors, |(x, y)| reals(&[*x, *y])), format),
+            Variant::PackedVector3Array(vectors) => constructor(name("PoolVector3Array", "PackedVector3Array"), &join(vectors, |(x, y, z)| reals(&[*x, *y, *z])), format),
+            Variant::PackedVector4Array(vectors) => constructor("PackedVector4Array", &join(vectors, |(x, y, z, w)| reals(&[*x, *y, *z, *w])), format),
+            Variant::PackedColorArray(colors) => constructor(name("PoolColorArray", "PackedColorArray"), &join(colors, |(r, g, b, a)| reals(&[*r, *g, *b, *a])), format),
+            Variant::ExtResource(id) => constructor("ExtResource", &id.to_string(), format),
+            Variant::SubResource(id) => constructor("SubResource", &id.to_string(), format),
+            Variant::Resource(path) => constructor("Resource", &format!("\"{}\"", escape_string(path)), format),
+            Variant::Object(class, properties) => {
+                let mut object = format!("Object({}", class);
+                for (name, value) in properties.iter() {
+                    object += &format!(",\"{}\":{}", escape_string(name), value.to_text(format));
+                }
+                object + ")"
+            },
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_text(Format::Godot4))
+    }
+}
+
+fn format_array(array:&[Variant], format:Format) -> String {
+    let items = join(array, |v| v.to_text(format));
+    match format {
+        Format::Godot3 => format!("[ {} ]", items),
+        Format::Godot4 => format!("[{}]", items),
+    }
+}
+
+fn format_dictionary(dictionary:&[(Variant, Variant)], format:Format) -> String {
+    if dictionary.is_empty() {
+        return String::from(if format == Format::Godot3 { "{\n}" } else { "{}" });
+    }
+    let entries = dictionary.iter().map(|(key, value)| format!("{}: {}", key.to_text(format), value.to_text(format))).collect::<Vec<String>>();
+    format!("{{\n{}\n}}", entries.join(",\n"))
+}
@@ -0,0 +1,144 @@
+use crate::{element::{Element, ElementData}, scene::Scene, variant::{Format, Variant}};
+
+// Data names in the order Godot writes them, unknown names keep their relative order after these.
+fn data_order(element_name:&str, format:Format) -> &'static [&'static str] {
+    match (element_name, format) {
+        ("gd_scene", _) => &["load_steps", "format", "uid"],
+        ("gd_resource", _) => &["type", "script_class", "load_steps", "format", "uid"],
+        ("ext_resource", Format::Godot3) => &["path", "type", "id"],
+        ("ext_resource", Format::Godot4) => &["type", "uid", "path", "id"],
+        ("sub_resource", _) => &["type", "id"],
+        ("node", _) => &["name", "type", "parent", "owner", "index", "unique_id", "node_paths", "groups", "instance_placeholder", "instance"],
+        ("connection", Format::Godot3) => &["signal", "from", "to", "method", "binds", "flags"],
+        ("connection", Format::Godot4) => &["signal", "from", "to", "method", "flags", "unbinds", "binds"],
+        ("editable", _) => &["path"],
+        _ => &[],
+    }
+}
+
+// Position of each kind of section in the file, properties above the first header stay above it.
+pub(crate) fn section_rank(element_name:&str) -> usize {
+    match element_name {
+        "" => 0,
+        "gd_scene" | "gd_resource" => 1,
+        "ext_resource" => 2,
+        "sub_resource" => 3,
+        "resource" => 4,
+        "node" => 5,
+        "connection" => 6,
+        "editable" => 7,
+        _ => 8,
+    }
+}
+
+// Sections Godot writes as consecutive lines instead of separating them with a blank line.
+pub(crate) fn is_grouped(element_name:&str) -> bool {
+    matches!(element_name, "ext_resource" | "connection" | "editable")
+}
+
+fn format_value(value:&str, format:Format) -> String {
+    match Variant::parse(value) {
+        Ok(variant) => variant.to_text(format),
+        Err(_) => value.trim().to_string(),
+    }
+}
+
+fn format_data(data:&ElementData, format:Format) -> String {
+    match (&data.0[..], format) {
+        ("groups", Format::Godot3) => {
+            // Godot 3 writes each group on its own line.
+            if let Ok(Variant::Array(groups)) = data.value() {
+                let groups = groups.iter().map(|group| group.to_text(format) + ",\n").collect::<String>();
+                return format!("groups=[\n{}]", groups);
+            }
+        },
+        ("binds", Format::Godot4) => {
+            return format!("binds= {}", format_value(&data.1, format));
+        },
+        _ => {}
+    }
+    format!("{}={}", data.0, format_value(&data.1, format))
+}
+
+fn write_element(element:&Element, format:Format, output:&mut String) {
+    let order = data_order(&element.element_name, format);
+    let mut element_data = element.element_data.iter().collect::<Vec<&ElementData>>();
+    element_data.sort_by_key(|data| order.iter().position(|name| *name == data.0).unwrap_or(order.len()));
+
+    if !element.is_preamble() {
+        output.push('[');
+        output.push_str(&element.element_name);
+        for data in element_data {
+            output.push(' ');
+            output.push_str(&format_data(data, format));
+        }
+        output.push_str("]\n");
+    }
+    for property in element.properties.iter() {
+        output.push_str(&property.0);
+        output.push_str(" = ");
+        output.push_str(&format_value(&property.1, format));
+        output.push('\n');
+    }
+}
+
+// The `load_steps` Godot writes in the header, one per resource plus the file itself. `None` without resources.
+pub(crate) fn load_steps<'e>(elements:impl IntoIterator<Item = &'e Element>) -> Option<usize> {
+    let resources = elements.into_iter().filter(|element| matches!(&element.element_name[..], "ext_resource" | "sub_resource")).count();
+    if resources > 0 { Some(resources + 1) } else { None }
+}
+
+// Sets or removes `load_steps` of the header in place, adding it where Godot puts it.
+pub(crate) fn set_load_steps(header:&mut Element, load_steps:Option<usize>) {
+    match load_steps {
+        Some(load_steps) => {
+            if header.update_data("load_steps", &load_steps.to_string()).is_err() {
+                let order = data_order(&header.element_name, Format::Godot4);
+                let before = &order[..order.iter().position(|name| *name == "load_steps").unwrap_or(0)];
+                let index = header.element_data.iter().take_while(|data| before.contains(&&data.0[..])).count();
+                let _ = header.insert_data_at(index, "load_steps", &load_steps.to_string());
+            }
+        },
+        None => {
+            let _ = header.remove_data("load_steps");
+        },
+    }
+}
+
+// Header data Godot derives from the rest of the file.
+fn canonical_header(header:&Element, elements:&[&Element]) -> Element {
+    let mut header = header.clone();
+    header.element_data.retain(|data| data.0 != "load_steps");
+    if let Some(load_steps) = load_steps(elements.iter().copied()) {
+        header.element_data.push(ElementData(String::from("load_steps"), load_steps.to_string()));
+    }
+    header
+}
+
+// Writes `scene` the way Godot's text resource saver does, ignoring the formatting it was read with.
+pub fn write_canonical(scene:&Scene) -> String {
+    write_elements(&scene.elements, scene.format())
+}
+
+pub(crate) fn write_elements(elements:&[Element], format:Format) -> String {
+    let mut elements = elements.iter().collect::<Vec<&Element>>();
+    elements.sort_by_key(|element| section_rank(&element.element_name));
+
+    let mut output = String::new();
+    let mut previous:Option<&str> = None;
+    for element in elements.iter() {
+        let name = &element.element_name[..];
+        let continues_group = previous == Some(name) && is_grouped(name);
+        if previous.is_some() && !continues_group {
+            output.push('\n');
+        }
+        if matches!(name, "gd_scene" | "gd_resource") {
+            write_element(&canonical_header(element, &elements), format, &mut output);
+        }
+        else {
+            write_element(element, format, &mut output);
+        }
+        previous = Some(name);
+    }
+    output
+}